@@ -5,9 +5,10 @@ use {
     crate::{
         config::{self, Config},
         encoding::Encoding,
+        proxy::Proxy,
         ui::Mode,
     },
-    std::{error::Error, fmt, result::Result},
+    std::{error::Error, fmt, result::Result, time::Duration},
 };
 
 /// The error returned if something goes awry while parsing the
@@ -102,10 +103,16 @@ pub fn parse<T: AsRef<str>>(args: &[T]) -> Result<Config, ArgError> {
     let mut set_notls = false;
     let mut set_tor = false;
     let mut set_notor = false;
+    let mut set_proxy = false;
+    let mut set_noproxy = false;
     let mut set_media = false;
     let mut set_nomedia = false;
     let mut set_autoplay = false;
     let mut set_noautoplay = false;
+    let mut set_port = false;
+    let mut set_mirror_opt = None;
+    let mut set_check_opt = None;
+    let mut set_depth = false;
 
     while let Some(arg) = iter.next() {
         match arg.as_ref() {
@@ -144,11 +151,27 @@ pub fn parse<T: AsRef<str>>(args: &[T]) -> Result<Config, ArgError> {
                 cfg.mode = Mode::PrintTheme;
             }
             "-s" | "--tls" | "-tls" => {
+                if set_notls {
+                    return Err(ArgError::new("can't set both --tls and --no-tls"));
+                }
+                // plain --tls overrides `tls strict` in phetch.conf,
+                // but not an earlier --tls-strict
+                if !set_tls {
+                    cfg.tls_strict = false;
+                }
+                set_tls = true;
+                cfg.tls = true;
+                if cfg!(not(feature = "tls")) {
+                    return Err(ArgError::new("phetch was compiled without TLS support"));
+                }
+            }
+            "--tls-strict" | "-tls-strict" => {
                 if set_notls {
                     return Err(ArgError::new("can't set both --tls and --no-tls"));
                 }
                 set_tls = true;
                 cfg.tls = true;
+                cfg.tls_strict = true;
                 if cfg!(not(feature = "tls")) {
                     return Err(ArgError::new("phetch was compiled without TLS support"));
                 }
@@ -159,6 +182,7 @@ pub fn parse<T: AsRef<str>>(args: &[T]) -> Result<Config, ArgError> {
                 }
                 set_notls = true;
                 cfg.tls = false;
+                cfg.tls_strict = false;
             }
             "-o" | "--tor" | "-tor" => {
                 if set_notor {
@@ -223,6 +247,127 @@ pub fn parse<T: AsRef<str>>(args: &[T]) -> Result<Config, ArgError> {
                 cfg.autoplay = false;
                 set_noautoplay = true;
             }
+            "--offline" | "-offline" => cfg.cache.offline = true,
+            "--proxy" | "-proxy" => {
+                if set_noproxy {
+                    return Err(ArgError::new("can't set both --proxy and --no-proxy"));
+                }
+                let url = iter
+                    .next()
+                    .ok_or_else(|| ArgError::new("--proxy expects a URL arg"))?;
+                set_proxy = true;
+                cfg.proxy = Some(
+                    Proxy::parse(url.as_ref())
+                        .map_err(|e| ArgError::new(format!("{}: {}", e, url.as_ref())))?,
+                );
+            }
+            "--no-proxy" | "-no-proxy" => {
+                if set_proxy {
+                    return Err(ArgError::new("can't set both --proxy and --no-proxy"));
+                }
+                set_noproxy = true;
+                cfg.proxy = None;
+            }
+            "--timeout" | "-timeout" => {
+                cfg.timeouts.connect = iter
+                    .next()
+                    .and_then(|secs| config::to_secs(secs.as_ref()))
+                    .ok_or_else(|| ArgError::new("--timeout expects a SECONDS arg"))?;
+            }
+            "--read-timeout" | "-read-timeout" => {
+                cfg.timeouts.read = iter
+                    .next()
+                    .and_then(|secs| config::to_secs(secs.as_ref()))
+                    .ok_or_else(|| ArgError::new("--read-timeout expects a SECONDS arg"))?;
+            }
+            "--retries" | "-retries" => {
+                if let Some(num) = iter.next() {
+                    if let Ok(num) = num.as_ref().parse() {
+                        cfg.timeouts.retries = num;
+                    } else {
+                        return Err(ArgError::new("--retries expects a NUMBER arg"));
+                    }
+                } else {
+                    return Err(ArgError::new("--retries expects a NUMBER arg"));
+                }
+            }
+            "--serve" | "-serve" => {
+                let dir = iter
+                    .next()
+                    .ok_or_else(|| ArgError::new("--serve expects a DIR arg"))?;
+                cfg.mode = Mode::Serve;
+                cfg.server.dir = dir.as_ref().into();
+            }
+            "--port" | "-port" => {
+                cfg.server.port = iter
+                    .next()
+                    .and_then(|port| port.as_ref().parse().ok())
+                    .ok_or_else(|| ArgError::new("--port expects a PORT arg"))?;
+                set_port = true;
+            }
+            "--mirror" | "-mirror" => {
+                let url = iter
+                    .next()
+                    .ok_or_else(|| ArgError::new("--mirror expects a URL arg"))?;
+                cfg.mode = Mode::Mirror;
+                cfg.start = url.as_ref().trim().into();
+                got_url = true;
+            }
+            "--depth" | "-depth" => {
+                cfg.mirror.depth = iter
+                    .next()
+                    .and_then(|num| num.as_ref().parse().ok())
+                    .ok_or_else(|| ArgError::new("--depth expects a NUMBER arg"))?;
+                cfg.check.depth = cfg.mirror.depth;
+                set_depth = true;
+            }
+            "--delay" | "-delay" => {
+                cfg.mirror.delay = iter
+                    .next()
+                    .and_then(|secs| secs.as_ref().trim().parse().ok())
+                    .map(Duration::from_secs_f64)
+                    .ok_or_else(|| ArgError::new("--delay expects a SECONDS arg"))?;
+                set_mirror_opt = Some("--delay");
+            }
+            "--output" | "-output" => {
+                let dir = iter
+                    .next()
+                    .ok_or_else(|| ArgError::new("--output expects a DIR arg"))?;
+                cfg.mirror.dir = dir.as_ref().into();
+                set_mirror_opt = Some("--output");
+            }
+            "--all-hosts" | "-all-hosts" => {
+                cfg.mirror.all_hosts = true;
+                set_mirror_opt = Some("--all-hosts");
+            }
+            "--check" | "-check" => {
+                let url = iter
+                    .next()
+                    .ok_or_else(|| ArgError::new("--check expects a URL arg"))?;
+                cfg.mode = Mode::Check;
+                cfg.start = url.as_ref().trim().into();
+                got_url = true;
+            }
+            "--lint" | "-lint" => {
+                let file = iter
+                    .next()
+                    .ok_or_else(|| ArgError::new("--lint expects a FILE arg"))?;
+                cfg.mode = Mode::Lint;
+                cfg.start = file.as_ref().into();
+                got_url = true;
+            }
+            "--slow" | "-slow" => {
+                cfg.check.slow = iter
+                    .next()
+                    .and_then(|secs| secs.as_ref().trim().parse().ok())
+                    .map(Duration::from_secs_f64)
+                    .ok_or_else(|| ArgError::new("--slow expects a SECONDS arg"))?;
+                set_check_opt = Some("--slow");
+            }
+            "--external" | "-external" => {
+                cfg.check.external = true;
+                set_check_opt = Some("--external");
+            }
             "-e" | "--encoding" | "-encoding" => {
                 if let Some(encoding) = iter.next() {
                     cfg.encoding = Encoding::from_str(encoding.as_ref())
@@ -244,14 +389,47 @@ pub fn parse<T: AsRef<str>>(args: &[T]) -> Result<Config, ArgError> {
         }
     }
 
+    if set_port && cfg.mode != Mode::Serve {
+        return Err(ArgError::new("--port only works with --serve"));
+    }
+
+    if let Some(flag) = set_mirror_opt {
+        if cfg.mode != Mode::Mirror {
+            return Err(ArgError::new(format!("{} only works with --mirror", flag)));
+        }
+    }
+
+    if let Some(flag) = set_check_opt {
+        if cfg.mode != Mode::Check {
+            return Err(ArgError::new(format!("{} only works with --check", flag)));
+        }
+    }
+
+    if set_depth && !matches!(cfg.mode, Mode::Mirror | Mode::Check) {
+        return Err(ArgError::new("--depth only works with --mirror or --check"));
+    }
+
     if cfg.tor && cfg.tls {
         return Err(ArgError::new("can't set both --tor and --tls"));
     }
 
+    if cfg.tor && cfg.proxy.is_some() {
+        return Err(ArgError::new("can't set both --tor and --proxy"));
+    }
+
     #[cfg(not(test))]
     {
         if !atty::is(atty::Stream::Stdout)
-            && !matches!(cfg.mode, Mode::Raw | Mode::Print | Mode::PrintTheme)
+            && !matches!(
+                cfg.mode,
+                Mode::Raw
+                    | Mode::Print
+                    | Mode::PrintTheme
+                    | Mode::Serve
+                    | Mode::Mirror
+                    | Mode::Check
+                    | Mode::Lint
+            )
         {
             cfg.mode = Mode::NoTTY;
         }
@@ -263,6 +441,8 @@ pub fn parse<T: AsRef<str>>(args: &[T]) -> Result<Config, ArgError> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{check, gopher::Tls, mirror, utils::TempDir};
+    use std::path::PathBuf;
 
     #[test]
     fn test_simple() {
@@ -299,6 +479,106 @@ mod tests {
         assert_eq!(cfg.tls, true);
     }
 
+    #[test]
+    fn test_serve() {
+        let cfg = parse(&["--serve", "gopherhole"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Serve);
+        assert_eq!(cfg.server.dir, PathBuf::from("gopherhole"));
+        assert_eq!(cfg.server.port, 7070);
+
+        let cfg = parse(&["--port", "7071", "--serve", "."]).expect("should work");
+        assert_eq!(cfg.server.port, 7071);
+
+        let err = parse(&["--serve"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--serve expects a DIR arg");
+        let err = parse(&["--serve", ".", "--port", "http"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--port expects a PORT arg");
+        let err = parse(&["--port", "7071"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--port only works with --serve");
+    }
+
+    #[test]
+    fn test_mirror() {
+        let cfg = parse(&["--mirror", "gopher://example.com/"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Mirror);
+        assert_eq!(cfg.start, "gopher://example.com/");
+        assert_eq!(cfg.mirror, mirror::Settings::default());
+
+        let cfg = parse(&[
+            "--depth",
+            "2",
+            "--delay",
+            "0.5",
+            "--output",
+            "archive",
+            "--all-hosts",
+            "--mirror",
+            "example.com",
+        ])
+        .expect("should work");
+        assert_eq!(cfg.mirror.depth, 2);
+        assert_eq!(cfg.mirror.delay, Duration::from_millis(500));
+        assert_eq!(cfg.mirror.dir, PathBuf::from("archive"));
+        assert_eq!(cfg.mirror.all_hosts, true);
+
+        let err = parse(&["--mirror"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--mirror expects a URL arg");
+        let err = parse(&["--mirror", "example.com", "--depth", "all"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--depth expects a NUMBER arg");
+        let err = parse(&["--mirror", "a.com", "b.com"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "unknown argument: b.com");
+        let err = parse(&["--all-hosts", "example.com"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--all-hosts only works with --mirror");
+    }
+
+    #[test]
+    fn test_check() {
+        let cfg = parse(&["--check", "gopher://example.com/"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Check);
+        assert_eq!(cfg.start, "gopher://example.com/");
+        assert_eq!(cfg.check, check::Settings::default());
+
+        let cfg = parse(&[
+            "--check",
+            "example.com",
+            "--depth",
+            "0",
+            "--slow",
+            "5",
+            "--external",
+        ])
+        .expect("should work");
+        assert_eq!(cfg.check.depth, 0);
+        assert_eq!(cfg.check.slow, Duration::from_secs(5));
+        assert_eq!(cfg.check.external, true);
+
+        let err = parse(&["--check"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--check expects a URL arg");
+        let err = parse(&["--check", "example.com", "--slow"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--slow expects a SECONDS arg");
+        let err = parse(&["--external", "example.com"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--external only works with --check");
+        let err = parse(&["--mirror", "example.com", "--slow", "1"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--slow only works with --check");
+        let err = parse(&["--depth", "1", "example.com"]).expect_err("should fail");
+        assert_eq!(
+            err.to_string(),
+            "--depth only works with --mirror or --check"
+        );
+    }
+
+    #[test]
+    fn test_lint() {
+        let cfg = parse(&["--lint", "gophermap"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Lint);
+        assert_eq!(cfg.start, "gophermap");
+
+        let err = parse(&["--lint"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--lint expects a FILE arg");
+        let err = parse(&["--lint", "gophermap", "other"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "unknown argument: other");
+    }
+
     #[test]
     fn test_raw() {
         let cfg = parse(&["--raw", "sdf.org"]).expect("should work");
@@ -330,6 +610,34 @@ mod tests {
         assert_eq!(cfg.mode, Mode::Version);
     }
 
+    #[test]
+    fn test_tls_strict() {
+        let cfg = parse(&["--tls-strict"]).expect("should work");
+        assert_eq!(cfg.tls, true);
+        assert_eq!(cfg.tls_strict, true);
+        assert_eq!(cfg.tls_mode(), Tls::Strict);
+
+        let cfg = parse(&["--tls"]).expect("should work");
+        assert_eq!(cfg.tls_mode(), Tls::On);
+
+        let cfg = parse(&["--tls-strict", "--tls"]).expect("should work");
+        assert_eq!(cfg.tls_mode(), Tls::Strict);
+
+        let dir = TempDir::new("strict");
+        let path = dir.join("phetch.conf");
+        std::fs::write(&path, "tls strict\n").unwrap();
+        let path = path.to_str().unwrap();
+        let cfg = parse(&["-c", path]).expect("should work");
+        assert_eq!(cfg.tls_mode(), Tls::Strict);
+        let cfg = parse(&["-c", path, "--tls"]).expect("should work");
+        assert_eq!(cfg.tls_mode(), Tls::On);
+
+        let err = parse(&["--tls-strict", "--no-tls"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "can't set both --tls and --no-tls");
+        let err = parse(&["--tls-strict", "--tor"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "can't set both --tor and --tls");
+    }
+
     #[test]
     fn test_tls_tor() {
         let err = parse(&["--tls", "--tor"]).expect_err("should fail");
@@ -381,4 +689,44 @@ mod tests {
         let cfg = parse(&["-C"]).expect("should work");
         assert_eq!(cfg.tls, false);
     }
+
+    #[test]
+    fn test_timeouts() {
+        use std::time::Duration;
+
+        let cfg = parse(&["--timeout", "3", "--read-timeout", "20", "--retries", "2"])
+            .expect("should work");
+        assert_eq!(cfg.timeouts.connect, Duration::from_secs(3));
+        assert_eq!(cfg.timeouts.read, Duration::from_secs(20));
+        assert_eq!(cfg.timeouts.retries, 2);
+
+        let err = parse(&["--timeout"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--timeout expects a SECONDS arg");
+
+        let err = parse(&["--retries", "lots"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--retries expects a NUMBER arg");
+    }
+
+    #[test]
+    fn test_proxy() {
+        let cfg = parse(&["--proxy", "http://127.0.0.1:3128"]).expect("should work");
+        assert_eq!(cfg.proxy.unwrap().to_string(), "http://127.0.0.1:3128");
+
+        let err = parse(&["--proxy"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--proxy expects a URL arg");
+        let err = parse(&["--proxy", "ftp://x"]).expect_err("should fail");
+        assert_eq!(
+            err.to_string(),
+            "Proxy must be a socks5:// or http:// URL: ftp://x"
+        );
+        let err = parse(&["--proxy", "socks5://x", "--no-proxy"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "can't set both --proxy and --no-proxy");
+    }
+
+    #[test]
+    fn test_offline() {
+        let cfg = parse(&["--offline", "gopher://phkt.io"]).expect("should work");
+        assert_eq!(cfg.cache.offline, true);
+        assert_eq!(cfg.start, "gopher://phkt.io");
+    }
 }
@@ -2,8 +2,8 @@
 //! manually. They are stored as a simple Gophermap, `BOOKMARKS_FILE`,
 //! in that directory.
 
-use crate::phetchdir;
-use std::io::{Read, Result};
+use crate::{phetchdir, Result};
+use std::io::Read;
 
 /// Bookmarks only work if you've created a ~/.config/phetch/ manually.
 const BOOKMARKS_FILE: &str = "bookmarks.gph";
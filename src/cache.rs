@@ -0,0 +1,223 @@
+//! phetch can keep a copy of every Gopher response it fetches in
+//! `CACHE_DIR` inside the phetchdir, so going back to a page you've
+//! already seen doesn't have to hit the network. It's off by default.
+//! Turn it on with `cache yes` in phetch.conf.
+//!
+//! With `--offline`, phetch never touches the network and only shows
+//! pages from the cache, no matter how old they are.
+//!
+//! Each cache file is named after a hash of the full URL and contains
+//! the URL on the first line, `tls` or `plain` on the second, and the
+//! raw response after that.
+
+use crate::{
+    gopher::{self, Timeouts, Tls, Transport},
+    phetchdir, Result,
+};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+/// Cached responses live in this directory inside the phetchdir.
+pub const CACHE_DIR: &str = "cache";
+
+/// How long cached pages are good for by default.
+const DEFAULT_TTL_IN_SECS: u64 = 60 * 60;
+
+/// How big the cache can get by default.
+const DEFAULT_SIZE_IN_MB: u64 = 50;
+
+/// Cache options from phetch.conf and the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// Save responses and serve them from the cache?
+    pub enabled: bool,
+    /// Only use the cache, never the network?
+    pub offline: bool,
+    /// How long a cached response is good for. 0 = forever
+    pub ttl: Duration,
+    /// Max size of the cache, in bytes. 0 = no limit
+    pub size: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            enabled: false,
+            offline: false,
+            ttl: Duration::from_secs(DEFAULT_TTL_IN_SECS),
+            size: DEFAULT_SIZE_IN_MB * 1024 * 1024,
+        }
+    }
+}
+
+/// Fetch a Gopher URL from the cache if we can, otherwise from the
+/// network, saving the response for next time. Returns a tuple of:
+///   (did tls work?, raw Gopher response, was it cached?)
+pub fn fetch_url(
+    url: &str,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+    settings: Settings,
+) -> Result<(bool, Vec<u8>, bool)> {
+    if let Some((was_tls, body)) = get(url, settings) {
+        // don't let the cache sneak in a page we got over plain TCP
+        if was_tls || tls != Tls::Strict {
+            return Ok((was_tls, body, true));
+        }
+    }
+    if settings.offline {
+        return Err(error!("Offline, and not in cache: {}", url));
+    }
+    let (tls, body) = refresh_url(url, tls, transport, timeouts, settings)?;
+    Ok((tls, body, false))
+}
+
+/// Fetch a Gopher URL from the network even if it's cached, replacing
+/// the cached copy. Returns a tuple of:
+///   (did tls work?, raw Gopher response)
+pub fn refresh_url(
+    url: &str,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+    settings: Settings,
+) -> Result<(bool, Vec<u8>)> {
+    let (tls, body) = gopher::fetch_url(url, tls, transport, timeouts)?;
+    if settings.enabled {
+        // not being able to cache a page shouldn't stop you reading it
+        let _ = put(url, tls, &body, settings);
+    }
+    Ok((tls, body))
+}
+
+/// Look up a cached response. Returns a tuple of:
+///   (did tls work?, raw Gopher response)
+pub fn get(url: &str, settings: Settings) -> Option<(bool, Vec<u8>)> {
+    if !settings.enabled && !settings.offline {
+        return None;
+    }
+    let path = path_for(url).ok()?;
+    if !settings.offline && !settings.ttl.is_zero() {
+        let age = fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        if age > settings.ttl {
+            return None;
+        }
+    }
+
+    let mut raw = vec![];
+    fs::File::open(&path).ok()?.read_to_end(&mut raw).ok()?;
+    let (cached_url, tls, body) = parse(&raw)?;
+    if cached_url != url {
+        return None;
+    }
+    Some((tls, body.to_vec()))
+}
+
+/// Save a response to the cache, then trim the cache down to size.
+pub fn put(url: &str, tls: bool, body: &[u8], settings: Settings) -> Result<()> {
+    let dir = dir()?;
+    fs::create_dir_all(&dir)?;
+    let path = path_for(url)?;
+    let tmp = path.with_extension("tmp");
+    {
+        let mut file = fs::File::create(&tmp)?;
+        write!(file, "{}\n{}\n", url, if tls { "tls" } else { "plain" })?;
+        file.write_all(body)?;
+    }
+    fs::rename(&tmp, &path)?;
+    prune(settings.size)
+}
+
+/// Remove the oldest cached responses until the cache is no bigger
+/// than `size` bytes.
+fn prune(size: u64) -> Result<()> {
+    if size == 0 {
+        return Ok(());
+    }
+    let mut files = fs::read_dir(dir()?)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let meta = entry.metadata().ok()?;
+            Some((meta.modified().ok()?, meta.len(), entry.path()))
+        })
+        .collect::<Vec<_>>();
+    let mut total = files.iter().map(|(_, len, _)| len).sum::<u64>();
+    files.sort();
+    for (_, len, path) in files {
+        if total <= size {
+            break;
+        }
+        fs::remove_file(path)?;
+        total -= len;
+    }
+    Ok(())
+}
+
+/// Full path to the cache directory. Tests get their own, so they
+/// don't clobber the real one.
+pub(crate) fn dir() -> Result<PathBuf> {
+    if cfg!(test) {
+        return Ok(std::env::temp_dir().join(format!("phetch-cache-{}", std::process::id())));
+    }
+    Ok(phetchdir::path()?.join(CACHE_DIR))
+}
+
+/// Where the response for `url` is cached.
+fn path_for(url: &str) -> Result<PathBuf> {
+    Ok(dir()?.join(format!("{:016x}", hash(url))))
+}
+
+/// 64-bit FNV-1a. We can't use `DefaultHasher` because its output
+/// may change between Rust releases, which would orphan the cache.
+fn hash(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Split a cache file into its URL, TLS flag, and response.
+fn parse(raw: &[u8]) -> Option<(&str, bool, &[u8])> {
+    let mut parts = raw.splitn(3, |&b| b == b'\n');
+    let url = std::str::from_utf8(parts.next()?).ok()?;
+    let tls = parts.next()? == b"tls";
+    Some((url, tls, parts.next()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(hash("gopher://phkt.io/1/"), hash("gopher://phkt.io/0/"));
+    }
+
+    #[test]
+    fn test_parse() {
+        let (url, tls, body) = parse(b"gopher://phkt.io/\ntls\n1Hi\t/\tphkt.io\t70\r\n").unwrap();
+        assert_eq!(url, "gopher://phkt.io/");
+        assert!(tls);
+        assert_eq!(body, b"1Hi\t/\tphkt.io\t70\r\n");
+
+        let (_, tls, body) = parse(b"gopher://phkt.io/\nplain\n").unwrap();
+        assert!(!tls);
+        assert!(body.is_empty());
+
+        assert!(parse(b"gopher://phkt.io/").is_none());
+    }
+
+    #[test]
+    fn test_disabled() {
+        assert_eq!(get("gopher://phkt.io/", Settings::default()), None);
+    }
+}
@@ -0,0 +1,484 @@
+//! Type c items are calendars in iCalendar format (RFC 5545), the
+//! `.ics` files calendar apps import and export. Instead of making
+//! you download one to see what's in it, phetch turns it into an
+//! agenda: every event in order, grouped by day, with where it is and
+//! how often it repeats.
+//!
+//! Times are shown the way the calendar gives them, with their time
+//! zone, rather than converted to your local time.
+
+use crate::Result;
+use std::fmt;
+
+/// Weekday names, starting with Monday. RFC 5545 uses the first two
+/// letters of each in BYDAY rules.
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Month names.
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// How far event details are indented under their time.
+const INDENT: usize = 4;
+
+/// A content line's parameters, like `TZID=America/Chicago`.
+type Params = Vec<(String, String)>;
+
+/// A date, and a time unless it's an all day date.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct DateTime {
+    /// Days since 1970-01-01.
+    days: i64,
+    /// Seconds since midnight, or None for all day dates.
+    secs: Option<i64>,
+    /// `UTC`, a TZID like `America/Chicago`, or empty for floating
+    /// times that are the same in every zone.
+    zone: String,
+}
+
+/// One VEVENT from a calendar.
+#[derive(Debug, Default)]
+struct Event {
+    summary: String,
+    start: Option<DateTime>,
+    end: Option<DateTime>,
+    duration: Option<i64>,
+    location: String,
+    description: String,
+    rrule: String,
+}
+
+/// Turn an iCalendar file into a readable agenda. Fails if it isn't
+/// one.
+pub fn to_agenda(ics: &str) -> Result<String> {
+    let lines = unfold(ics);
+    if !lines
+        .iter()
+        .any(|l| l.eq_ignore_ascii_case("BEGIN:VCALENDAR"))
+    {
+        return Err(error!("Not an iCalendar file"));
+    }
+
+    let mut name = String::new();
+    let mut events = vec![];
+    // components we're inside of, like VCALENDAR > VEVENT > VALARM
+    let mut stack: Vec<String> = vec![];
+    for line in &lines {
+        let (prop, params, value) = match split_line(line) {
+            Some(parts) => parts,
+            None => continue,
+        };
+        match prop.as_ref() {
+            "BEGIN" => {
+                if value.eq_ignore_ascii_case("VEVENT") {
+                    events.push(Event::default());
+                }
+                stack.push(value.to_uppercase());
+                continue;
+            }
+            "END" => {
+                stack.pop();
+                continue;
+            }
+            _ => {}
+        }
+        match stack.last().map(|s| s.as_str()) {
+            Some("VCALENDAR") if prop == "X-WR-CALNAME" => name = unescape(value),
+            Some("VEVENT") => {
+                let event = match events.last_mut() {
+                    Some(e) => e,
+                    None => continue,
+                };
+                match prop.as_ref() {
+                    "SUMMARY" => event.summary = unescape(value),
+                    "LOCATION" => event.location = unescape(value),
+                    "DESCRIPTION" => event.description = unescape(value),
+                    "DTSTART" => event.start = parse_date(value, &params),
+                    "DTEND" => event.end = parse_date(value, &params),
+                    "DURATION" => event.duration = parse_duration(value),
+                    "RRULE" => event.rrule = value.into(),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // events without a start can't go in an agenda
+    events.retain(|e| e.start.is_some());
+    events.sort_by(|a, b| a.start.cmp(&b.start));
+
+    let mut out = vec![];
+    if !name.is_empty() {
+        out.push(name);
+    }
+    out.push(match events.len() {
+        0 => "No events.".into(),
+        1 => "1 event".into(),
+        n => format!("{} events", n),
+    });
+
+    let mut day = None;
+    for event in &events {
+        let start = event.start.as_ref().unwrap();
+        if day != Some(start.days) {
+            day = Some(start.days);
+            out.push(String::new());
+            out.push(format_date(start.days));
+        }
+        out.push(event.to_string());
+    }
+    out.push(String::new());
+    Ok(out.join("\n"))
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let start = self.start.as_ref().unwrap();
+        let end = self.end.clone().or_else(|| {
+            self.duration.map(|d| {
+                let secs = start.secs.unwrap_or(0) + d;
+                DateTime {
+                    days: start.days + secs.div_euclid(86400),
+                    secs: start.secs.map(|_| secs.rem_euclid(86400)),
+                    zone: start.zone.clone(),
+                }
+            })
+        });
+        let summary = if self.summary.is_empty() {
+            "(untitled)"
+        } else {
+            &self.summary
+        };
+        let pad = " ".repeat(INDENT);
+
+        match start.secs {
+            None => write!(f, "  all day  {}", summary)?,
+            Some(secs) => {
+                let mut when = format_time(secs);
+                if let Some(end_secs) = end
+                    .as_ref()
+                    .filter(|e| e.days == start.days)
+                    .and_then(|e| e.secs)
+                {
+                    when.push('-');
+                    when.push_str(&format_time(end_secs));
+                }
+                if !start.zone.is_empty() {
+                    when.push(' ');
+                    when.push_str(&start.zone);
+                }
+                write!(f, "  {}  {}", when, summary)?;
+            }
+        }
+
+        // all day events end the day after their last day
+        if let Some(end) = end {
+            let last = if end.secs.is_none() {
+                end.days - 1
+            } else {
+                end.days
+            };
+            if last > start.days {
+                write!(f, "\n{}Until: {}", pad, format_date(last))?;
+                if let Some(secs) = end.secs {
+                    write!(f, " {}", format_time(secs))?;
+                }
+            }
+        }
+        if !self.location.is_empty() {
+            write!(f, "\n{}Where: {}", pad, self.location)?;
+        }
+        if !self.rrule.is_empty() {
+            write!(f, "\n{}Repeats: {}", pad, recurrence(&self.rrule))?;
+        }
+        for line in self.description.lines() {
+            write!(f, "\n{}{}", pad, line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Join folded lines, which continue with a space or tab.
+fn unfold(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for line in ics.lines() {
+        let line = line.trim_end_matches('\r');
+        match line.strip_prefix([' ', '\t']) {
+            Some(rest) if !lines.is_empty() => lines.last_mut().unwrap().push_str(rest),
+            _ => lines.push(line.into()),
+        }
+    }
+    lines
+}
+
+/// Split a content line like `DTSTART;TZID=America/Chicago:19930728T170000`
+/// into its uppercased property name, its parameters, and its value.
+fn split_line(line: &str) -> Option<(String, Params, &str)> {
+    // the value starts at the first colon that isn't in a quoted
+    // parameter value
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+    let mut parts = line[..colon].split(';');
+    let prop = parts.next()?.trim().to_uppercase();
+    let params = parts
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.to_uppercase(), v.trim_matches('"').to_string()))
+        .collect();
+    Some((prop, params, &line[colon + 1..]))
+}
+
+/// Undo TEXT value escaping: `\n`, `\,`, `\;`, and `\\`.
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Parse a DATE (`19930728`) or DATE-TIME (`19930728T170000Z`).
+fn parse_date(value: &str, params: &[(String, String)]) -> Option<DateTime> {
+    let value = value.trim();
+    let num = |range: std::ops::Range<usize>| -> Option<i64> { value.get(range)?.parse().ok() };
+    let days = days_from_civil(num(0..4)?, num(4..6)?, num(6..8)?);
+    let secs = match value.get(8..9) {
+        Some("T") => Some(num(9..11)? * 3600 + num(11..13)? * 60 + num(13..15).unwrap_or(0)),
+        _ => None,
+    };
+    let zone = if secs.is_none() {
+        String::new()
+    } else if value.ends_with('Z') {
+        "UTC".into()
+    } else {
+        params
+            .iter()
+            .find(|(k, _)| k == "TZID")
+            .map(|(_, v)| v.clone())
+            .unwrap_or_default()
+    };
+    Some(DateTime { days, secs, zone })
+}
+
+/// Parse a DURATION like `PT1H30M` or `P1D` into seconds.
+fn parse_duration(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.trim_start_matches('+')),
+    };
+    let mut total = 0;
+    let mut num = String::new();
+    for c in value.strip_prefix('P')?.chars() {
+        match c {
+            '0'..='9' => num.push(c),
+            'T' => {}
+            'W' | 'D' | 'H' | 'M' | 'S' => {
+                let n: i64 = num.parse().ok()?;
+                num.clear();
+                total += n * match c {
+                    'W' => 7 * 86400,
+                    'D' => 86400,
+                    'H' => 3600,
+                    'M' => 60,
+                    _ => 1,
+                };
+            }
+            _ => return None,
+        }
+    }
+    Some(sign * total)
+}
+
+/// Describe an RRULE, ex: `FREQ=WEEKLY;BYDAY=MO,WE;COUNT=10` is
+/// "every week on Mon, Wed, 10 times".
+fn recurrence(rrule: &str) -> String {
+    let part = |name: &str| {
+        rrule
+            .split(';')
+            .filter_map(|p| p.split_once('='))
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    };
+    let unit = match part("FREQ").unwrap_or("").to_uppercase().as_ref() {
+        "SECONDLY" => "second",
+        "MINUTELY" => "minute",
+        "HOURLY" => "hour",
+        "DAILY" => "day",
+        "WEEKLY" => "week",
+        "MONTHLY" => "month",
+        "YEARLY" => "year",
+        _ => return rrule.into(),
+    };
+    let mut out = match part("INTERVAL").and_then(|i| i.parse::<u32>().ok()) {
+        Some(n) if n > 1 => format!("every {} {}s", n, unit),
+        _ => format!("every {}", unit),
+    };
+    if let Some(days) = part("BYDAY") {
+        let days = days
+            .split(',')
+            .map(|d| {
+                // MO, or 1MO for the first Monday, -1MO for the last
+                let code =
+                    d.trim_start_matches(|c: char| c == '-' || c == '+' || c.is_ascii_digit());
+                let name = WEEKDAYS
+                    .iter()
+                    .find(|name| name[..2].eq_ignore_ascii_case(code))
+                    .map_or(code, |name| *name);
+                match d[..d.len() - code.len()].trim_start_matches('+') {
+                    "" => name.to_string(),
+                    "-1" => format!("last {}", name),
+                    "1" => format!("1st {}", name),
+                    "2" => format!("2nd {}", name),
+                    "3" => format!("3rd {}", name),
+                    n => format!("{}th {}", n, name),
+                }
+            })
+            .collect::<Vec<_>>();
+        out.push_str(&format!(" on {}", days.join(", ")));
+    }
+    if let Some(until) = part("UNTIL").and_then(|u| parse_date(u, &[])) {
+        out.push_str(&format!(", until {}", format_date(until.days)));
+    }
+    if let Some(count) = part("COUNT") {
+        out.push_str(&format!(", {} times", count));
+    }
+    out
+}
+
+/// Format a day like "Wed 28 Jul 1993".
+fn format_date(days: i64) -> String {
+    let (y, m, d) = civil_from_days(days);
+    // 1970-01-01 was a Thursday
+    let weekday = WEEKDAYS[(days + 3).rem_euclid(7) as usize];
+    format!("{} {} {} {}", weekday, d, MONTHS[m as usize - 1], y)
+}
+
+/// Format seconds since midnight like "17:00".
+fn format_time(secs: i64) -> String {
+    format!("{:02}:{:02}", secs / 3600, secs % 3600 / 60)
+}
+
+/// Days since 1970-01-01 for a year, month, and day.
+/// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Year, month, and day for days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + if m <= 2 { 1 } else { 0 }, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ICS: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//Gopher Team//EN\r
+X-WR-CALNAME:Gopher Events\r
+BEGIN:VEVENT\r
+UID:2@example.com\r
+DTSTART;VALUE=DATE:19930730\r
+DTEND;VALUE=DATE:19930801\r
+SUMMARY:Hack weekend\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:1@example.com\r
+DTSTART;TZID=America/Chicago:19930728T170000\r
+DURATION:PT1H30M\r
+SUMMARY:GopherCon keynote\r
+LOCATION:Room 101\\, Shepherd Labs\r
+RRULE:FREQ=WEEKLY;BYDAY=WE;COUNT=4\r
+DESCRIPTION:Bring your own\\nmodem.\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+DESCRIPTION:Reminder\r
+TRIGGER:-PT15M\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTART:19930728T120000Z\r
+DTEND:19930728T130000Z\r
+SUMMARY:Lunch with a very long na\r
+ me\r
+RRULE:FREQ=DAILY;INTERVAL=2;UNTIL=19930831T000000Z\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn test_to_agenda() {
+        assert_eq!(
+            to_agenda(ICS).unwrap(),
+            "Gopher Events
+3 events
+
+Wed 28 Jul 1993
+  12:00-13:00 UTC  Lunch with a very long name
+    Repeats: every 2 days, until Tue 31 Aug 1993
+  17:00-18:30 America/Chicago  GopherCon keynote
+    Where: Room 101, Shepherd Labs
+    Repeats: every week on Wed, 4 times
+    Bring your own
+    modem.
+
+Fri 30 Jul 1993
+  all day  Hack weekend
+    Until: Sat 31 Jul 1993
+"
+        );
+        assert_eq!(
+            to_agenda("BEGIN:VCALENDAR\nEND:VCALENDAR\n").unwrap(),
+            "No events.\n"
+        );
+        assert!(to_agenda("Not a calendar.").is_err());
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(days_from_civil(2000, 2, 29)), (2000, 2, 29));
+        assert_eq!(format_date(days_from_civil(1993, 7, 28)), "Wed 28 Jul 1993");
+        assert_eq!(parse_duration("P1W2DT3H"), Some(9 * 86400 + 3 * 3600));
+        assert_eq!(parse_duration("-PT15M"), Some(-900));
+        assert_eq!(parse_duration("1H"), None);
+        assert_eq!(
+            recurrence("FREQ=MONTHLY;BYDAY=-1FR"),
+            "every month on last Fri"
+        );
+        assert_eq!(recurrence("FREQ=YEARLY;BYDAY=2SU"), "every year on 2nd Sun");
+        assert_eq!(recurrence("X-WHATEVER"), "X-WHATEVER");
+    }
+}
@@ -0,0 +1,516 @@
+//! `phetch --check URL` finds dead links before your visitors do. It
+//! walks every menu on URL's server, starting at URL, and tries each
+//! link it finds:
+//!
+//!   - Gopher items are requested, and broken if the server can't be
+//!     reached or answers with an error.
+//!   - Telnet, CSO, and search links only need their server to accept
+//!     a connection, since there's nothing to fetch without a query.
+//!   - External `URL:` links are skipped, unless `--external` is
+//!     given, in which case their server has to accept a connection.
+//!
+//! Links that take longer than `--slow SECONDS` are reported as slow,
+//! and servers that answered over TLS are listed too. If anything is
+//! broken, phetch exits with status 1, so it can run from cron.
+
+use crate::{
+    config::SharedConfig,
+    gopher::{self, Timeouts, Tls, Transport, Type, Url},
+    menu, Result,
+};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// How many menus deep to check by default.
+pub const DEFAULT_DEPTH: usize = 10;
+
+/// Links that take longer than this are slow.
+pub const DEFAULT_SLOW: Duration = Duration::from_secs(2);
+
+/// `--check` options from the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// How many menus deep to go, like `mirror::Settings::depth`. The
+    /// start menu is 0 deep, the menus it links to are 1 deep, and so
+    /// on. 0 = no limit
+    pub depth: usize,
+    /// Links that take longer than this are slow.
+    pub slow: Duration,
+    /// Check `URL:` links too?
+    pub external: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            depth: DEFAULT_DEPTH,
+            slow: DEFAULT_SLOW,
+            external: false,
+        }
+    }
+}
+
+/// How a link did.
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    /// Worked.
+    Ok,
+    /// Worked, eventually.
+    Slow,
+    /// Didn't work, for this reason.
+    Broken(String),
+    /// Not checked, for this reason.
+    Skipped(&'static str),
+}
+
+/// One link, and how it did.
+#[derive(Debug)]
+pub struct Link {
+    /// Where it goes.
+    pub url: String,
+    /// The first menu we found it in.
+    pub menu: String,
+    /// How it did.
+    pub status: Status,
+    /// How long it took.
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.status {
+            Status::Broken(why) => write!(f, "{} ({})", self.url, why)?,
+            Status::Slow => write!(f, "{} ({:.1}s)", self.url, self.elapsed.as_secs_f64())?,
+            _ => write!(f, "{}", self.url)?,
+        }
+        if !self.menu.is_empty() {
+            write!(f, "\n    in {}", self.menu)?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything the checker found.
+#[derive(Debug)]
+pub struct Report {
+    /// Where it started.
+    pub url: String,
+    /// How many menus it walked.
+    pub menus: usize,
+    /// Every link it came across, in order.
+    pub links: Vec<Link>,
+    /// Servers that answered over TLS, as `host:port`.
+    pub tls: BTreeSet<String>,
+    /// How long it took.
+    pub elapsed: Duration,
+}
+
+impl Report {
+    /// Links with this status.
+    fn count(&self, f: impl Fn(&Status) -> bool) -> usize {
+        self.links.iter().filter(|l| f(&l.status)).count()
+    }
+
+    /// How many links are broken?
+    pub fn broken(&self) -> usize {
+        self.count(|s| matches!(s, Status::Broken(_)))
+    }
+
+    /// One line version of the report.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} links in {} menus: {} ok, {} broken, {} slow, {} skipped. {} TLS servers. {}s",
+            self.links.len(),
+            self.menus,
+            self.count(|s| *s == Status::Ok),
+            self.broken(),
+            self.count(|s| *s == Status::Slow),
+            self.count(|s| matches!(s, Status::Skipped(_))),
+            self.tls.len(),
+            self.elapsed.as_secs()
+        )
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "phetch check of {}", self.url)?;
+        for (title, slow) in [("broken", false), ("slow", true)] {
+            let links = self
+                .links
+                .iter()
+                .filter(|l| match l.status {
+                    Status::Slow => slow,
+                    Status::Broken(_) => !slow,
+                    _ => false,
+                })
+                .collect::<Vec<_>>();
+            if !links.is_empty() {
+                writeln!(f, "\n{}:", title)?;
+                for link in links {
+                    writeln!(f, "  {}", link)?;
+                }
+            }
+        }
+        if !self.tls.is_empty() {
+            writeln!(f, "\ntls:")?;
+            for addr in &self.tls {
+                writeln!(f, "  {}", addr)?;
+            }
+        }
+        writeln!(f, "\n{}", self.summary())
+    }
+}
+
+/// Check every link in every menu on `url`'s server, starting at
+/// `url`.
+pub fn check(
+    url: &str,
+    settings: &Settings,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+) -> Result<Report> {
+    let started = Instant::now();
+    let mut checker = Checker {
+        tls,
+        transport,
+        timeouts,
+        settings,
+        servers: HashMap::new(),
+    };
+    let mut start = gopher::parse_url(url);
+    // menus link to `/` as an empty selector
+    if start.typ == Type::Menu && start.sel == "/" {
+        start.sel.clear();
+    }
+    let mut report = Report {
+        url: start.to_string(),
+        menus: 0,
+        links: vec![],
+        tls: BTreeSet::new(),
+        elapsed: Duration::default(),
+    };
+
+    let mut seen = HashSet::new();
+    seen.insert(report.url.clone());
+    let mut queue = VecDeque::from(vec![(report.url.clone(), String::new(), 0)]);
+
+    while let Some((url, parent, depth)) = queue.pop_front() {
+        let u = gopher::parse_url(&url);
+        // only walk menus on our server, and not too deep
+        let walk = u.typ == Type::Menu
+            && u.addr() == start.addr()
+            && (settings.depth == 0 || depth <= settings.depth);
+        let started = Instant::now();
+        let (status, body) = if walk {
+            checker.probe(&u)
+        } else {
+            (checker.probe_start(&u), None)
+        };
+        let elapsed = started.elapsed();
+        report.links.push(Link {
+            url: url.clone(),
+            menu: parent,
+            status: checker.timed(status, elapsed),
+            elapsed,
+        });
+
+        if let Some(body) = body {
+            report.menus += 1;
+            let raw = gopher::response_to_string(&body);
+            let menu = menu::parse(&url, raw, SharedConfig::default());
+            for line in menu.lines() {
+                let link = line.url();
+                if line.typ.is_link() && line.typ != Type::Error && seen.insert(link.clone()) {
+                    queue.push_back((link, url.clone(), depth + 1));
+                }
+            }
+        }
+    }
+
+    report.tls = checker
+        .servers
+        .into_iter()
+        .filter(|(_, tls)| *tls == Some(true))
+        .map(|(addr, _)| addr)
+        .collect();
+    report.elapsed = started.elapsed();
+    Ok(report)
+}
+
+/// State for one run of `check()`.
+struct Checker<'a> {
+    tls: Tls,
+    transport: &'a dyn Transport,
+    timeouts: Timeouts,
+    settings: &'a Settings,
+    /// Gopher servers we've talked to, by `host:port`, and whether
+    /// they did TLS. None means we couldn't reach them.
+    servers: HashMap<String, Option<bool>>,
+}
+
+impl Checker<'_> {
+    /// Fetch a menu. Returns how it went, and the menu if it worked.
+    fn probe(&mut self, u: &Url) -> (Status, Option<Vec<u8>>) {
+        let tls = self.tls_for(u);
+        match gopher::fetch_url(&u.to_string(), tls, self.transport, self.timeouts) {
+            Ok((tls, body)) => {
+                self.servers.insert(u.addr(), Some(tls));
+                match gopher::error_response(&body) {
+                    Some(err) => (Status::Broken(err), None),
+                    None => (Status::Ok, Some(body)),
+                }
+            }
+            Err(e) => {
+                self.servers.entry(u.addr()).or_insert(None);
+                (Status::Broken(e.to_string()), None)
+            }
+        }
+    }
+
+    /// Try a link, only reading enough of it to know it works.
+    fn probe_start(&mut self, u: &Url) -> Status {
+        match u.typ {
+            Type::Telnet | Type::Telnet3270 | Type::CSOEntity | Type::Search => {
+                self.connect(&u.host, &u.port)
+            }
+            Type::HTML if !self.settings.external => Status::Skipped("external"),
+            Type::HTML => match external_addr(&u.sel) {
+                Some((host, port)) => self.connect(&host, &port),
+                None => Status::Skipped("unknown protocol"),
+            },
+            typ if !typ.is_supported() => Status::Skipped("unsupported type"),
+            _ => {
+                let tls = self.tls_for(u);
+                let url = u.to_string();
+                match gopher::fetch_start(&url, tls, self.transport, self.timeouts, 2) {
+                    Ok((tls, body, _)) => {
+                        self.servers.insert(u.addr(), Some(tls));
+                        match gopher::error_response(&body) {
+                            Some(err) => Status::Broken(err),
+                            None => Status::Ok,
+                        }
+                    }
+                    Err(e) => {
+                        self.servers.entry(u.addr()).or_insert(None);
+                        Status::Broken(e.to_string())
+                    }
+                }
+            }
+        }
+    }
+
+    /// Can we connect to `host:port`?
+    fn connect(&self, host: &str, port: &str) -> Status {
+        match self.transport.open(host, port, self.timeouts) {
+            Ok(_) => Status::Ok,
+            Err(e) => Status::Broken(e.to_string()),
+        }
+    }
+
+    /// How to connect to `u`'s server. The first time, we try TLS so
+    /// we can tell if it's supported, after that we know.
+    fn tls_for(&self, u: &Url) -> Tls {
+        match (self.tls, self.servers.get(&u.addr())) {
+            (Tls::Strict, _) => Tls::Strict,
+            (_, Some(Some(true))) | (_, None) | (Tls::On, _) => Tls::On,
+            _ => Tls::Off,
+        }
+    }
+
+    /// Worked links that took too long are slow.
+    fn timed(&self, status: Status, elapsed: Duration) -> Status {
+        if status == Status::Ok && elapsed > self.settings.slow {
+            Status::Slow
+        } else {
+            status
+        }
+    }
+}
+
+/// Where to connect for an external URL, if we know how.
+fn external_addr(url: &str) -> Option<(String, String)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let default_port = match scheme.to_lowercase().as_ref() {
+        "http" => "80",
+        "https" => "443",
+        "gemini" => "1965",
+        "ftp" => "21",
+        _ => return None,
+    };
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit('@').next()?;
+    let u = gopher::parse_url(&format!("gopher://{}", authority));
+    if u.host.is_empty() {
+        return None;
+    }
+    let port = if authority.ends_with(&format!(":{}", u.port)) {
+        u.port
+    } else {
+        default_port.into()
+    };
+    Some((u.host, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gopher::Memory, mirror, utils::TempDir};
+
+    #[test]
+    fn test_check() {
+        let net = Memory::new()
+            .page(
+                "example.com:70",
+                "",
+                "iWelcome\r\n\
+                 1Docs\t/docs\texample.com\t70\r\n\
+                 0About\t/about.txt\texample.com\t70\r\n\
+                 0Gone\t/gone.txt\texample.com\t70\r\n\
+                 1Friend\t/\tfriend.org\t70\r\n\
+                 1Dead\t/\tdead.org\t70\r\n\
+                 8BBS\t\tbbs.example.com\t23\r\n\
+                 hWeb\tURL:https://example.com/\texample.com\t70\r\n\
+                 3Oops\t\terror.host\t1\r\n",
+            )
+            .page(
+                "example.com:70",
+                "/docs",
+                "0About\t/about.txt\texample.com\t70\r\n\
+                 1Home\t\texample.com\t70\r\n\
+                 9Game\t/game.zip\texample.com\t70\r\n",
+            )
+            .page("example.com:70", "/about.txt", "About us.\r\n.\r\n")
+            .page("example.com:70", "/game.zip", "PK\u{3}\u{4}")
+            .page("friend.org:70", "", "1Deep\t/deep\tfriend.org\t70\r\n")
+            .page("bbs.example.com:23", "", "");
+
+        let report = check(
+            "gopher://example.com",
+            &Settings::default(),
+            Tls::Off,
+            &net,
+            Timeouts::default(),
+        )
+        .unwrap();
+        let links = report
+            .links
+            .iter()
+            .map(|l| (l.url.as_ref(), &l.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            links,
+            [
+                ("gopher://example.com", &Status::Ok),
+                ("gopher://example.com/1/docs", &Status::Ok),
+                ("gopher://example.com/0/about.txt", &Status::Ok),
+                (
+                    "gopher://example.com/0/gone.txt",
+                    &Status::Broken("Not found: /gone.txt".into())
+                ),
+                ("gopher://friend.org", &Status::Ok),
+                (
+                    "gopher://dead.org",
+                    &Status::Broken("Can't connect to dead.org:70: connection refused".into())
+                ),
+                ("telnet://bbs.example.com:23", &Status::Ok),
+                ("https://example.com/", &Status::Skipped("external")),
+                ("gopher://example.com/9/game.zip", &Status::Ok),
+            ]
+        );
+        assert_eq!(report.menus, 2);
+        assert_eq!(report.broken(), 2);
+        assert_eq!(report.tls.len(), 0);
+        assert_eq!(report.links[3].menu, "gopher://example.com");
+
+        let text = report.to_string();
+        assert!(text.contains(
+            "\nbroken:\n  gopher://example.com/0/gone.txt (Not found: /gone.txt)\n    in gopher://example.com\n"
+        ));
+        assert!(text.contains("9 links in 2 menus: 6 ok, 2 broken, 0 slow, 1 skipped."));
+
+        // one level deep still walks the menus the start menu links to
+        let settings = Settings {
+            depth: 1,
+            external: true,
+            ..Default::default()
+        };
+        let report = check(
+            "example.com",
+            &settings,
+            Tls::Off,
+            &net,
+            Timeouts::default(),
+        )
+        .unwrap();
+        assert_eq!(report.menus, 2);
+        assert_eq!(report.links.len(), 9);
+        assert_eq!(
+            report.links[7].status,
+            Status::Broken("Can't connect to example.com:443: connection refused".into())
+        );
+    }
+
+    #[test]
+    fn test_depth() {
+        let net = Memory::new()
+            .page("example.com:70", "", "1One\t/1\texample.com\t70\r\n")
+            .page("example.com:70", "/1", "1Two\t/2\texample.com\t70\r\n")
+            .page("example.com:70", "/2", "1Three\t/3\texample.com\t70\r\n");
+        let url = "gopher://example.com/";
+
+        // --depth means the same thing to --check and --mirror
+        let report = check(
+            url,
+            &Settings {
+                depth: 1,
+                ..Default::default()
+            },
+            Tls::Off,
+            &net,
+            Timeouts::default(),
+        )
+        .unwrap();
+        assert_eq!(report.menus, 2);
+        assert_eq!(report.links.last().unwrap().url, "gopher://example.com/1/2");
+
+        let dir = TempDir::new("depth");
+        let settings = mirror::Settings {
+            dir: dir.to_path_buf(),
+            depth: 1,
+            delay: Duration::from_secs(0),
+            ..Default::default()
+        };
+        let report =
+            mirror::mirror(url, &settings, Tls::Off, &net, Timeouts::default(), |_| {}).unwrap();
+        let lines = report
+            .entries
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("saved    gopher://example.com -> "));
+        assert!(lines[1].starts_with("saved    gopher://example.com/1/1 -> "));
+        assert_eq!(lines[2], "skipped  gopher://example.com/1/2 (too deep)");
+    }
+
+    #[test]
+    fn test_external_addr() {
+        let addr = |host: &str, port: &str| Some((host.to_string(), port.to_string()));
+        assert_eq!(
+            external_addr("https://example.com/x?y"),
+            addr("example.com", "443")
+        );
+        assert_eq!(
+            external_addr("http://me@Example.com:8080"),
+            addr("example.com", "8080")
+        );
+        assert_eq!(external_addr("gemini://[::1]/"), addr("::1", "1965"));
+        assert_eq!(external_addr("mailto:me@example.com"), None);
+        assert_eq!(external_addr("ssh://example.com"), None);
+    }
+}
@@ -1,20 +1,16 @@
-use std::{borrow::Cow, io::Result};
+use crate::Result;
+use std::borrow::Cow;
 
 /// Encoding of Gopher response. Only UTF8 and CP437 are supported.
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
 pub enum Encoding {
     /// Unicode
+    #[default]
     UTF8,
     /// https://en.wikipedia.org/wiki/Code_page_437
     CP437,
 }
 
-impl Default for Encoding {
-    fn default() -> Self {
-        Encoding::UTF8
-    }
-}
-
 impl Encoding {
     /// Accepts a string like "UTF8" or "CP437" and returns the
     /// appropriate `Encoding`, or an `Err`.
@@ -0,0 +1,188 @@
+//! phetch's `Error` type. Everything that can go wrong while fetching
+//! a page, reading the config, or saving a file ends up here, so
+//! callers can tell a DNS failure from a timeout from a typo on line
+//! 12 of `phetch.conf`. Its `Display` is the friendly message the UI
+//! shows in the status line.
+
+use crate::{gopher::Phase, known_hosts::CertChanged};
+use std::{error, fmt, io, time::Duration};
+
+/// Result type used throughout phetch.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Something went wrong.
+#[derive(Debug)]
+pub enum Error {
+    /// Couldn't look up a host's address.
+    Dns {
+        /// `host:port` we were looking up.
+        addr: String,
+        /// What the resolver said.
+        source: io::Error,
+    },
+    /// Couldn't connect to a server, or it hung up on us.
+    Network {
+        /// `host:port` of the server.
+        addr: String,
+        /// What went wrong.
+        source: io::Error,
+    },
+    /// A server took too long during some part of a request.
+    Timeout {
+        /// What we were waiting on.
+        phase: Phase,
+        /// `host:port` of the server.
+        addr: String,
+        /// How long we waited.
+        limit: Duration,
+    },
+    /// A TLS handshake or certificate problem.
+    Tls(String),
+    /// A server's certificate doesn't match the one we pinned.
+    CertChanged(CertChanged),
+    /// Couldn't reach the Tor proxy, or Tor support isn't compiled in.
+    Tor(String),
+    /// The proxy refused us or spoke gibberish.
+    Proxy(String),
+    /// Couldn't make sense of a URL, response, flag, or file.
+    Parse(String),
+    /// The server answered with an error message of its own, like a
+    /// Gopher+ error or a CSO lookup that found nothing.
+    Server(String),
+    /// Bad line in `phetch.conf` or a theme file.
+    Config {
+        /// 1-based line number.
+        line: usize,
+        /// What's wrong with it.
+        msg: String,
+        /// The key or value at fault, if it's worth showing.
+        detail: Option<String>,
+    },
+    /// A download was cancelled from the downloads page, by its
+    /// `progress` callback returning false.
+    Cancelled,
+    /// Reading or writing a local file failed.
+    Io(io::Error),
+    /// Anything else.
+    Other(String),
+}
+
+impl Error {
+    /// Is this worth retrying? True for timeouts and servers that
+    /// couldn't be reached, since those are often temporary.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Timeout { .. } => true,
+            Error::Network { source, .. } | Error::Io(source) => matches!(
+                source.kind(),
+                io::ErrorKind::TimedOut
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Dns { addr, source } => write!(f, "Can't find {}: {}", addr, source),
+            Error::Network { addr, source } => write!(f, "Can't connect to {}: {}", addr, source),
+            Error::Timeout { phase, addr, limit } => write!(
+                f,
+                "Timed out during {} with {} after {}s",
+                phase,
+                addr,
+                limit.as_secs()
+            ),
+            Error::CertChanged(e) => write!(f, "{}", e),
+            Error::Config {
+                line,
+                msg,
+                detail: Some(detail),
+            } => write!(f, "{} on line {}: {}", msg, line, detail),
+            Error::Config { line, msg, .. } => write!(f, "Line {}: {}", line, msg),
+            Error::Cancelled => write!(f, "Download cancelled"),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Tls(msg)
+            | Error::Tor(msg)
+            | Error::Proxy(msg)
+            | Error::Parse(msg)
+            | Error::Server(msg)
+            | Error::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Dns { source, .. } | Error::Network { source, .. } | Error::Io(source) => {
+                Some(source)
+            }
+            Error::CertChanged(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<CertChanged> for Error {
+    fn from(e: CertChanged) -> Self {
+        Error::CertChanged(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let e = Error::Timeout {
+            phase: Phase::Read,
+            addr: "phkt.io:70".into(),
+            limit: Duration::from_secs(8),
+        };
+        assert_eq!(
+            e.to_string(),
+            "Timed out during read with phkt.io:70 after 8s"
+        );
+        assert_eq!(e.is_retryable(), true);
+
+        let e = Error::Config {
+            line: 12,
+            msg: "Unknown key".into(),
+            detail: Some("colour".into()),
+        };
+        assert_eq!(e.to_string(), "Unknown key on line 12: colour");
+        assert_eq!(e.is_retryable(), false);
+
+        let e = Error::Config {
+            line: 3,
+            msg: "$HOME not set, can't decode `~`".into(),
+            detail: None,
+        };
+        assert_eq!(e.to_string(), "Line 3: $HOME not set, can't decode `~`");
+    }
+
+    #[test]
+    fn test_source() {
+        use std::error::Error as _;
+        let e = Error::Network {
+            addr: "phkt.io:70".into(),
+            source: io::Error::from(io::ErrorKind::ConnectionRefused),
+        };
+        assert!(e.source().is_some());
+        assert_eq!(e.is_retryable(), true);
+        assert!(Error::Cancelled.source().is_none());
+    }
+}
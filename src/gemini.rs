@@ -0,0 +1,391 @@
+//! Gemini is a newer, Gopher-inspired protocol that always runs over
+//! TLS. Requests are just a full URL, and responses are a one line
+//! header (status code + "meta" info) followed by the body.
+//!
+//! Most Gemini servers use self-signed certificates, so instead of
+//! checking with a certificate authority we pin each host's
+//! certificate the first time we see it. See `known_hosts`.
+//!
+//! gemini://gemini.circumlunar.space/docs/specification.gmi
+
+use crate::{
+    gopher::{self, Phase, Timeouts, Transport},
+    known_hosts, Error, Result,
+};
+use native_tls::{HandshakeError, TlsConnector, TlsStream};
+use std::io::{Read, Write};
+
+/// Gemini servers listen here unless told otherwise.
+pub const DEFAULT_PORT: &str = "1965";
+
+/// Give up after following this many redirects in a row.
+pub const MAX_REDIRECTS: usize = 5;
+
+/// Longest header a server is allowed to send: a two digit status,
+/// a space, 1024 bytes of meta, and CRLF.
+const MAX_HEADER_LEN: usize = 1029;
+
+/// A response from a Gemini server.
+#[derive(Debug)]
+pub struct Response {
+    /// The URL this response came from, after any redirects.
+    pub url: String,
+    /// Two digit status code. 1x = input, 2x = success, etc.
+    pub status: u8,
+    /// Prompt for input, MIME type of the body, redirect URL, or
+    /// error message, depending on the status.
+    pub meta: String,
+    /// Body of the response. Only successful responses have one.
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Does the server want us to ask the user for input?
+    pub fn is_input(&self) -> bool {
+        self.status / 10 == 1
+    }
+
+    /// Is the input sensitive, like a password?
+    pub fn is_sensitive(&self) -> bool {
+        self.status == 11
+    }
+
+    /// Is this a text/gemini document?
+    pub fn is_gemtext(&self) -> bool {
+        self.meta.is_empty() || self.mime() == "text/gemini"
+    }
+
+    /// Is this any kind of text document?
+    pub fn is_text(&self) -> bool {
+        self.mime().starts_with("text/")
+    }
+
+    /// MIME type of the body, without any parameters.
+    pub fn mime(&self) -> String {
+        self.meta
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_lowercase()
+    }
+}
+
+/// Fetch a Gemini URL, following redirects.
+pub fn fetch_url(url: &str, transport: &dyn Transport, timeouts: Timeouts) -> Result<Response> {
+    let mut url = url.to_string();
+    for _ in 0..=MAX_REDIRECTS {
+        let res = gopher::with_retries(timeouts.retries, || request(&url, transport, timeouts))?;
+        match res.status / 10 {
+            1 | 2 => return Ok(res),
+            3 => {
+                let next = resolve(&url, &res.meta);
+                if !next.starts_with("gemini://") {
+                    return Err(error!("Refusing to redirect to {}", next));
+                }
+                url = next;
+            }
+            4 | 5 => return Err(error!("Gemini error {}: {}", res.status, res.meta)),
+            6 => return Err(error!("Client certificates aren't supported: {}", res.meta)),
+            _ => return Err(error!("Unknown Gemini status: {}", res.status)),
+        }
+    }
+    Err(error!("Too many redirects: {}", url))
+}
+
+/// Make a single Gemini request and read the whole response.
+pub fn request(url: &str, transport: &dyn Transport, timeouts: Timeouts) -> Result<Response> {
+    let (host, port) = host_and_port(url)?;
+    let addr = format!("{}:{}", host, port);
+
+    let connector = TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .build()
+        .map_err(|e| Error::Tls(format!("TLS error: {}", e)))?;
+
+    let stream = transport.open(&host, &port, timeouts)?;
+    let mut stream = tls_connect(&connector, &host, &port, stream, timeouts)?;
+
+    stream
+        .write_all(format!("{}\r\n", url).as_bytes())
+        .map_err(|e| gopher::read_error(e, &addr, timeouts))?;
+
+    // Plenty of servers hang up without a TLS close_notify, so hold
+    // on to whatever we got before the error.
+    let mut raw = vec![];
+    if let Err(e) = stream.read_to_end(&mut raw) {
+        if raw.is_empty() {
+            return Err(gopher::read_error(e, &addr, timeouts));
+        }
+    }
+
+    let mut res = parse_response(&raw)?;
+    res.url = url.into();
+    Ok(res)
+}
+
+/// Start a TLS session and check the server's certificate against
+/// the one we saw last time, if any.
+fn tls_connect<S: Read + Write>(
+    connector: &TlsConnector,
+    host: &str,
+    port: &str,
+    stream: S,
+    timeouts: Timeouts,
+) -> Result<TlsStream<S>> {
+    let stream = connector.connect(host, stream).map_err(|e| match e {
+        HandshakeError::Failure(e) => Error::Tls(format!("TLS handshake failed: {}", e)),
+        HandshakeError::WouldBlock(_) => {
+            gopher::timed_out(Phase::Tls, &format!("{}:{}", host, port), timeouts.read)
+        }
+    })?;
+    let cert = stream
+        .peer_certificate()
+        .map_err(|e| Error::Tls(format!("TLS error: {}", e)))?
+        .ok_or_else(|| Error::Tls("Server didn't send a certificate".into()))?;
+    let der = cert
+        .to_der()
+        .map_err(|e| Error::Tls(format!("TLS error: {}", e)))?;
+    known_hosts::verify(host, port, &der)?;
+    Ok(stream)
+}
+
+/// Parse a raw response into its header and body.
+fn parse_response(raw: &[u8]) -> Result<Response> {
+    let end = raw
+        .iter()
+        .take(MAX_HEADER_LEN)
+        .position(|&b| b == b'\n')
+        .ok_or_else(|| Error::Parse("Bad Gemini response: header not found".into()))?;
+    let header = String::from_utf8_lossy(&raw[..end]);
+    let header = header.trim_end_matches('\r');
+
+    let status = header
+        .get(..2)
+        .and_then(|s| s.parse::<u8>().ok())
+        .filter(|s| *s >= 10)
+        .ok_or_else(|| Error::Parse(format!("Bad Gemini status: {}", header)))?;
+    let meta = header.get(2..).unwrap_or("").trim().to_string();
+
+    Ok(Response {
+        url: String::new(),
+        status,
+        meta,
+        body: raw[end + 1..].to_vec(),
+    })
+}
+
+/// Get the host and port out of a Gemini URL.
+fn host_and_port(url: &str) -> Result<(String, String)> {
+    let rest = url
+        .strip_prefix("gemini://")
+        .ok_or_else(|| Error::Parse(format!("Not a Gemini URL: {}", url)))?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let authority = authority.rsplit('@').next().unwrap_or(authority);
+
+    // ipv6
+    if let Some(rest) = authority.strip_prefix('[') {
+        let end = rest
+            .find(']')
+            .ok_or_else(|| Error::Parse(format!("Unclosed ipv6 bracket: {}", url)))?;
+        let port = rest[end + 1..].trim_start_matches(':');
+        return Ok((
+            rest[..end].into(),
+            if port.is_empty() { DEFAULT_PORT } else { port }.into(),
+        ));
+    }
+
+    match authority.find(':') {
+        Some(idx) => Ok((authority[..idx].into(), authority[idx + 1..].into())),
+        None if authority.is_empty() => Err(Error::Parse(format!("No host in URL: {}", url))),
+        None => Ok((authority.into(), DEFAULT_PORT.into())),
+    }
+}
+
+/// Does this link start with a URL scheme like `gemini:` or `mailto:`?
+fn has_scheme(link: &str) -> bool {
+    match link.find(':') {
+        Some(idx) => {
+            idx > 0
+                && link[..idx]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Resolve a link found on the page at `base` into a full URL.
+pub fn resolve(base: &str, link: &str) -> String {
+    let link = link.trim();
+    if has_scheme(link) {
+        return link.into();
+    }
+
+    let (scheme, rest) = match base.find("://") {
+        Some(idx) => (&base[..idx], &base[idx + 3..]),
+        None => ("gemini", base),
+    };
+    if let Some(link) = link.strip_prefix("//") {
+        return format!("{}://{}", scheme, link);
+    }
+
+    let rest = rest.split('#').next().unwrap_or(rest);
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, ""),
+    };
+    let path = path.split('?').next().unwrap_or(path);
+
+    let path = if link.is_empty() {
+        path.to_string()
+    } else if link.starts_with('?') {
+        format!("{}{}", path, link)
+    } else if link.starts_with('/') {
+        remove_dots(link)
+    } else {
+        let dir = match path.rfind('/') {
+            Some(idx) => &path[..=idx],
+            None => "/",
+        };
+        remove_dots(&format!("{}{}", dir, link))
+    };
+
+    format!("{}://{}{}", scheme, authority, path)
+}
+
+/// Remove `.` and `..` segments from a path, keeping any query.
+fn remove_dots(path: &str) -> String {
+    let (path, query) = match path.find('?') {
+        Some(idx) => (&path[..idx], &path[idx..]),
+        None => (path, ""),
+    };
+
+    let mut out: Vec<&str> = vec![];
+    let segments = path.split('/').skip(1).collect::<Vec<_>>();
+    for (i, seg) in segments.iter().enumerate() {
+        let last = i == segments.len() - 1;
+        match *seg {
+            "." if last => out.push(""),
+            "." => {}
+            ".." => {
+                out.pop();
+                if last {
+                    out.push("");
+                }
+            }
+            seg => out.push(seg),
+        }
+    }
+
+    format!("/{}{}", out.join("/"), query)
+}
+
+/// Replace a URL's query string with the user's input.
+pub fn with_query(url: &str, query: &str) -> String {
+    let url = url.split('#').next().unwrap_or(url);
+    let url = url.split('?').next().unwrap_or(url);
+    format!("{}?{}", url, percent_encode(query))
+}
+
+/// Percent-encode everything but unreserved characters.
+pub fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let res = parse_response(b"20 text/gemini; lang=en\r\n# Hi\r\n").unwrap();
+        assert_eq!(res.status, 20);
+        assert_eq!(res.meta, "text/gemini; lang=en");
+        assert_eq!(res.mime(), "text/gemini");
+        assert!(res.is_gemtext());
+        assert!(res.is_text());
+        assert_eq!(res.body, b"# Hi\r\n");
+
+        let res = parse_response(b"10 Enter a search term\r\n").unwrap();
+        assert!(res.is_input());
+        assert!(!res.is_sensitive());
+        assert_eq!(res.meta, "Enter a search term");
+
+        let res = parse_response(b"20\r\nbody").unwrap();
+        assert!(res.is_gemtext());
+
+        assert!(parse_response(b"hello there").is_err());
+        assert!(parse_response(b"9 nope\r\n").is_err());
+    }
+
+    #[test]
+    fn test_host_and_port() {
+        assert_eq!(
+            host_and_port("gemini://example.com/").unwrap(),
+            ("example.com".into(), "1965".into())
+        );
+        assert_eq!(
+            host_and_port("gemini://example.com:1966?q").unwrap(),
+            ("example.com".into(), "1966".into())
+        );
+        assert_eq!(
+            host_and_port("gemini://[::1]:1967/path").unwrap(),
+            ("::1".into(), "1967".into())
+        );
+        assert_eq!(
+            host_and_port("gemini://[::1]").unwrap(),
+            ("::1".into(), "1965".into())
+        );
+        assert!(host_and_port("gopher://example.com").is_err());
+        assert!(host_and_port("gemini:///path").is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let base = "gemini://example.com/docs/intro.gmi?x=1";
+        assert_eq!(
+            resolve(base, "faq.gmi"),
+            "gemini://example.com/docs/faq.gmi"
+        );
+        assert_eq!(resolve(base, "/"), "gemini://example.com/");
+        assert_eq!(resolve(base, "/a/./b/../c"), "gemini://example.com/a/c");
+        assert_eq!(resolve(base, "../up.gmi"), "gemini://example.com/up.gmi");
+        assert_eq!(resolve(base, "sub/"), "gemini://example.com/docs/sub/");
+        assert_eq!(resolve(base, ".."), "gemini://example.com/");
+        assert_eq!(
+            resolve(base, "?search"),
+            "gemini://example.com/docs/intro.gmi?search"
+        );
+        assert_eq!(resolve(base, "//other.org/x"), "gemini://other.org/x");
+        assert_eq!(
+            resolve(base, "gopher://sdf.org/1/maps"),
+            "gopher://sdf.org/1/maps"
+        );
+        assert_eq!(
+            resolve(base, "mailto:me@example.com"),
+            "mailto:me@example.com"
+        );
+        assert_eq!(
+            resolve("gemini://example.com", "page.gmi"),
+            "gemini://example.com/page.gmi"
+        );
+    }
+
+    #[test]
+    fn test_with_query() {
+        assert_eq!(
+            with_query("gemini://example.com/search?old#top", "gopher holes & more"),
+            "gemini://example.com/search?gopher%20holes%20%26%20more"
+        );
+    }
+}
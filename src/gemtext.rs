@@ -0,0 +1,645 @@
+//! A View representing a Gemini text document, aka "gemtext". Its
+//! links are numbered and selected with the cursor just like a Gopher
+//! `Menu`, while the rest of the document is wrapped to fit the
+//! screen like a `Text` view.
+//!
+//! gemini://gemini.circumlunar.space/docs/gemtext.gmi
+
+use crate::{
+    config::SharedConfig as Config,
+    gemini, terminal,
+    text::wrap_text,
+    ui::{self, Action, Key, View, MAX_COLS},
+};
+use std::fmt;
+
+/// Width of the column on the left where link numbers and the
+/// cursor are drawn. Same as in a Menu.
+const GUTTER: usize = 6;
+
+/// A single parsed line of gemtext.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Line {
+    /// Regular paragraph text.
+    Text(String),
+    /// `=> URL label`
+    Link {
+        /// Fully resolved URL.
+        url: String,
+        /// What to show on screen. The URL if no label is given.
+        label: String,
+    },
+    /// `#`, `##`, or `###` heading. (Level, Text)
+    Heading(usize, String),
+    /// `* ` list item.
+    Item(String),
+    /// `>` quote.
+    Quote(String),
+    /// A line inside a ``` preformatted block. Never wrapped.
+    Pre(String),
+}
+
+/// A single row on screen, after wrapping.
+struct Row {
+    /// Index of the `Line` this row belongs to.
+    line: usize,
+    /// Is this the first row of its `Line`?
+    first: bool,
+    /// Text to display.
+    text: String,
+}
+
+/// The Gemtext View holds the parsed document, its links, and which
+/// link is selected.
+pub struct Gemtext {
+    /// Gemini URL
+    url: String,
+    /// Raw gemtext response
+    raw: String,
+    /// Parsed lines
+    lines: Vec<Line>,
+    /// Indexes of links in the `lines` vector.
+    links: Vec<usize>,
+    /// `lines` wrapped to fit the screen. Rebuilt when the text width
+    /// changes.
+    rows: Vec<Row>,
+    /// Currently selected link. Index of the `links` vec.
+    link: usize,
+    /// Number entered so far, for jumping to links.
+    input: String,
+    /// Scrolling offset, in rows
+    offset: usize,
+    /// Size of the screen currently, cols and rows
+    size: (usize, usize),
+    /// UI mode. Interactive (Run), Printing, Raw mode...
+    mode: ui::Mode,
+    /// Wide mode?
+    wide: bool,
+    /// Scroll by how many lines? 0 = full screen
+    scroll: usize,
+    /// Retrieved via Tor?
+    tor: bool,
+    /// Global config
+    config: Config,
+}
+
+impl fmt::Display for Gemtext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.url())
+    }
+}
+
+impl View for Gemtext {
+    fn is_tls(&self) -> bool {
+        true
+    }
+
+    fn is_tor(&self) -> bool {
+        self.tor
+    }
+
+    fn url(&self) -> &str {
+        self.url.as_ref()
+    }
+
+    fn raw(&self) -> &str {
+        self.raw.as_ref()
+    }
+
+    fn term_size(&mut self, cols: usize, rows: usize) {
+        let width = self.text_width();
+        self.size = (cols, rows);
+        if self.text_width() != width {
+            self.rows = self.wrap();
+        }
+    }
+
+    fn set_wide(&mut self, wide: bool) {
+        let width = self.text_width();
+        self.wide = wide;
+        if self.text_width() != width {
+            self.rows = self.wrap();
+        }
+    }
+
+    fn wide(&mut self) -> bool {
+        self.wide
+    }
+
+    fn respond(&mut self, key: Key) -> Action {
+        match key {
+            Key::Char('\n') => self.action_open(),
+            Key::Up | Key::Ctrl('p') | Key::Char('p') | Key::Ctrl('k') | Key::Char('k') => {
+                self.action_up()
+            }
+            Key::Down | Key::Ctrl('n') | Key::Char('n') | Key::Ctrl('j') | Key::Char('j') => {
+                self.action_down()
+            }
+            Key::PageUp | Key::Ctrl('-') | Key::Char('-') => self.action_page_up(),
+            Key::PageDown | Key::Ctrl(' ') | Key::Char(' ') => self.action_page_down(),
+            Key::Home => {
+                self.offset = 0;
+                self.link = 0;
+                Action::Redraw
+            }
+            Key::End => {
+                self.offset = self.final_offset();
+                if !self.links.is_empty() {
+                    self.link = self.links.len() - 1;
+                }
+                Action::Redraw
+            }
+            Key::Char(c) if c.is_ascii_digit() => self.action_number(c),
+            _ => Action::Keypress(key),
+        }
+    }
+
+    fn render(&mut self) -> String {
+        let rows = self.rows();
+        let limit = if self.mode == ui::Mode::Run {
+            self.size.1.saturating_sub(1)
+        } else {
+            rows.len()
+        };
+        let left_margin = " ".repeat(self.indent());
+        let config = self.config.read().unwrap();
+        let theme = &config.theme;
+        let mut out = String::new();
+
+        for row in rows.iter().skip(self.offset).take(limit) {
+            out.push_str(&left_margin);
+            let line = &self.lines[row.line];
+
+            match line {
+                Line::Link { .. } if row.first => {
+                    let link = self.links.iter().position(|&l| l == row.line).unwrap_or(0);
+                    if link == self.link && self.mode == ui::Mode::Run {
+                        out.push_str(&theme.ui_cursor);
+                        out.push('*');
+                        out.push_str(reset_color!());
+                    } else {
+                        out.push(' ');
+                    }
+                    out.push(' ');
+                    out.push_str(&theme.ui_number);
+                    if link < 9 {
+                        out.push(' ');
+                    }
+                    out.push_str(&(link + 1).to_string());
+                    out.push_str(". ");
+                    out.push_str(reset_color!());
+                }
+                _ => out.push_str(&" ".repeat(GUTTER)),
+            }
+
+            match line {
+                Line::Text(_) => out.push_str(&theme.ui_text),
+                Line::Link { url, .. } => {
+                    if url.starts_with("gemini://") || url.starts_with("gopher://") {
+                        out.push_str(&theme.item_menu);
+                    } else {
+                        out.push_str(&theme.item_external);
+                    }
+                }
+                Line::Heading(..) => {
+                    out.push_str("\x1b[1m");
+                    out.push_str(&theme.ui_menu);
+                }
+                Line::Item(_) => out.push_str(&theme.ui_text),
+                Line::Quote(_) => out.push_str(&theme.gemtext_quote),
+                Line::Pre(_) => out.push_str(&theme.ui_text),
+            }
+            out.push_str(&row.text);
+            out.push_str(reset_color!());
+            out.push_str(terminal::ClearUntilNewline.as_ref());
+            out.push_str("\r\n");
+        }
+
+        out.push_str(terminal::ClearAfterCursor.as_ref());
+        out
+    }
+}
+
+impl Gemtext {
+    /// Create a Gemtext View from a gemtext response.
+    pub fn from(url: &str, response: String, config: Config) -> Gemtext {
+        let lines = parse(url, &response);
+        let links = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| matches!(line, Line::Link { .. }))
+            .map(|(i, _)| i)
+            .collect();
+        let (mode, wide, scroll, tor) = {
+            let cfg = config.read().unwrap();
+            (cfg.mode, cfg.wide, cfg.scroll, cfg.tor)
+        };
+
+        let mut page = Gemtext {
+            url: url.into(),
+            raw: response,
+            lines,
+            links,
+            rows: vec![],
+            link: 0,
+            input: String::new(),
+            offset: 0,
+            size: (0, 0),
+            mode,
+            wide,
+            scroll,
+            tor,
+            config,
+        };
+        page.rows = page.wrap();
+        page
+    }
+
+    /// Parsed lines in this document.
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// How wide the text column is, not counting the gutter.
+    fn text_width(&self) -> usize {
+        let cols = if self.wide || self.size.0 < MAX_COLS {
+            self.size.0
+        } else {
+            MAX_COLS
+        };
+        cols.saturating_sub(GUTTER).max(20)
+    }
+
+    /// Calculated size of left margin.
+    fn indent(&self) -> usize {
+        if self.wide {
+            0
+        } else {
+            self.size
+                .0
+                .saturating_sub(self.text_width() + GUTTER)
+                .checked_div(2)
+                .unwrap_or(0)
+        }
+    }
+
+    /// Wrapped rows, as of the last resize.
+    fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    /// Wrap every line to fit the screen.
+    fn wrap(&self) -> Vec<Row> {
+        let width = self.text_width();
+        let mut rows = vec![];
+        for (i, line) in self.lines.iter().enumerate() {
+            let (text, first_prefix, prefix) = match line {
+                Line::Text(text) => (text.as_ref(), "", ""),
+                Line::Link { label, .. } => (label.as_ref(), "", ""),
+                Line::Heading(_, text) => (text.as_ref(), "", ""),
+                Line::Item(text) => (text.as_ref(), "• ", "  "),
+                Line::Quote(text) => (text.as_ref(), "> ", "> "),
+                Line::Pre(text) => {
+                    rows.push(Row {
+                        line: i,
+                        first: true,
+                        text: text.clone(),
+                    });
+                    continue;
+                }
+            };
+
+            let wrapped = wrap_text(text, width.saturating_sub(prefix.len()).max(1));
+            if wrapped.is_empty() {
+                rows.push(Row {
+                    line: i,
+                    first: true,
+                    text: first_prefix.into(),
+                });
+            }
+            for (j, part) in wrapped.iter().enumerate() {
+                let text = if j == 0 {
+                    format!("{}{}", first_prefix, part)
+                } else {
+                    format!("{}{}", prefix, part.trim_start())
+                };
+                rows.push(Row {
+                    line: i,
+                    first: j == 0,
+                    text,
+                });
+            }
+        }
+        rows
+    }
+
+    /// Row where a link starts.
+    fn link_row(&self, link: usize) -> Option<usize> {
+        let &line = self.links.get(link)?;
+        self.rows().iter().position(|row| row.line == line)
+    }
+
+    /// Number of rows that fit on screen.
+    fn screen_rows(&self) -> usize {
+        self.size.1.saturating_sub(1).max(1)
+    }
+
+    /// Is the given link on screen?
+    fn is_visible(&self, link: usize) -> bool {
+        match self.link_row(link) {
+            Some(row) => row >= self.offset && row < self.offset + self.screen_rows(),
+            None => false,
+        }
+    }
+
+    /// Final `self.offset` value.
+    fn final_offset(&self) -> usize {
+        let padding = (self.size.1 as f64 * 0.9) as usize;
+        self.rows().len().saturating_sub(padding)
+    }
+
+    /// How many lines to scroll by when paging up or down.
+    fn scroll_by(&self) -> usize {
+        if self.scroll == 0 {
+            self.screen_rows()
+        } else {
+            self.scroll
+        }
+    }
+
+    /// Select a link, scrolling to it if it isn't on screen.
+    fn action_select_link(&mut self, link: usize) -> Action {
+        if link >= self.links.len() {
+            return Action::None;
+        }
+        self.link = link;
+        if !self.is_visible(link) {
+            if let Some(row) = self.link_row(link) {
+                self.offset = row.saturating_sub(5).min(self.final_offset());
+            }
+        }
+        Action::Redraw
+    }
+
+    /// Select the next link, or scroll down if it's off screen.
+    fn action_down(&mut self) -> Action {
+        let next = self.link + 1;
+        if next >= self.links.len() || !self.is_visible(next) {
+            if self.offset < self.final_offset() {
+                self.offset += 1;
+                if next < self.links.len() && self.is_visible(next) {
+                    self.link = next;
+                }
+                return Action::Redraw;
+            }
+            if next >= self.links.len() {
+                return Action::None;
+            }
+        }
+        self.action_select_link(next)
+    }
+
+    /// Select the previous link, or scroll up if it's off screen.
+    fn action_up(&mut self) -> Action {
+        if self.links.is_empty() || self.link == 0 || !self.is_visible(self.link - 1) {
+            if self.offset > 0 {
+                self.offset -= 1;
+                if self.link > 0 && self.is_visible(self.link - 1) {
+                    self.link -= 1;
+                }
+                return Action::Redraw;
+            }
+            if self.link == 0 {
+                return Action::None;
+            }
+        }
+        self.action_select_link(self.link - 1)
+    }
+
+    /// Scroll down by a page and select the first link on screen.
+    fn action_page_down(&mut self) -> Action {
+        let final_offset = self.final_offset();
+        if self.offset >= final_offset {
+            if self.links.is_empty() || self.link == self.links.len() - 1 {
+                return Action::None;
+            }
+            self.link = self.links.len() - 1;
+            return Action::Redraw;
+        }
+        self.offset = (self.offset + self.scroll_by()).min(final_offset);
+        if !self.is_visible(self.link) {
+            if let Some(link) = (0..self.links.len()).find(|&l| self.is_visible(l)) {
+                self.link = link;
+            }
+        }
+        Action::Redraw
+    }
+
+    /// Scroll up by a page and select the last link on screen.
+    fn action_page_up(&mut self) -> Action {
+        if self.offset == 0 {
+            if self.link == 0 {
+                return Action::None;
+            }
+            self.link = 0;
+            return Action::Redraw;
+        }
+        self.offset = self.offset.saturating_sub(self.scroll_by());
+        if !self.is_visible(self.link) {
+            if let Some(link) = (0..self.links.len()).rev().find(|&l| self.is_visible(l)) {
+                self.link = link;
+            }
+        }
+        Action::Redraw
+    }
+
+    /// Jump to a link by number, opening it if there's no ambiguity.
+    fn action_number(&mut self, c: char) -> Action {
+        self.input.push(c);
+        match self.input.parse::<usize>() {
+            Ok(num) if num > 0 && num <= self.links.len() => {
+                if self.links.len() < num * 10 {
+                    self.action_select_link(num - 1);
+                    self.action_open()
+                } else {
+                    self.action_select_link(num - 1)
+                }
+            }
+            _ => {
+                self.input.clear();
+                Action::None
+            }
+        }
+    }
+
+    /// Open the currently selected link.
+    fn action_open(&mut self) -> Action {
+        self.input.clear();
+        if !self.is_visible(self.link) {
+            return self.action_select_link(self.link);
+        }
+        match self.links.get(self.link).map(|&l| &self.lines[l]) {
+            Some(Line::Link { url, label }) => Action::Open(label.clone(), url.clone()),
+            _ => Action::None,
+        }
+    }
+}
+
+/// Parse a gemtext document found at `url`. Links are resolved
+/// relative to `url`.
+pub fn parse(url: &str, raw: &str) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut pre = false;
+
+    for line in raw.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with("```") {
+            pre = !pre;
+            continue;
+        }
+        if pre {
+            lines.push(Line::Pre(line.replace('\t', "    ")));
+            continue;
+        }
+
+        lines.push(if let Some(rest) = line.strip_prefix("=>") {
+            let rest = rest.trim();
+            let (link, label) = match rest.find(char::is_whitespace) {
+                Some(idx) => (&rest[..idx], rest[idx..].trim()),
+                None => (rest, ""),
+            };
+            let url = gemini::resolve(url, link);
+            Line::Link {
+                label: if label.is_empty() {
+                    link.into()
+                } else {
+                    label.into()
+                },
+                url,
+            }
+        } else if line.starts_with('#') {
+            let level = line.chars().take_while(|&c| c == '#').count().min(3);
+            Line::Heading(level, line.trim_start_matches('#').trim().into())
+        } else if let Some(rest) = line.strip_prefix("* ") {
+            Line::Item(rest.trim().into())
+        } else if let Some(rest) = line.strip_prefix('>') {
+            Line::Quote(rest.trim().into())
+        } else {
+            Line::Text(line.into())
+        });
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "# Welcome\r
+\r
+Some text that goes on for quite a while, long enough that it should wrap at least once on a small screen.\r
+=> /about.gmi About\r
+=>other.gmi\r
+* one\r
+* two\r
+> quoted\r
+```ascii art\r
+ ( o o )\r
+```\r
+=> gopher://sdf.org/1/maps Gopher\r
+### Done\r
+";
+
+    #[test]
+    fn test_parse() {
+        let lines = parse("gemini://example.com/dir/index.gmi", DOC);
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[0], Line::Heading(1, "Welcome".into()));
+        assert_eq!(lines[1], Line::Text("".into()));
+        assert_eq!(
+            lines[3],
+            Line::Link {
+                url: "gemini://example.com/about.gmi".into(),
+                label: "About".into()
+            }
+        );
+        assert_eq!(
+            lines[4],
+            Line::Link {
+                url: "gemini://example.com/dir/other.gmi".into(),
+                label: "other.gmi".into()
+            }
+        );
+        assert_eq!(lines[5], Line::Item("one".into()));
+        assert_eq!(lines[7], Line::Quote("quoted".into()));
+        assert_eq!(lines[8], Line::Pre(" ( o o )".into()));
+        assert_eq!(lines[10], Line::Heading(3, "Done".into()));
+    }
+
+    #[test]
+    fn test_rewrap() {
+        let mut page = Gemtext::from("gemini://example.com/", DOC.into(), Config::default());
+        page.term_size(40, 10);
+        let narrow = page.rows().len();
+        page.term_size(40, 20);
+        assert_eq!(page.rows().len(), narrow);
+        page.term_size(200, 20);
+        assert!(page.rows().len() < narrow);
+        page.set_wide(true);
+        assert_eq!(page.rows().len(), page.lines.len());
+        page.term_size(40, 20);
+        assert_eq!(page.rows().len(), narrow);
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut page = Gemtext::from("gemini://example.com/", DOC.into(), Config::default());
+        page.term_size(40, 10);
+        assert_eq!(page.links.len(), 3);
+        assert!(page.rows().len() > page.lines.len());
+
+        assert_eq!(page.link, 0);
+        page.action_down();
+        assert_eq!(page.link, 1);
+        match page.action_open() {
+            Action::Open(label, url) => {
+                assert_eq!(label, "other.gmi");
+                assert_eq!(url, "gemini://example.com/other.gmi");
+            }
+            a => panic!("expected Open, got {:?}", a),
+        }
+
+        // last link is off screen, so we scroll first
+        page.action_down();
+        assert_eq!(page.link, 1);
+        assert_eq!(page.offset, 1);
+
+        page.respond(Key::End);
+        assert_eq!(page.link, 2);
+        page.respond(Key::Home);
+        assert_eq!(page.link, 0);
+        assert_eq!(page.offset, 0);
+
+        match page.respond(Key::Char('3')) {
+            Action::Open(label, _) => assert_eq!(label, "Gopher"),
+            a => panic!("expected Open, got {:?}", a),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let config = Config::default();
+        config.write().unwrap().theme.gemtext_quote = crate::theme::to_color("magenta");
+        let mut page = Gemtext::from("gemini://example.com/", DOC.into(), config);
+        page.mode = ui::Mode::Print;
+        page.term_size(80, 40);
+        let out = page.render();
+        assert!(out.contains("Welcome"));
+        assert!(out.contains(" 1. "));
+        assert!(out.contains("• one"));
+        assert!(out.contains("\x1b[95m> quoted"));
+        assert!(out.contains(" ( o o )"));
+        assert!(!out.contains("```"));
+    }
+}
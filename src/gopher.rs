@@ -1,41 +1,123 @@
 //! phetch's Gopher library contains a few phetch-specific features:
-//! the ability to make requests or downloads over TLS or Tor,
-//! cleaning Unicode control characters from Gopher responses, and
-//! URL parsing that recognizes different protocols like telnet and
-//! IPv6 addresses.
+//! the ability to make requests or downloads over TLS, Tor, or a proxy
+//! (or any other `Transport`), cleaning Unicode control characters
+//! from Gopher responses, and URL parsing that recognizes different
+//! protocols like telnet and IPv6 addresses.
 
-use crate::ui::{self, Key};
+use crate::{known_hosts, Error, Result};
 use std::{
-    fs,
-    io::{Read, Result, Write},
-    net::TcpStream,
-    net::ToSocketAddrs,
-    os::unix::fs::OpenOptionsExt,
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::mpsc,
+    thread,
     time::Duration,
 };
 
-#[cfg(feature = "tor")]
-use tor_stream::TorStream;
-
 #[cfg(feature = "tls")]
-use native_tls::TlsConnector;
+use native_tls::{HandshakeError, TlsConnector, TlsStream};
 
+pub mod cso;
+pub mod decode;
+mod download;
+pub mod plus;
+mod transport;
 mod r#type;
-pub use self::r#type::Type;
+mod url;
+pub(crate) use self::download::clean_filename;
+pub use self::{
+    download::{download_filename, download_url, download_url_with_filename},
+    r#type::Type,
+    transport::{Memory, Network, ReadWrite, Transport},
+    url::{parse_url, search_url, socket_addr, Url},
+};
 
-/// Some Gopher servers can be kind of slow, we may want to up this or
-/// make it configurable eventually.
+/// Some Gopher servers can be kind of slow, so this is how long we
+/// wait by default. Use `timeout` and `read-timeout` in phetch.conf
+/// to change it.
 pub const TCP_TIMEOUT_IN_SECS: u64 = 8;
-/// Based on `TCP_TIMEOUT_IN_SECS` but a `Duration` type.
-pub const TCP_TIMEOUT_DURATION: Duration = Duration::from_secs(TCP_TIMEOUT_IN_SECS);
 
-trait ReadWrite: Read + Write {}
-impl<T: Read + Write> ReadWrite for T {}
+/// How long to wait on a server before giving up, and how many times
+/// to try again. A zero `Duration` means wait forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Max time to look up a host, and then to connect to it.
+    pub connect: Duration,
+    /// Max time to wait for a server to send us something.
+    pub read: Duration,
+    /// How many times to retry a request that timed out or couldn't
+    /// connect.
+    pub retries: usize,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            connect: Duration::from_secs(TCP_TIMEOUT_IN_SECS),
+            read: Duration::from_secs(TCP_TIMEOUT_IN_SECS),
+            retries: 0,
+        }
+    }
+}
+
+impl Timeouts {
+    /// `connect`, or None if we should wait forever.
+    fn connect_limit(&self) -> Option<Duration> {
+        Some(self.connect).filter(|d| !d.is_zero())
+    }
+
+    /// `read`, or None if we should wait forever.
+    fn read_limit(&self) -> Option<Duration> {
+        Some(self.read).filter(|d| !d.is_zero())
+    }
+}
+
+/// The parts of a request that can time out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Looking up the host's address.
+    Dns,
+    /// Opening the TCP connection.
+    Connect,
+    /// Negotiating TLS.
+    Tls,
+    /// Waiting for the server to respond.
+    Read,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Phase::Dns => "DNS lookup",
+                Phase::Connect => "connect",
+                Phase::Tls => "TLS handshake",
+                Phase::Read => "read",
+            }
+        )
+    }
+}
+
+/// When to use TLS for a Gopher connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tls {
+    /// Plain TCP, except for hosts that have done TLS with us before.
+    #[default]
+    Off,
+    /// Try TLS first, and fall back to plain TCP if it doesn't work.
+    On,
+    /// TLS or nothing. Never falls back to plain TCP.
+    Strict,
+}
 
 /// Wrapper for TLS and regular TCP streams.
 pub struct Stream {
     io: Box<dyn ReadWrite>,
     tls: bool,
+    /// Bytes we've already read from `io` but haven't handed out yet.
+    head: Vec<u8>,
 }
 
 impl Stream {
@@ -45,38 +127,44 @@ impl Stream {
 }
 
 impl Read for Stream {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.head.is_empty() {
+            let len = buf.len().min(self.head.len());
+            buf[..len].copy_from_slice(&self.head[..len]);
+            self.head.drain(..len);
+            return Ok(len);
+        }
         self.io.read(buf)
     }
 }
 
 impl Write for Stream {
-    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.io.write(buf)
     }
 
-    fn flush(&mut self) -> Result<()> {
+    fn flush(&mut self) -> io::Result<()> {
         self.io.flush()
     }
 }
 
-/// Gopher URL. Returned by `parse_url()`.
-pub struct Url<'a> {
-    /// Gopher Type
-    pub typ: Type,
-    /// Hostname
-    pub host: &'a str,
-    /// Port. Defaults to 70
-    pub port: &'a str,
-    /// Selector
-    pub sel: &'a str,
-}
-
 /// Fetches a gopher URL and returns a tuple of:
 ///   (did tls work?, raw Gopher response)
-pub fn fetch_url(url: &str, tls: bool, tor: bool) -> Result<(bool, Vec<u8>)> {
+pub fn fetch_url(
+    url: &str,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+) -> Result<(bool, Vec<u8>)> {
     let u = parse_url(url);
-    fetch(u.host, u.port, u.sel, tls, tor)
+    fetch(
+        &u.host,
+        &u.port,
+        &u.selector_line(),
+        tls,
+        transport,
+        timeouts,
+    )
 }
 
 /// Fetches a gopher URL by its component parts and returns a tuple of:
@@ -85,13 +173,50 @@ pub fn fetch(
     host: &str,
     port: &str,
     selector: &str,
-    tls: bool,
-    tor: bool,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
 ) -> Result<(bool, Vec<u8>)> {
-    let mut stream = request(host, port, selector, tls, tor)?;
-    let mut body = Vec::new();
-    stream.read_to_end(&mut body)?;
-    Ok((stream.is_tls(), body))
+    with_retries(timeouts.retries, || {
+        let mut stream = request_once(host, port, selector, tls, transport, timeouts)?;
+        let mut body = Vec::new();
+        stream
+            .read_to_end(&mut body)
+            .map_err(|e| read_error(e, &format!("{}:{}", host, port), timeouts))?;
+        Ok((stream.is_tls(), body))
+    })
+}
+
+/// Fetches the start of a Gopher URL: at least `lines` lines of it,
+/// or the whole thing if it's shorter. Returns a tuple of:
+///   (did tls work?, what we've read so far, the stream if there's more)
+pub fn fetch_start(
+    url: &str,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+    lines: usize,
+) -> Result<(bool, Vec<u8>, Option<Stream>)> {
+    let u = parse_url(url);
+    let addr = u.addr();
+    let selector = u.selector_line();
+    with_retries(timeouts.retries, || {
+        let mut stream = request_once(&u.host, &u.port, &selector, tls, transport, timeouts)?;
+        let mut body = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let count = stream
+                .read(&mut buf)
+                .map_err(|e| read_error(e, &addr, timeouts))?;
+            if count == 0 {
+                return Ok((stream.is_tls(), body, None));
+            }
+            body.extend_from_slice(&buf[..count]);
+            if body.iter().filter(|&&b| b == b'\n').count() >= lines {
+                return Ok((stream.is_tls(), body, Some(stream)));
+            }
+        }
+    })
 }
 
 /// Turn a Gopher response from `fetch` into a UTF8 String, cleaning
@@ -102,6 +227,18 @@ pub fn response_to_string(res: &[u8]) -> String {
     s
 }
 
+/// If a response is just a Gopher error, what it says.
+pub(crate) fn error_response(body: &[u8]) -> Option<String> {
+    let body = String::from_utf8_lossy(body);
+    let mut lines = body.lines().filter(|l| *l != "." && !l.is_empty());
+    let first = lines.next()?;
+    if first.starts_with('3') && first.contains('\t') && lines.next().is_none() {
+        Some(first[1..].split('\t').next().unwrap_or("").to_string())
+    } else {
+        None
+    }
+}
+
 /// Removes unprintable characters from Gopher response.
 /// https://en.wikipedia.org/wiki/Control_character#In_Unicode
 fn clean_response(res: &mut String) {
@@ -112,258 +249,266 @@ fn clean_response(res: &mut String) {
     })
 }
 
-/// Downloads menu or text to disk as `filename`.
-/// Allows canceling with Ctrl-c, but it's
-/// kind of hacky - needs the UI receiver passed in.
-/// Returns a tuple of:
-///   (path it was saved to, the size in bytes)
-pub fn download_url_with_filename(
-    url: &str,
-    tls: bool,
-    tor: bool,
-    chan: ui::KeyReceiver,
-    filename: &str,
-) -> Result<(String, usize)> {
-    let u = parse_url(url);
-    let mut path = std::path::PathBuf::from(".");
-    path.push(filename);
-
-    let mut stream = request(u.host, u.port, u.sel, tls, tor)?;
-    let mut file = fs::OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .append(true)
-        .open(&path)
-        .map_err(|e| error!("{}", e))?;
-
-    let mut buf = [0; 1024];
-    let mut bytes = 0;
-    while let Ok(count) = stream.read(&mut buf) {
-        if count == 0 {
-            break;
-        }
-        bytes += count;
-        file.write_all(&buf[..count])?;
-        if let Ok(chan) = chan.lock() {
-            if let Ok(Key::Ctrl('c')) = chan.try_recv() {
-                if path.exists() {
-                    fs::remove_file(path)?;
-                }
-                return Err(error!("Download cancelled"));
-            }
-        }
-    }
-
-    Ok((filename.to_string(), bytes))
+/// Make a Gopher request and return a TcpStream ready to be read()'d.
+/// Will attempt a TLS connection first, then retry a regular
+/// connection if it fails. Gopher+ response headers are removed.
+pub fn request(
+    host: &str,
+    port: &str,
+    selector: &str,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+) -> Result<Stream> {
+    with_retries(timeouts.retries, || {
+        request_once(host, port, selector, tls, transport, timeouts)
+    })
 }
 
-/// Downloads a binary to disk. Allows canceling with Ctrl-c, but it's
-/// kind of hacky - needs the UI receiver passed in.
-/// Returns a tuple of:
-///   (path it was saved to, the size in bytes)
-pub fn download_url(
-    url: &str,
-    tls: bool,
-    tor: bool,
-    chan: ui::KeyReceiver,
-) -> Result<(String, usize)> {
-    let u = parse_url(url);
-    let filename = u
-        .sel
-        .split_terminator('/')
-        .rev()
-        .next()
-        .ok_or_else(|| error!("Bad download filename: {}", u.sel))?;
-    let mut path = std::path::PathBuf::from(".");
-    path.push(filename);
-
-    let mut stream = request(u.host, u.port, u.sel, tls, tor)?;
-    let mut file = fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .mode(0o770)
-        .open(&path)?;
-
-    let mut buf = [0; 1024];
-    let mut bytes = 0;
-    while let Ok(count) = stream.read(&mut buf) {
-        if count == 0 {
-            break;
-        }
-        bytes += count;
-        file.write_all(&buf[..count])?;
-        if let Ok(chan) = chan.lock() {
-            if let Ok(Key::Ctrl('c')) = chan.try_recv() {
-                if path.exists() {
-                    fs::remove_file(path)?;
-                }
-                return Err(error!("Download cancelled"));
-            }
-        }
+/// `request()`, minus the retrying.
+fn request_once(
+    host: &str,
+    port: &str,
+    selector: &str,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+) -> Result<Stream> {
+    let (selector, is_plus) = plus::wire_selector(selector);
+    let addr = format!("{}:{}", host, port);
+    let mut stream = connect(host, port, tls, transport, timeouts)?;
+    stream
+        .write_all(format!("{}\r\n", selector).as_ref())
+        .map_err(|e| read_error(e, &addr, timeouts))?;
+    if is_plus {
+        plus::read_header(&mut stream, &addr, timeouts)?;
     }
-
-    Ok((filename.to_string(), bytes))
+    Ok(stream)
 }
 
-/// Make a Gopher request and return a TcpStream ready to be read()'d.
+/// Open a connection to a Gopher server, ready to send a selector.
 /// Will attempt a TLS connection first, then retry a regular
-/// connection if it fails.
-pub fn request(host: &str, port: &str, selector: &str, tls: bool, tor: bool) -> Result<Stream> {
-    let selector = selector.replace('?', "\t"); // search queries
+/// connection if it fails, unless `tls` is `Tls::Strict`. Hosts we've
+/// pinned a certificate for get TLS even when it's off, since we know
+/// they support it, and never fall back: a broken handshake with one
+/// of them is more likely an attack than a server that forgot TLS.
+/// Everything goes through `transport`.
+fn connect(
+    host: &str,
+    port: &str,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+) -> Result<Stream> {
     let addr = format!("{}:{}", host, port);
+    let pinned = known_hosts::is_known(&addr);
+    let tls = if tls == Tls::Off && pinned {
+        Tls::On
+    } else {
+        tls
+    };
 
     // attempt tls connection
-    if tls {
+    if tls != Tls::Off {
         #[cfg(feature = "tls")]
         {
-            {
-                if let Ok(connector) = TlsConnector::new() {
-                    let stream = addr
-                        .to_socket_addrs()?
-                        .find_map(|s| TcpStream::connect_timeout(&s, TCP_TIMEOUT_DURATION).ok())
-                        .ok_or_else(|| error!("Can't create socket"))?;
-                    stream.set_read_timeout(Some(TCP_TIMEOUT_DURATION))?;
-                    if let Ok(mut stream) = connector.connect(host, stream) {
-                        stream.write_all(selector.as_ref())?;
-                        stream.write_all("\r\n".as_ref())?;
-                        return Ok(Stream {
-                            io: Box::new(stream),
-                            tls: true,
-                        });
-                    }
+            let stream = transport.open(host, port, timeouts)?;
+            match tls_handshake(host, port, stream, timeouts) {
+                Ok(stream) => {
+                    return Ok(Stream {
+                        io: Box::new(stream),
+                        tls: true,
+                        head: vec![],
+                    })
                 }
+                Err(e @ Error::CertChanged(_)) => return Err(e),
+                // a server this slow to shake hands will be just as
+                // slow in plain text
+                Err(e @ Error::Timeout { .. }) => return Err(e),
+                Err(e) if tls == Tls::Strict => {
+                    return Err(Error::Tls(format!(
+                        "{}. Not falling back to plain text in strict TLS mode.",
+                        e
+                    )))
+                }
+                Err(e) if pinned => {
+                    return Err(Error::Tls(format!(
+                        "{}. Not falling back to plain text for a host with a pinned certificate.",
+                        e
+                    )))
+                }
+                Err(_) => {}
             }
         }
-    }
-
-    // tls didn't work or wasn't selected, try Tor or default
-    if tor {
-        #[cfg(feature = "tor")]
+        #[cfg(not(feature = "tls"))]
         {
-            let mut stream = std::env::var("TOR_PROXY")
-                .unwrap_or_else(|_| "127.0.0.1:9050".into())
-                .to_socket_addrs()?
-                .find_map(|s| TorStream::connect_with_address(s, addr.as_ref()).ok())
-                .ok_or_else(|| error!("Can't create socket"))?;
-            stream.write_all(selector.as_ref())?;
-            stream.write_all("\r\n".as_ref())?;
-            return Ok(Stream {
-                io: Box::new(stream),
-                tls: false,
-            });
+            if tls == Tls::Strict {
+                return Err(Error::Tls("phetch was compiled without TLS support".into()));
+            }
         }
     }
 
-    // no tls or tor, try regular connection
-    let mut stream = addr
-        .to_socket_addrs()?
-        .find_map(|s| TcpStream::connect_timeout(&s, TCP_TIMEOUT_DURATION).ok())
-        .ok_or_else(|| error!("Can't create socket"))?;
-    stream.set_read_timeout(Some(TCP_TIMEOUT_DURATION))?;
-    stream.write_all(selector.as_ref())?;
-    stream.write_all("\r\n".as_ref())?;
+    // tls didn't work or wasn't selected, try a regular connection
     Ok(Stream {
-        io: Box::new(stream),
+        io: transport.open(host, port, timeouts)?,
         tls: false,
+        head: vec![],
     })
 }
 
-impl<'a> Url<'a> {
-    /// Creates a new Gopher Url quickly from a tuple of Url fields.
-    pub fn new(typ: Type, host: &'a str, port: &'a str, sel: &'a str) -> Url<'a> {
-        Url {
-            typ,
-            host,
-            port,
-            sel,
+/// Start a TLS session over `stream`. Most gopherholes with TLS use
+/// self-signed certificates, so rather than asking a certificate
+/// authority we pin each host's certificate the first time we see it.
+/// See `known_hosts`.
+#[cfg(feature = "tls")]
+fn tls_handshake<S: Read + Write>(
+    host: &str,
+    port: &str,
+    stream: S,
+    timeouts: Timeouts,
+) -> Result<TlsStream<S>> {
+    let connector = TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .build()
+        .map_err(|e| Error::Tls(format!("TLS error: {}", e)))?;
+    let stream = connector.connect(host, stream).map_err(|e| match e {
+        HandshakeError::Failure(e) => Error::Tls(format!(
+            "TLS handshake with {}:{} failed: {}",
+            host, port, e
+        )),
+        HandshakeError::WouldBlock(_) => {
+            timed_out(Phase::Tls, &format!("{}:{}", host, port), timeouts.read)
         }
-    }
+    })?;
+    let der = stream
+        .peer_certificate()
+        .map_err(|e| Error::Tls(format!("TLS error: {}", e)))?
+        .ok_or_else(|| Error::Tls(format!("{}:{} didn't send a certificate", host, port)))?
+        .to_der()
+        .map_err(|e| Error::Tls(format!("TLS error: {}", e)))?;
+    known_hosts::verify(host, port, &der)?;
+    Ok(stream)
 }
 
-/// Given a Gopher URL, returns a gopher::Type.
-pub fn type_for_url(url: &str) -> Type {
-    if url.starts_with("telnet://") {
-        return Type::Telnet;
+/// Open a TCP connection to `addr` ("host:port"), trying each address
+/// it resolves to. Reads and writes on the returned stream time out
+/// after `timeouts.read`.
+pub(crate) fn tcp_connect(addr: &str, timeouts: Timeouts) -> Result<TcpStream> {
+    let mut last_err = None;
+    for sock in resolve(addr, timeouts)? {
+        let res = match timeouts.connect_limit() {
+            Some(limit) => TcpStream::connect_timeout(&sock, limit),
+            None => TcpStream::connect(sock),
+        };
+        match res {
+            Ok(stream) => {
+                stream.set_read_timeout(timeouts.read_limit())?;
+                stream.set_write_timeout(timeouts.read_limit())?;
+                return Ok(stream);
+            }
+            Err(e) => last_err = Some(e),
+        }
     }
 
-    if url.starts_with("URL:") || url.starts_with("/URL:") {
-        return Type::HTML;
+    match last_err {
+        Some(e) if e.kind() == ErrorKind::TimedOut => {
+            Err(timed_out(Phase::Connect, addr, timeouts.connect))
+        }
+        Some(source) => Err(Error::Network {
+            addr: addr.into(),
+            source,
+        }),
+        None => Err(Error::Dns {
+            addr: addr.into(),
+            source: io::Error::new(ErrorKind::NotFound, "no addresses found"),
+        }),
+    }
+}
+
+/// Look up `addr`, giving up after `timeouts.connect`. The lookup
+/// itself can't be cancelled, so it runs on its own thread.
+fn resolve(addr: &str, timeouts: Timeouts) -> Result<Vec<SocketAddr>> {
+    let dns_error = |source| Error::Dns {
+        addr: addr.into(),
+        source,
+    };
+    let limit = match timeouts.connect_limit() {
+        Some(limit) => limit,
+        None => return Ok(addr.to_socket_addrs().map_err(dns_error)?.collect()),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let thread_addr = addr.to_string();
+    thread::spawn(move || {
+        let _ = tx.send(
+            thread_addr
+                .to_socket_addrs()
+                .map(|addrs| addrs.collect::<Vec<_>>()),
+        );
+    });
+    match rx.recv_timeout(limit) {
+        Ok(res) => res.map_err(dns_error),
+        Err(_) => Err(timed_out(Phase::Dns, addr, timeouts.connect)),
     }
+}
 
-    let url = url.trim_start_matches("gopher://");
-    if let Some(idx) = url.find('/') {
-        if let Some(t) = url.chars().nth(idx + 1) {
-            return Type::from(t).unwrap_or(Type::Menu);
-        }
+/// Error for a request to `addr` that took longer than `limit`
+/// during `phase`.
+pub(crate) fn timed_out(phase: Phase, addr: &str, limit: Duration) -> Error {
+    Error::Timeout {
+        phase,
+        addr: addr.into(),
+        limit,
     }
+}
 
-    Type::Menu
+/// Reads from a socket with a read timeout fail with `WouldBlock` or
+/// `TimedOut`, depending on the OS. Turn either into a nicer error.
+pub(crate) fn read_error(e: io::Error, addr: &str, timeouts: Timeouts) -> Error {
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => timed_out(Phase::Read, addr, timeouts.read),
+        _ => Error::Network {
+            addr: addr.into(),
+            source: e,
+        },
+    }
 }
 
-/// Parses gopher URL into parts.
-pub fn parse_url(url: &str) -> Url {
-    let mut url = url.trim_start_matches("gopher://");
-    let mut typ = Type::Menu;
-    let mut host;
-    let mut port = "70";
-    let mut sel = "";
-
-    // simple URLs, ex: "dog.com"
-    if !url.contains(':') && !url.contains('/') {
-        return Url::new(Type::Menu, url, "70", "");
+/// Run `f`, trying again up to `retries` times if it fails because
+/// the server couldn't be reached or took too long.
+pub(crate) fn with_retries<T>(retries: usize, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(e) if attempt < retries && e.is_retryable() => attempt += 1,
+            res => return res,
+        }
     }
+}
 
-    // telnet urls
+/// Given a Gopher URL, returns a gopher::Type.
+pub fn type_for_url(url: &str) -> Type {
     if url.starts_with("telnet://") {
-        typ = Type::Telnet;
-        url = url.trim_start_matches("telnet://");
-    } else if url.contains("://") {
-        // non-gopher URLs, stick everything in selector
-        return Url::new(Type::HTML, "", "", url);
+        return Type::Telnet;
     }
 
-    // check selector first
-    if let Some(idx) = url.find('/') {
-        host = &url[..idx];
-        sel = &url[idx..];
-    } else {
-        host = url;
+    if url.starts_with("finger://") {
+        return Type::Text;
     }
 
-    // ipv6
-    if let Some(idx) = host.find('[') {
-        if let Some(end) = host[idx + 1..].find(']') {
-            host = &host[idx + 1..=end];
-            if host.len() > end {
-                if let Some(idx) = host[end..].find(':') {
-                    port = &host[idx + 1..];
-                }
-            }
-        } else {
-            return Url::new(Type::Error, "Unclosed ipv6 bracket", "", url);
-        }
-    } else if let Some(idx) = host.find(':') {
-        // two :'s == probably ipv6
-        if host.len() > idx + 1 && !host[idx + 1..].contains(':') {
-            // regular hostname w/ port -- grab port
-            port = &host[idx + 1..];
-            host = &host[..idx];
-        }
+    if url.starts_with("URL:") || url.starts_with("/URL:") {
+        return Type::HTML;
     }
 
-    // ignore type prefix on selector
-    if typ != Type::Telnet {
-        let mut chars = sel.chars();
-        if let (Some('/'), Some(c)) = (chars.next(), chars.next()) {
-            if let Some(t) = Type::from(c) {
-                typ = t;
-                sel = &sel[2..];
-            }
+    let url = url.trim_start_matches("gopher://");
+    if let Some(idx) = url.find('/') {
+        if let Some(t) = url.chars().nth(idx + 1) {
+            return Type::from(t).unwrap_or(Type::Menu);
         }
     }
 
-    Url::new(typ, host, port, sel)
+    Type::Menu
 }
 
 #[cfg(test)]
@@ -394,7 +539,15 @@ mod tests {
             "gopher://microsoft.com:7070/x/developer/sitemap.xml",
             "gopher://mtv.com/c/kriss-kross/tour-dates.ical",
             "gopher://protonmail.com/M/mymail/inbox.eml",
+            "finger://xvxx@sdf.org",
+            "finger://tilde.team/ben",
+            "finger://[::1]:7979",
+            "finger://user@host1@host2:1079",
+            "gopher://Gopher.Club/7/search%09what%3F%20now",
+            "gopher://[fe80::1%25eth0]:7070/0/notes%20%26%20things.txt%09%09+",
+            "gopher://bücher.example/1/caf%C3%A9",
         ];
+        let all = urls.clone();
         let mut urls = urls.iter();
 
         macro_rules! parse_next_url {
@@ -442,7 +595,7 @@ mod tests {
         let url = parse_next_url!();
         assert_eq!(url.typ, Type::Menu);
         assert_eq!(url.host, "1234:2345:dead:4567:7890:1234:beef:1111");
-        assert_eq!(url.port, "70");
+        assert_eq!(url.port, "7443");
         assert_eq!(url.sel, "/files");
 
         let url = parse_next_url!();
@@ -529,8 +682,91 @@ mod tests {
         assert_eq!(url.port, "70");
         assert_eq!(url.sel, "/mymail/inbox.eml");
 
+        let url = parse_next_url!();
+        assert_eq!(url.typ, Type::Text);
+        assert_eq!(url.host, "sdf.org");
+        assert_eq!(url.port, "79");
+        assert_eq!(url.sel, "xvxx");
+
+        let url = parse_next_url!();
+        assert_eq!(url.typ, Type::Text);
+        assert_eq!(url.host, "tilde.team");
+        assert_eq!(url.port, "79");
+        assert_eq!(url.sel, "ben");
+
+        let url = parse_next_url!();
+        assert_eq!(url.typ, Type::Text);
+        assert_eq!(url.host, "::1");
+        assert_eq!(url.port, "7979");
+        assert_eq!(url.sel, "");
+
+        let url = parse_next_url!();
+        assert_eq!(url.typ, Type::Text);
+        assert_eq!(url.host, "host2");
+        assert_eq!(url.port, "1079");
+        assert_eq!(url.sel, "user@host1");
+
+        let url = parse_next_url!();
+        assert_eq!(url.typ, Type::Search);
+        assert_eq!(url.host, "gopher.club");
+        assert_eq!(url.sel, "/search");
+        assert_eq!(url.search.as_deref(), Some("what? now"));
+        assert_eq!(url.plus, None);
+
+        let url = parse_next_url!();
+        assert_eq!(url.typ, Type::Text);
+        assert_eq!(url.host, "fe80::1%eth0");
+        assert_eq!(url.port, "7070");
+        assert_eq!(url.sel, "/notes & things.txt");
+        assert_eq!(url.search, None);
+        assert_eq!(url.plus.as_deref(), Some("+"));
+
+        let url = parse_next_url!();
+        assert_eq!(url.typ, Type::Menu);
+        assert_eq!(url.host, "xn--bcher-kva.example");
+        assert_eq!(url.sel, "/café");
+
         // make sure we got em all
         assert_eq!(urls.next(), None);
+
+        // and that they all survive a round trip
+        for url in all {
+            let u = parse_url(url);
+            assert_eq!(parse_url(&u.to_string()), u, "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_url_to_string() {
+        let urls = vec![
+            ("gopher.floodgap.org", "gopher://gopher.floodgap.org"),
+            ("sdf.org:7777/1/maps", "gopher://sdf.org:7777/1/maps"),
+            (
+                "phkt.io/7/lookup?Gopher",
+                "gopher://phkt.io/7/lookup%3FGopher",
+            ),
+            (
+                "phkt.io/7/lookup%09Gopher",
+                "gopher://phkt.io/7/lookup%09Gopher",
+            ),
+            ("phkt.io/0/x%09%09!", "gopher://phkt.io/0/x%09%09!"),
+            ("phkt.io/0/x%09%09?", "gopher://phkt.io/0/x%09%09?"),
+            ("phkt.io/0/a b", "gopher://phkt.io/0/a%20b"),
+            ("[::1]:7070", "gopher://[::1]:7070"),
+            ("[fe80::1%25lo]/1/", "gopher://[fe80::1%25lo]/1/"),
+            (
+                "telnet://bbs.impakt.net:6502/",
+                "telnet://bbs.impakt.net:6502/",
+            ),
+            ("finger://xvxx@sdf.org", "finger://xvxx@sdf.org"),
+            (
+                "https://github.com/xvxx/phetch",
+                "https://github.com/xvxx/phetch",
+            ),
+        ];
+        for (url, expected) in urls {
+            assert_eq!(parse_url(url).to_string(), expected);
+        }
     }
 
     #[test]
@@ -539,6 +775,7 @@ mod tests {
         assert_eq!(type_for_url("phkt.io/1"), Type::Menu);
         assert_eq!(type_for_url("phkt.io/1/"), Type::Menu);
         assert_eq!(type_for_url("phkt.io/0/info.txt"), Type::Text);
+        assert_eq!(type_for_url("finger://xvxx@sdf.org/1/"), Type::Text);
         assert_eq!(
             type_for_url("gopher://vernunftzentrum.de/0/tfurrows/resources/tokipona.txt"),
             Type::Text
@@ -563,4 +800,152 @@ mod tests {
         clean_response(&mut test);
         assert_eq!(test, "* \x1b[92mTitle\x1b[0m".to_string());
     }
+
+    #[test]
+    fn test_retries() {
+        let mut tries = 0;
+        let res: Result<()> = with_retries(2, || {
+            tries += 1;
+            Err(timed_out(
+                Phase::Connect,
+                "example.com:70",
+                Duration::from_secs(1),
+            ))
+        });
+        assert_eq!(tries, 3);
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "Timed out during connect with example.com:70 after 1s"
+        );
+
+        let mut tries = 0;
+        let res: Result<()> = with_retries(2, || {
+            tries += 1;
+            Err(error!("Gopher+ error: Not found"))
+        });
+        assert_eq!(tries, 1);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_fetch() {
+        let net = Memory::new()
+            .page(
+                "example.com:70",
+                "/about",
+                "0About\t/about.txt\texample.com\t70\r\n",
+            )
+            .page("example.com:70", "/search\tgopher", "iFound it\r\n");
+        let fetch = |url| fetch_url(url, Tls::Off, &net, Timeouts::default());
+
+        let (tls, body) = fetch("gopher://example.com/1/about").unwrap();
+        assert_eq!(tls, false);
+        assert_eq!(body, b"0About\t/about.txt\texample.com\t70\r\n");
+
+        let (_, body) = fetch("gopher://example.com/7/search%09gopher").unwrap();
+        assert_eq!(body, b"iFound it\r\n");
+
+        let (_, body) = fetch("gopher://example.com/1/nope").unwrap();
+        assert!(body.starts_with(b"3Not found"));
+
+        assert!(fetch("gopher://example.org/").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "tls")]
+    fn test_pinned_handshake_failure() {
+        // a plain text server can't finish a TLS handshake
+        let net = Memory::new()
+            .page("pinned.example:70", "/", "iHello\r\n")
+            .page("unpinned.example:70", "/", "iHello\r\n");
+        known_hosts::save("pinned.example:70", "AB:CD").unwrap();
+
+        let (tls, body) = fetch_url(
+            "gopher://unpinned.example/1/",
+            Tls::On,
+            &net,
+            Timeouts::default(),
+        )
+        .unwrap();
+        assert_eq!((tls, body.as_ref()), (false, b"iHello\r\n".as_ref()));
+
+        let err = fetch_url(
+            "gopher://pinned.example/1/",
+            Tls::Off,
+            &net,
+            Timeouts::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::Tls(_)));
+        assert!(err.to_string().contains("pinned certificate"));
+    }
+
+    #[test]
+    #[cfg(feature = "tls")]
+    fn test_tls_timeout() {
+        let server = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port().to_string();
+        thread::spawn(move || {
+            // accept and never shake hands
+            let _conn = server.accept();
+            thread::sleep(Duration::from_secs(5));
+        });
+
+        let timeouts = Timeouts {
+            read: Duration::from_secs(1),
+            ..Timeouts::default()
+        };
+        let err = fetch(
+            "127.0.0.1",
+            &port,
+            "/",
+            Tls::On,
+            &Network::default(),
+            timeouts,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Timed out during TLS handshake with 127.0.0.1:{} after 1s",
+                port
+            )
+        );
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        let server = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port().to_string();
+        thread::spawn(move || {
+            // accept and never answer
+            let _conn = server.accept();
+            thread::sleep(Duration::from_secs(5));
+        });
+
+        let timeouts = Timeouts {
+            read: Duration::from_secs(1),
+            ..Timeouts::default()
+        };
+        let err = fetch(
+            "127.0.0.1",
+            &port,
+            "/",
+            Tls::Off,
+            &Network::default(),
+            timeouts,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Timeout {
+                phase: Phase::Read,
+                ..
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            format!("Timed out during read with 127.0.0.1:{} after 1s", port)
+        );
+    }
 }
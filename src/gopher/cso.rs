@@ -0,0 +1,170 @@
+//! CSO, also known as ph or qi, is the campus phone book protocol
+//! from RFC 2378. Gopher menus link to CSO servers with type 2 items,
+//! usually on port 105. Instead of a selector, the server gets a
+//! `query` command and answers with numbered records, one field per
+//! line:
+//!
+//!   102:There was 1 match to your request.
+//!   -200:1:         name: Gopher, Joe
+//!   -200:1:        email: joe@example.com
+//!   200:Ok.
+//!
+//! phetch represents a lookup as the item's URL with the query as its
+//! RFC 4266 search string: `gopher://ns.example.edu:105/2%09joe`
+
+use super::{parse_url, read_error, Timeouts, Tls, Transport};
+use crate::{Error, Result};
+use std::io::{Read, Write};
+
+/// Send the query in `url`'s search string to its CSO server. Returns
+/// a tuple of:
+///   (did tls work?, raw CSO response)
+pub fn fetch_url(
+    url: &str,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+) -> Result<(bool, Vec<u8>)> {
+    let u = parse_url(url);
+    let query = u
+        .search
+        .as_deref()
+        .map(|q| q.replace(['\r', '\n'], " "))
+        .filter(|q| !q.trim().is_empty())
+        .ok_or_else(|| Error::Parse("Nothing to look up".into()))?;
+    let addr = u.addr();
+    let mut stream = super::connect(&u.host, &u.port, tls, transport, timeouts)?;
+    stream
+        .write_all(format!("query {}\r\nquit\r\n", query.trim()).as_bytes())
+        .map_err(|e| read_error(e, &addr, timeouts))?;
+
+    let mut body = Vec::new();
+    stream
+        .read_to_end(&mut body)
+        .map_err(|e| read_error(e, &addr, timeouts))?;
+    Ok((stream.is_tls(), body))
+}
+
+/// Turn a CSO response into something readable: one block of fields
+/// per match. Fails with the server's message if nothing matched.
+pub fn to_text(res: &str) -> Result<String> {
+    let mut out = vec![];
+    let mut record = None;
+    let mut error = None;
+    let mut replied = false;
+
+    for line in res.lines() {
+        let line = line.trim_end_matches('\r');
+        let (code, rest) = match line.split_once(':') {
+            Some((code, rest)) => match code.parse::<i32>() {
+                Ok(code) => (code, rest),
+                Err(_) => continue,
+            },
+            None => continue,
+        };
+        replied = true;
+
+        // records: -200:index:field: value, or -5xx:index:field:error
+        if code < 0 {
+            let (index, field) = match rest.split_once(':') {
+                Some(parts) => parts,
+                None => continue,
+            };
+            if record != Some(index) {
+                if record.is_some() {
+                    out.push(String::new());
+                }
+                record = Some(index);
+            }
+            if code == -200 {
+                out.push(field.to_string());
+            } else if let Some((name, msg)) = field.split_once(':') {
+                out.push(format!("{}: ({})", name, msg.trim()));
+            }
+            continue;
+        }
+
+        match code {
+            // There were N matches to your request.
+            102 => out.insert(0, format!("{}\n", rest.trim())),
+            400..=599 if error.is_none() => error = Some(rest.trim().to_string()),
+            _ => {}
+        }
+    }
+
+    if !replied {
+        return Err(Error::Parse("Not a CSO response".into()));
+    } else if record.is_none() {
+        return Err(Error::Server(error.unwrap_or_else(|| "No matches".into())));
+    }
+    out.push(String::new());
+    Ok(out.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gopher::Memory;
+
+    const RESPONSE: &str = "102:There were 2 matches to your request.\r
+-200:1:         name: Gopher, Joe\r
+-200:1:        email: joe@example.com\r
+-200:1:      address: 1 Hole Ln\r
+-200:1:             : Minneapolis, MN\r
+-200:2:         name: Gopher, Jane\r
+-508:2:        email:Not present in entry.\r
+200:Ok.\r
+200:Bye!\r
+";
+
+    #[test]
+    fn test_to_text() {
+        assert_eq!(
+            to_text(RESPONSE).unwrap(),
+            "There were 2 matches to your request.
+
+         name: Gopher, Joe
+        email: joe@example.com
+      address: 1 Hole Ln
+             : Minneapolis, MN
+
+         name: Gopher, Jane
+        email: (Not present in entry.)
+"
+        );
+
+        let err = to_text("501:No matches to your query.\r\n200:Bye!\r\n").unwrap_err();
+        assert_eq!(err.to_string(), "No matches to your query.");
+        assert!(matches!(err, Error::Server(_)));
+        assert!(matches!(to_text("200:Bye!\r\n"), Err(Error::Server(_))));
+        assert!(matches!(to_text(""), Err(Error::Parse(_))));
+        assert!(matches!(to_text("<html>"), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_fetch_url() {
+        let net = Memory::new().handler("ns.example.edu:105", |req| {
+            if req == "query name=gopher\r\nquit" {
+                RESPONSE.into()
+            } else {
+                format!("598:{}:Unknown request\r\n", req).into_bytes()
+            }
+        });
+        let (tls, res) = fetch_url(
+            "gopher://ns.example.edu:105/2%09name=gopher",
+            Tls::Off,
+            &net,
+            Timeouts::default(),
+        )
+        .unwrap();
+        assert_eq!(tls, false);
+        assert_eq!(res, RESPONSE.as_bytes());
+
+        let url = "gopher://ns.example.edu:105/2%09joe%0D%0Aquit";
+        let (_, res) = fetch_url(url, Tls::Off, &net, Timeouts::default()).unwrap();
+        assert!(String::from_utf8_lossy(&res).starts_with("598:query joe  quit\r\nquit:"));
+
+        let url = "gopher://ns.example.edu:105/2";
+        assert!(fetch_url(url, Tls::Off, &net, Timeouts::default()).is_err());
+    }
+}
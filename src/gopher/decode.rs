@@ -0,0 +1,401 @@
+//! Type 4 and 6 items are files wrapped up as text, BinHex 4.0 and
+//! UUEncoding respectively, from back when mail and news couldn't
+//! carry binaries. This module unwraps them so downloads are saved as
+//! the real file, and so we can show what's inside before saving it.
+
+use super::{
+    download::{clean_filename, partial_path, place},
+    Type,
+};
+use crate::{utils, Error, Result};
+use std::{fs, io::Write, os::unix::fs::PermissionsExt, path::Path};
+
+/// BinHex 4.0's 6-bit alphabet.
+const BINHEX_CHARS: &[u8] = b"!\"#$%&'()*+,-012345689@ABCDEFGHIJKLMNPQRSTUVXYZ[`abcdefhijklmpqr";
+
+/// BinHex run-length encoding marker.
+const BINHEX_RUN: u8 = 0x90;
+
+/// A file unwrapped from a UUEncoded or BinHex item.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Decoded {
+    /// Name it was encoded with.
+    pub name: String,
+    /// Unix permissions, from UUEncoded files.
+    pub mode: Option<u32>,
+    /// Mac file type and creator codes, from BinHex files.
+    pub mac: Option<(String, String)>,
+    /// Size of the Mac resource fork, which we don't keep.
+    pub resource_len: usize,
+    /// The file itself.
+    pub data: Vec<u8>,
+}
+
+impl Decoded {
+    /// The last part of `name`, made safe to save to disk.
+    pub fn filename(&self) -> String {
+        self.name
+            .rsplit(['/', '\\', ':'])
+            .map(clean_filename)
+            .find(|name| !name.is_empty())
+            .unwrap_or_else(|| "download".into())
+    }
+
+    /// What's inside, for showing before it's downloaded.
+    pub fn summary(&self) -> String {
+        let mut out = format!(
+            "Encoded file: {}\nSize: {}\n",
+            self.name,
+            utils::human_bytes(self.data.len())
+        );
+        if let Some(mode) = self.mode {
+            out.push_str(&format!("Mode: {:03o}\n", mode));
+        }
+        if let Some((typ, creator)) = &self.mac {
+            out.push_str(&format!("Mac type: {}  Creator: {}\n", typ, creator));
+        }
+        if self.resource_len > 0 {
+            out.push_str(&format!(
+                "Resource fork: {} (not saved)\n",
+                utils::human_bytes(self.resource_len)
+            ));
+        }
+        out
+    }
+}
+
+/// Can we decode items of this type?
+pub fn is_encoded(typ: Type) -> bool {
+    matches!(typ, Type::UUEncoded | Type::Binhex)
+}
+
+/// Unwrap a type 4 or 6 item.
+pub fn decode(typ: Type, raw: &[u8]) -> Result<Decoded> {
+    match typ {
+        Type::UUEncoded => uudecode(raw),
+        Type::Binhex => unbinhex(raw),
+        _ => Err(error!("Not an encoded type: {:?}", typ)),
+    }
+}
+
+/// A Text view's worth of `raw`: what's inside, then the item itself.
+pub fn preview(typ: Type, raw: &[u8]) -> Vec<u8> {
+    let mut out = match decode(typ, raw) {
+        Ok(decoded) => format!("{}Press d to download and decode it.\n", decoded.summary()),
+        Err(e) => format!("Can't decode this file: {}\n", e),
+    }
+    .into_bytes();
+    out.extend_from_slice(b"\n");
+    out.extend_from_slice(raw);
+    out
+}
+
+/// Decode the downloaded item at `path` and replace it with the file
+/// inside, saved in the same directory. Keeps the name of `path` if
+/// `keep_name` is set, otherwise uses the name it was encoded with.
+/// Returns the new path and its size in bytes.
+pub fn decode_file(typ: Type, path: &str, keep_name: bool) -> Result<(String, usize)> {
+    let path = Path::new(path);
+    let decoded = decode(typ, &fs::read(path)?)?;
+    let target = if keep_name {
+        path.to_path_buf()
+    } else {
+        path.with_file_name(decoded.filename())
+    };
+
+    // if there's already a partial file, someone else is decoding
+    // this very file, so leave it to them
+    let partial = partial_path(&target);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&partial)?;
+    let written = file
+        .write_all(&decoded.data)
+        .and_then(|()| match decoded.mode {
+            Some(mode) => fs::set_permissions(&partial, fs::Permissions::from_mode(mode & 0o777)),
+            None => Ok(()),
+        });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&partial);
+        return Err(e.into());
+    }
+
+    // the original is only removed once its replacement is in place
+    let placed = if target == path {
+        fs::rename(&partial, &target).map(|()| target)
+    } else {
+        place(&partial, &target)
+    };
+    let target = match placed {
+        Ok(target) => target,
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            return Err(e.into());
+        }
+    };
+    if target != path {
+        fs::remove_file(path)?;
+    }
+    Ok((target.to_string_lossy().to_string(), decoded.data.len()))
+}
+
+/// Decode a UUEncoded file:
+///
+///   begin 644 name.txt
+///   <line length><encoded line>
+///   `
+///   end
+pub fn uudecode(raw: &[u8]) -> Result<Decoded> {
+    let text = String::from_utf8_lossy(raw);
+    let mut lines = text.lines();
+    let (mode, name) = lines
+        .find_map(|line| {
+            let mut parts = line.strip_prefix("begin ")?.trim().splitn(2, ' ');
+            let mode = u32::from_str_radix(parts.next()?, 8).ok()?;
+            Some((mode, parts.next()?.trim().to_string()))
+        })
+        .ok_or_else(|| Error::Parse("No `begin` line found".into()))?;
+
+    let mut data = vec![];
+    for line in lines {
+        let line = line.trim_end_matches('\r').as_bytes();
+        if line == b"end" {
+            return Ok(Decoded {
+                name,
+                mode: Some(mode),
+                mac: None,
+                resource_len: 0,
+                data,
+            });
+        }
+        let len = match line.first() {
+            Some(&c) => uu_char(c) as usize,
+            None => continue,
+        };
+        let mut bytes = vec![];
+        for chunk in line[1..].chunks(4) {
+            let mut n = 0u32;
+            for i in 0..4 {
+                n = n << 6 | u32::from(uu_char(*chunk.get(i).unwrap_or(&b'`')));
+            }
+            bytes.extend_from_slice(&n.to_be_bytes()[1..]);
+        }
+        if bytes.len() < len {
+            return Err(Error::Parse("UUEncoded line is too short".into()));
+        }
+        data.extend_from_slice(&bytes[..len]);
+    }
+    Err(Error::Parse("No `end` line found".into()))
+}
+
+/// Value of one UUEncoded character. Both ` and space mean zero.
+fn uu_char(c: u8) -> u8 {
+    c.wrapping_sub(b' ') & 0x3f
+}
+
+/// Decode a BinHex 4.0 file. Only the data fork is kept.
+pub fn unbinhex(raw: &[u8]) -> Result<Decoded> {
+    let text = String::from_utf8_lossy(raw);
+    let start = text
+        .find("(This file must be converted with BinHex")
+        .ok_or_else(|| Error::Parse("No BinHex header found".into()))?;
+    let text = &text[start..];
+    let body = text
+        .find("\n:")
+        .or_else(|| text.find("\r:"))
+        .map(|i| &text[i + 2..])
+        .ok_or_else(|| Error::Parse("No BinHex data found".into()))?;
+    let end = body
+        .find(':')
+        .ok_or_else(|| Error::Parse("BinHex data is cut off".into()))?;
+
+    // 6 bits at a time, then undo the run-length encoding
+    let mut packed = vec![];
+    let (mut bits, mut nbits) = (0u32, 0);
+    for c in body[..end].bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let val = BINHEX_CHARS
+            .iter()
+            .position(|&b| b == c)
+            .ok_or_else(|| Error::Parse(format!("Bad BinHex character: {}", c as char)))?;
+        bits = bits << 6 | val as u32;
+        nbits += 6;
+        if nbits >= 8 {
+            nbits -= 8;
+            packed.push((bits >> nbits) as u8);
+            bits &= (1 << nbits) - 1;
+        }
+    }
+    let mut data = Vec::with_capacity(packed.len());
+    let mut bytes = packed.into_iter();
+    while let Some(b) = bytes.next() {
+        if b != BINHEX_RUN {
+            data.push(b);
+            continue;
+        }
+        match bytes.next() {
+            Some(0) => data.push(BINHEX_RUN),
+            Some(n) => {
+                let last = *data
+                    .last()
+                    .ok_or_else(|| Error::Parse("BinHex run with nothing to repeat".into()))?;
+                data.extend(std::iter::repeat_n(last, n as usize - 1));
+            }
+            None => return Err(Error::Parse("BinHex data is cut off".into())),
+        }
+    }
+
+    let mut r = BinhexReader {
+        data: &data,
+        pos: 0,
+    };
+    let name_len = r.take(1)?[0] as usize;
+    let name = String::from_utf8_lossy(r.take(name_len)?).to_string();
+    r.take(1)?; // version
+    let typ = String::from_utf8_lossy(r.take(4)?).to_string();
+    let creator = String::from_utf8_lossy(r.take(4)?).to_string();
+    r.take(2)?; // Finder flags
+    let data_len = r.u32()? as usize;
+    let resource_len = r.u32()? as usize;
+    r.check_crc(0)?;
+    let fork = r.take(data_len)?.to_vec();
+    r.check_crc(r.pos - data_len)?;
+
+    Ok(Decoded {
+        name,
+        mode: None,
+        mac: Some((typ, creator)),
+        resource_len,
+        data: fork,
+    })
+}
+
+/// Walks through the sections of a decoded BinHex file.
+struct BinhexReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BinhexReader<'a> {
+    /// The next `n` bytes.
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| Error::Parse("BinHex data is cut off".into()))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    /// The next 4 bytes, big-endian.
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Read a CRC and check it against everything since `start`.
+    fn check_crc(&mut self, start: usize) -> Result<()> {
+        let expected = crc16(&self.data[start..self.pos]);
+        let bytes = self.take(2)?;
+        if u16::from_be_bytes([bytes[0], bytes[1]]) != expected {
+            return Err(Error::Parse("BinHex checksum doesn't match".into()));
+        }
+        Ok(())
+    }
+}
+
+/// CRC-16/XMODEM, which BinHex uses for each section.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &b in data {
+        crc ^= u16::from(b) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempDir;
+
+    #[test]
+    fn test_uudecode() {
+        let raw =
+            "Some news post\r\n\r\nbegin 644 hello.txt\r\n.2&5L;&\\L('=O<FQD(0H`\r\n`\r\nend\r\n";
+        let decoded = uudecode(raw.as_bytes()).unwrap();
+        assert_eq!(decoded.name, "hello.txt");
+        assert_eq!(decoded.mode, Some(0o644));
+        assert_eq!(decoded.data, b"Hello, world!\n");
+        assert!(decoded.summary().contains("Mode: 644"));
+
+        assert!(matches!(
+            uudecode(b"begin 644 x\n#86)C\n"),
+            Err(Error::Parse(_))
+        ));
+        assert!(matches!(uudecode(b"no header here"), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_unbinhex() {
+        let raw = r#"(This file must be converted with BinHex 4.0)
+:#@j[G'9c,R4iG!"849K8G(4iG!#3"5)!!!!%rEe0B@-JCQPXC3eKN"53!(4KD@b
+VM&*68N12Y`:
+"#;
+        let decoded = unbinhex(raw.as_bytes()).unwrap();
+        assert_eq!(decoded.name, "notes.txt");
+        assert_eq!(decoded.mode, None);
+        assert_eq!(decoded.mac, Some(("TEXT".into(), "ttxt".into())));
+        assert_eq!(decoded.resource_len, 4);
+        let mut data = b"Mac file\r".to_vec();
+        data.extend_from_slice(&[b'a'; 20]);
+        data.extend_from_slice(b"\x90tail");
+        assert_eq!(decoded.data, data);
+        assert!(decoded.summary().contains("Creator: ttxt"));
+
+        let corrupt = raw.replace("#@j[", "#@j!");
+        assert!(matches!(unbinhex(corrupt.as_bytes()), Err(Error::Parse(_))));
+        assert!(unbinhex(b"(This file must be converted with BinHex 4.0)").is_err());
+    }
+
+    #[test]
+    fn test_decode_file() {
+        let dir = TempDir::new("decode");
+        let uu = "begin 755 ../../bin/hello\n.2&5L;&\\L('=O<FQD(0H`\n`\nend\n";
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+
+        fs::write(path("hello.uue"), uu).unwrap();
+        let (saved, bytes) = decode_file(Type::UUEncoded, &path("hello.uue"), false).unwrap();
+        assert_eq!((saved.as_str(), bytes), (path("hello").as_str(), 14));
+        assert_eq!(fs::read(&saved).unwrap(), b"Hello, world!\n");
+        let mode = fs::metadata(&saved).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert!(!Path::new(&path("hello.uue")).exists());
+
+        fs::write(path("mine"), uu).unwrap();
+        let (saved, _) = decode_file(Type::UUEncoded, &path("mine"), true).unwrap();
+        assert_eq!(saved, path("mine"));
+
+        // someone else is already decoding it
+        fs::write(path("busy.uue"), uu).unwrap();
+        fs::write(path(".hello.part"), "mine").unwrap();
+        assert!(decode_file(Type::UUEncoded, &path("busy.uue"), false).is_err());
+        assert_eq!(fs::read_to_string(path(".hello.part")).unwrap(), "mine");
+        assert!(Path::new(&path("busy.uue")).exists());
+
+        fs::write(path("bad.uue"), "not encoded").unwrap();
+        assert!(decode_file(Type::UUEncoded, &path("bad.uue"), false).is_err());
+        assert!(Path::new(&path("bad.uue")).exists());
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+    }
+}
@@ -0,0 +1,292 @@
+//! Saving Gopher items to disk. Downloads are written to a hidden
+//! `.name.part` file next to where they're going, then renamed once
+//! they're complete, so a half-finished download never looks like a
+//! finished one. Names taken from selectors are cleaned up so a server
+//! can't write outside the downloads directory, and nothing already
+//! on disk gets overwritten: `file.txt` becomes `file (1).txt`.
+
+use super::{parse_url, read_error, request, Timeouts, Tls, Transport};
+use crate::{Error, Result};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Longest filename most filesystems will take, in bytes.
+const MAX_FILENAME: usize = 255;
+
+/// Downloads `url` into `dir`, named after the last part of its
+/// selector. `progress` is called with the number of bytes saved so
+/// far as they arrive; return `false` from it to cancel the download.
+/// Returns a tuple of:
+///   (path it was saved to, the size in bytes)
+pub fn download_url(
+    url: &str,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+    dir: &Path,
+    progress: impl FnMut(usize) -> bool,
+) -> Result<(String, usize)> {
+    let path = dir.join(download_filename(url));
+    download(url, tls, transport, timeouts, &path, progress)
+}
+
+/// Downloads `url` as `filename`, which is relative to `dir` unless
+/// it's an absolute path. Otherwise the same as `download_url()`.
+pub fn download_url_with_filename(
+    url: &str,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+    dir: &Path,
+    filename: &str,
+    progress: impl FnMut(usize) -> bool,
+) -> Result<(String, usize)> {
+    let path = dir.join(filename);
+    download(url, tls, transport, timeouts, &path, progress)
+}
+
+/// A safe name to save `url` as: the last part of its selector,
+/// without any path tricks, control characters, or leading dots. Falls
+/// back to the host name when the selector doesn't have one.
+pub fn download_filename(url: &str) -> String {
+    let u = parse_url(url);
+    let name = u
+        .sel
+        .rsplit(['/', '\\'])
+        .map(clean_filename)
+        .find(|name| !name.is_empty())
+        .unwrap_or_else(|| clean_filename(&u.host));
+    if name.is_empty() {
+        "download".into()
+    } else {
+        name
+    }
+}
+
+/// Strip anything from `name` that doesn't belong in a filename.
+pub(crate) fn clean_filename(name: &str) -> String {
+    let name = name
+        .chars()
+        .filter(|c| !c.is_control() && *c != '/' && *c != '\\')
+        .collect::<String>();
+    let mut name = name.trim().trim_start_matches('.').to_string();
+    while name.len() > MAX_FILENAME {
+        name.pop();
+    }
+    name
+}
+
+/// Saves the response to `url` as `path`, or the next free name after
+/// it. Returns the path it was saved to and the size in bytes.
+fn download(
+    url: &str,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+    path: &Path,
+    mut progress: impl FnMut(usize) -> bool,
+) -> Result<(String, usize)> {
+    let u = parse_url(url);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut stream = request(
+        &u.host,
+        &u.port,
+        &u.selector_line(),
+        tls,
+        transport,
+        timeouts,
+    )?;
+
+    // claim a name before we start, so two downloads of the same
+    // file don't end up in the same place
+    let path = unique_path(path, |p| p.exists() || partial_path(p).exists());
+    let partial = partial_path(&path);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&partial)?;
+
+    let mut buf = [0; 4096];
+    let mut bytes = 0;
+    loop {
+        let count = match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(e) => {
+                let _ = fs::remove_file(&partial);
+                return Err(read_error(e, &u.addr(), timeouts));
+            }
+        };
+        bytes += count;
+        if let Err(e) = file.write_all(&buf[..count]) {
+            let _ = fs::remove_file(&partial);
+            return Err(e.into());
+        }
+        if !progress(bytes) {
+            let _ = fs::remove_file(&partial);
+            return Err(Error::Cancelled);
+        }
+    }
+
+    // someone else may have used our name in the meantime
+    let path = match place(&partial, &path) {
+        Ok(path) => path,
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            return Err(e.into());
+        }
+    };
+    Ok((path.to_string_lossy().to_string(), bytes))
+}
+
+/// Where a download is kept until it's complete.
+pub(super) fn partial_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.part", name))
+}
+
+/// `path`, or the first of `name (1).ext`, `name (2).ext`, etc that
+/// isn't `taken`.
+pub(super) fn unique_path(path: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    candidates(path).find(|p| !taken(p)).unwrap()
+}
+
+/// Moves a finished `partial` file to `path`, or to the first of
+/// `name (1).ext`, `name (2).ext`, etc that's free. Names are claimed
+/// with a hard link, which fails rather than replacing a file that
+/// shows up between checking a name and using it.
+pub(super) fn place(partial: &Path, path: &Path) -> io::Result<PathBuf> {
+    for path in candidates(path) {
+        match fs::hard_link(partial, &path) {
+            Ok(()) => {
+                fs::remove_file(partial)?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+/// `path`, then `name (1).ext`, `name (2).ext`, and so on.
+fn candidates(path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = match path.extension() {
+        Some(ext) => format!(".{}", ext.to_string_lossy()),
+        None => String::new(),
+    };
+    std::iter::once(path.to_path_buf())
+        .chain((1..).map(move |i| path.with_file_name(format!("{} ({}){}", stem, i, ext))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gopher::Memory, utils::TempDir};
+
+    #[test]
+    fn test_download_filename() {
+        let names = [
+            ("gopher://example.com/9/files/game.zip", "game.zip"),
+            ("gopher://example.com/9/files/game.zip/", "game.zip"),
+            ("gopher://example.com/9/../../.bashrc", "bashrc"),
+            ("gopher://example.com/9/files/..", "files"),
+            ("gopher://example.com/9\\DOS\\GAMES\\DOOM.ZIP", "DOOM.ZIP"),
+            ("gopher://example.com/0/bell%07.txt", "bell.txt"),
+            ("gopher://example.com/1/", "example.com"),
+            ("gopher://example.com/1", "example.com"),
+        ];
+        for (url, name) in names {
+            assert_eq!(download_filename(url), name, "{}", url);
+        }
+        assert_eq!(
+            download_filename(&format!("gopher://example.com/9/{}", "x".repeat(300))).len(),
+            MAX_FILENAME
+        );
+    }
+
+    #[test]
+    fn test_unique_path() {
+        let taken = ["a/file.txt", "a/file (1).txt", "a/README"];
+        let taken = |p: &Path| taken.iter().any(|t| Path::new(t) == p);
+        assert_eq!(
+            unique_path(Path::new("a/new.txt"), taken),
+            Path::new("a/new.txt")
+        );
+        assert_eq!(
+            unique_path(Path::new("a/file.txt"), taken),
+            Path::new("a/file (2).txt")
+        );
+        assert_eq!(
+            unique_path(Path::new("a/README"), taken),
+            Path::new("a/README (1)")
+        );
+        assert_eq!(
+            partial_path(Path::new("a/file.txt")),
+            Path::new("a/.file.txt.part")
+        );
+    }
+
+    #[test]
+    fn test_download() {
+        let dir = TempDir::new("download");
+        let net = Memory::new().page("example.com:70", "/docs/notes.txt", "hello");
+        let url = "gopher://example.com/0/docs/notes.txt";
+        let get = |progress: fn(usize) -> bool| {
+            download_url(url, Tls::Off, &net, Timeouts::default(), &dir, progress)
+        };
+
+        let (path, bytes) = get(|_| true).unwrap();
+        assert_eq!(bytes, 5);
+        assert_eq!(Path::new(&path), dir.join("notes.txt"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello");
+
+        let (path, _) = get(|_| true).unwrap();
+        assert_eq!(Path::new(&path), dir.join("notes (1).txt"));
+
+        assert!(matches!(get(|_| false), Err(Error::Cancelled)));
+        assert!(!dir.join("notes (2).txt").exists());
+        assert!(!dir.join(".notes (2).txt.part").exists());
+
+        let (path, _) = download_url_with_filename(
+            url,
+            Tls::Off,
+            &net,
+            Timeouts::default(),
+            &dir,
+            "sub/mine.txt",
+            |_| true,
+        )
+        .unwrap();
+        assert_eq!(Path::new(&path), dir.join("sub/mine.txt"));
+
+        // a name that's taken by the time we finish is left alone
+        fs::write(dir.join(".late.part"), "late").unwrap();
+        assert_eq!(
+            place(&dir.join(".late.part"), &dir.join("notes.txt")).unwrap(),
+            dir.join("notes (2).txt")
+        );
+        assert_eq!(fs::read_to_string(dir.join("notes.txt")).unwrap(), "hello");
+        assert_eq!(
+            fs::read_to_string(dir.join("notes (2).txt")).unwrap(),
+            "late"
+        );
+
+        let mut names = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            ["notes (1).txt", "notes (2).txt", "notes.txt", "sub"]
+        );
+    }
+}
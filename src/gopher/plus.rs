@@ -0,0 +1,524 @@
+//! Gopher+ is a backwards compatible extension to Gopher. Servers
+//! mark Gopher+ items in their menus with a fifth, tab separated `+`
+//! field (or `?` if the item has an +ASK form) and will describe
+//! those items in more detail if you ask for their attributes:
+//! +INFO, +ADMIN, +ABSTRACT, +VIEWS, and so on.
+//!
+//! phetch represents Gopher+ requests using the RFC 4266 URL form,
+//! where the Gopher+ string follows two `%09` tabs:
+//!
+//!   gopher://host/0/file.txt%09%09!            (item attributes)
+//!   gopher://host/0/file.txt%09%09+text/plain  (alternate view)
+//!   gopher://host/0/form%09%09?                (fill out +ASK form)
+
+use super::{parse_url, read_error, Stream, Timeouts, Tls, Transport};
+use crate::{Error, Result};
+use std::io::{Read, Write};
+
+/// Suffix for URLs that request an item's attributes.
+const ATTRIBUTES: &str = "%09%09!";
+/// Suffix for URLs that point to an item with an +ASK form.
+const ASK: &str = "%09%09?";
+
+/// Longest Gopher+ header line we're willing to look for before
+/// deciding the response doesn't have one.
+const MAX_HEADER_LEN: usize = 32;
+
+/// A single attribute block, like `+ADMIN:` and the lines under it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Block {
+    /// Name of the block, without the `+` or `:`. Ex: "VIEWS"
+    pub name: String,
+    /// Anything after the `:` on the first line of the block.
+    pub value: String,
+    /// The rest of the lines in the block, leading space removed.
+    pub lines: Vec<String>,
+}
+
+/// All the attribute blocks the server told us about for an item.
+#[derive(Debug, Default)]
+pub struct Attributes {
+    /// Blocks in the order the server sent them.
+    pub blocks: Vec<Block>,
+}
+
+/// One of the alternate representations listed in +VIEWS.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ItemView {
+    /// MIME type, ex: "Text/plain"
+    pub mime: String,
+    /// Language code, if any. Ex: "En_US"
+    pub lang: Option<String>,
+    /// Approximate size, if the server sent one. Ex: "12k"
+    pub size: Option<String>,
+}
+
+/// The kinds of questions an +ASK form can ask.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AskKind {
+    /// Single line of text.
+    Ask,
+    /// Password. Works like Ask.
+    AskP,
+    /// Multiple lines of text. We only take one.
+    AskL,
+    /// Pick one of a list of choices.
+    Choose,
+    /// Yes or no checkbox.
+    Select,
+}
+
+/// A single question in an +ASK form.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Question {
+    /// What kind of question is it?
+    pub kind: AskKind,
+    /// The text of the question.
+    pub prompt: String,
+    /// Default answer, or list of choices for `Choose`.
+    pub defaults: Vec<String>,
+}
+
+impl Attributes {
+    /// Parse the response to a `!` attribute request.
+    pub fn parse(raw: &str) -> Attributes {
+        let mut blocks: Vec<Block> = vec![];
+        for line in raw.lines() {
+            let line = line.trim_end_matches('\r');
+            if line == "." {
+                break;
+            }
+            if is_header(line) {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix('+') {
+                let (name, value) = match rest.find(':') {
+                    Some(idx) => (&rest[..idx], rest[idx + 1..].trim()),
+                    None => (rest.trim(), ""),
+                };
+                blocks.push(Block {
+                    name: name.to_uppercase(),
+                    value: value.into(),
+                    lines: vec![],
+                });
+            } else if let Some(block) = blocks.last_mut() {
+                block
+                    .lines
+                    .push(line.strip_prefix(' ').unwrap_or(line).into());
+            }
+        }
+        Attributes { blocks }
+    }
+
+    /// Find a block by name, ex: "ABSTRACT"
+    pub fn get(&self, name: &str) -> Option<&Block> {
+        self.blocks.iter().find(|b| b.name == name)
+    }
+
+    /// Alternate views of this item, from +VIEWS.
+    pub fn views(&self) -> Vec<ItemView> {
+        let block = match self.get("VIEWS") {
+            Some(block) => block,
+            None => return vec![],
+        };
+        block
+            .lines
+            .iter()
+            .filter_map(|line| {
+                let (desc, size) = match line.find(':') {
+                    Some(idx) => (&line[..idx], line[idx + 1..].trim()),
+                    None => (line.as_ref(), ""),
+                };
+                let mut parts = desc.split_whitespace();
+                let mime = parts.next()?.to_string();
+                let lang = parts.next().map(String::from);
+                let size = size.trim_start_matches('<').trim_end_matches('>');
+                Some(ItemView {
+                    mime,
+                    lang,
+                    size: if size.is_empty() {
+                        None
+                    } else {
+                        Some(size.into())
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// Questions in this item's +ASK form, if it has one. Notes are
+    /// skipped since there's nothing to answer.
+    pub fn questions(&self) -> Vec<Question> {
+        let block = match self.get("ASK") {
+            Some(block) => block,
+            None => return vec![],
+        };
+        block
+            .lines
+            .iter()
+            .filter_map(|line| {
+                let idx = line.find(':')?;
+                let kind = match line[..idx].trim() {
+                    "Ask" => AskKind::Ask,
+                    "AskP" => AskKind::AskP,
+                    "AskL" => AskKind::AskL,
+                    "Choose" => AskKind::Choose,
+                    "Select" => AskKind::Select,
+                    _ => return None,
+                };
+                let mut parts = line[idx + 1..].trim_start().split('\t');
+                let mut prompt = parts.next().unwrap_or("").to_string();
+                let mut defaults: Vec<String> = parts.map(String::from).collect();
+                // Select: Question:1
+                if kind == AskKind::Select {
+                    if let Some(idx) = prompt.rfind(':') {
+                        defaults = vec![prompt[idx + 1..].to_string()];
+                        prompt.truncate(idx);
+                    }
+                }
+                Some(Question {
+                    kind,
+                    prompt,
+                    defaults,
+                })
+            })
+            .collect()
+    }
+
+    /// Render these attributes as a Gophermap for the item info
+    /// page of `url`. Views and +ASK forms become links.
+    pub fn to_menu(&self, url: &str) -> String {
+        let url = base_url(url);
+        let u = parse_url(url);
+        let mut out = format!("i{}\r\ni\r\n", url);
+
+        for block in &self.blocks {
+            match block.name.as_ref() {
+                "INFO" => {}
+                "VIEWS" => {
+                    out.push_str("iViews:\r\n");
+                    for view in self.views() {
+                        let mut label = view.mime.clone();
+                        if let Some(lang) = &view.lang {
+                            label.push(' ');
+                            label.push_str(lang);
+                        }
+                        let mut view_url = u.clone();
+                        view_url.plus = Some(format!("+{}", &label));
+                        if let Some(size) = &view.size {
+                            label.push_str(&format!(" ({})", size));
+                        }
+                        out.push_str(&format!(
+                            "{}{}\tURL:{}\t{}\t{}\r\n",
+                            u.typ, label, view_url, u.host, u.port
+                        ));
+                    }
+                    out.push_str("i\r\n");
+                }
+                "ASK" => {
+                    let mut ask_url = u.clone();
+                    ask_url.plus = Some("?".into());
+                    out.push_str(&format!(
+                        "{}Fill out form\tURL:{}\t{}\t{}\r\n",
+                        u.typ, ask_url, u.host, u.port
+                    ));
+                    for line in &block.lines {
+                        out.push_str(&format!("i  {}\r\n", line.replace('\t', " | ")));
+                    }
+                    out.push_str("i\r\n");
+                }
+                _ => {
+                    out.push_str(&format!("i{}:", capitalize(&block.name)));
+                    if !block.value.is_empty() {
+                        out.push(' ');
+                        out.push_str(&block.value);
+                    }
+                    out.push_str("\r\n");
+                    for line in &block.lines {
+                        out.push_str(&format!("i  {}\r\n", line.replace('\t', "    ")));
+                    }
+                    out.push_str("i\r\n");
+                }
+            }
+        }
+
+        out
+    }
+}
+
+/// "ABSTRACT" => "Abstract"
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_string() + &chars.as_str().to_lowercase(),
+        None => String::new(),
+    }
+}
+
+/// URL to fetch the attributes of a Gopher+ item.
+pub fn attributes_url(url: &str) -> String {
+    format!("{}{}", base_url(url), ATTRIBUTES)
+}
+
+/// URL for an item that has an +ASK form.
+pub fn ask_url(url: &str) -> String {
+    format!("{}{}", base_url(url), ASK)
+}
+
+/// Is this a request for an item's attributes?
+pub fn is_attributes_url(url: &str) -> bool {
+    url.ends_with(ATTRIBUTES)
+}
+
+/// Is this an item with an +ASK form that needs filling out?
+pub fn is_ask_url(url: &str) -> bool {
+    url.ends_with(ASK)
+}
+
+/// Strip any Gopher+ string from a URL, leaving the plain Gopher
+/// URL behind.
+pub fn base_url(url: &str) -> &str {
+    match url.find("%09%09") {
+        Some(idx) => &url[..idx],
+        None => url,
+    }
+}
+
+/// Turn a selector with tabs into what we send over the wire, and
+/// whether it's a Gopher+ request. `sel\t\t+` becomes `sel\t+` since
+/// the empty search field only exists in the URL form.
+pub fn wire_selector(selector: &str) -> (String, bool) {
+    let parts: Vec<&str> = selector.splitn(3, '\t').collect();
+    if parts.len() == 3 && matches!(parts[2].chars().next(), Some('+') | Some('!') | Some('$')) {
+        if parts[1].is_empty() {
+            (format!("{}\t{}", parts[0], parts[2]), true)
+        } else {
+            (selector.to_string(), true)
+        }
+    } else {
+        (selector.to_string(), false)
+    }
+}
+
+/// Is this line a Gopher+ response header? They look like `+-1`
+/// (ends with a `.`), `+-2` (ends when the connection closes), or
+/// `+1234` (the size in bytes). Errors start with `-` instead of `+`.
+fn is_header(line: &str) -> bool {
+    let rest = match line.chars().next() {
+        Some('+') | Some('-') => line[1..].trim_end(),
+        _ => return false,
+    };
+    rest == "-1" || rest == "-2" || (!rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Read and remove the Gopher+ header from the start of a response.
+/// If the server sent an error instead, returns it as an `Err`.
+/// Responses without a header are left untouched.
+pub(crate) fn read_header(stream: &mut Stream, addr: &str, timeouts: Timeouts) -> Result<()> {
+    let mut line = vec![];
+    let mut byte = [0; 1];
+    while line.len() < MAX_HEADER_LEN
+        && stream
+            .io
+            .read(&mut byte)
+            .map_err(|e| read_error(e, addr, timeouts))?
+            == 1
+    {
+        line.push(byte[0]);
+        if byte[0] == b'\n' {
+            break;
+        }
+    }
+
+    let header = String::from_utf8_lossy(&line).to_string();
+    if !is_header(&header) {
+        stream.head = line;
+        return Ok(());
+    }
+
+    if header.starts_with('-') {
+        let mut body = String::new();
+        stream
+            .read_to_string(&mut body)
+            .map_err(|e| read_error(e, addr, timeouts))?;
+        let msg = body
+            .lines()
+            .map(|l| l.trim_end_matches('\r'))
+            .find(|l| !l.is_empty() && *l != ".")
+            .unwrap_or("Unknown error");
+        return Err(Error::Server(format!("Gopher+ error: {}", msg)));
+    }
+
+    Ok(())
+}
+
+/// Submit the answers to an item's +ASK form. Returns a tuple of:
+///   (did tls work?, raw Gopher response)
+pub fn submit(
+    url: &str,
+    answers: &[String],
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+) -> Result<(bool, Vec<u8>)> {
+    let u = parse_url(base_url(url));
+    let addr = u.addr();
+    let mut stream = super::connect(&u.host, &u.port, tls, transport, timeouts)?;
+    write!(stream, "{}\t+\t1\r\n+-1\r\n", u.sel)?;
+    for answer in answers {
+        write!(stream, "{}\r\n", answer)?;
+    }
+    stream.write_all(b".\r\n")?;
+    read_header(&mut stream, &addr, timeouts)?;
+
+    let mut body = Vec::new();
+    stream
+        .read_to_end(&mut body)
+        .map_err(|e| read_error(e, &addr, timeouts))?;
+    Ok((stream.is_tls(), body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gopher::Memory;
+
+    const ATTRS: &str = "+-1\r
++INFO: 0About this server\t/about\tgopher.example.com\t70\t+\r
++ADMIN:\r
+ Admin: Joe Gopher <joe@example.com>\r
+ Mod-Date: Wed Jul 28 17:02:01 1993 <19930728170201>\r
++VIEWS:\r
+ Text/plain: <1k>\r
+ Text/plain De_DE: <2k>\r
+ application/postscript\r
++ABSTRACT:\r
+ This is a short description\r
+ of the item.\r
+.\r
+";
+
+    #[test]
+    fn test_parse_attributes() {
+        let attrs = Attributes::parse(ATTRS);
+        assert_eq!(attrs.blocks.len(), 4);
+        assert_eq!(
+            attrs.get("INFO").unwrap().value,
+            "0About this server\t/about\tgopher.example.com\t70\t+"
+        );
+        let admin = attrs.get("ADMIN").unwrap();
+        assert_eq!(admin.value, "");
+        assert_eq!(admin.lines[0], "Admin: Joe Gopher <joe@example.com>");
+        assert_eq!(
+            attrs.get("ABSTRACT").unwrap().lines,
+            vec!["This is a short description", "of the item."]
+        );
+    }
+
+    #[test]
+    fn test_views() {
+        let views = Attributes::parse(ATTRS).views();
+        assert_eq!(views.len(), 3);
+        assert_eq!(
+            views[0],
+            ItemView {
+                mime: "Text/plain".into(),
+                lang: None,
+                size: Some("1k".into())
+            }
+        );
+        assert_eq!(views[1].lang, Some("De_DE".into()));
+        assert_eq!(views[2].mime, "application/postscript");
+        assert_eq!(views[2].size, None);
+    }
+
+    #[test]
+    fn test_questions() {
+        let attrs = Attributes::parse(
+            "+ASK:\r
+ Ask: What is your name?\tGopher\r
+ Note: This is just a note.\r
+ AskP: Password:\r
+ Choose: Favorite color?\tred\tgreen\tblue\r
+ Select: Subscribe?:1\r
+",
+        );
+        let qs = attrs.questions();
+        assert_eq!(qs.len(), 4);
+        assert_eq!(qs[0].kind, AskKind::Ask);
+        assert_eq!(qs[0].prompt, "What is your name?");
+        assert_eq!(qs[0].defaults, vec!["Gopher"]);
+        assert_eq!(qs[1].kind, AskKind::AskP);
+        assert_eq!(qs[2].kind, AskKind::Choose);
+        assert_eq!(qs[2].defaults, vec!["red", "green", "blue"]);
+        assert_eq!(qs[3].kind, AskKind::Select);
+        assert_eq!(qs[3].prompt, "Subscribe?");
+        assert_eq!(qs[3].defaults, vec!["1"]);
+    }
+
+    #[test]
+    fn test_urls() {
+        let url = "gopher://example.com/0/file.txt";
+        assert_eq!(
+            attributes_url(url),
+            "gopher://example.com/0/file.txt%09%09!"
+        );
+        assert_eq!(ask_url(url), "gopher://example.com/0/file.txt%09%09?");
+        assert!(is_attributes_url(&attributes_url(url)));
+        assert!(is_ask_url(&ask_url(url)));
+        assert_eq!(base_url(&ask_url(url)), url);
+        assert_eq!(base_url(url), url);
+    }
+
+    #[test]
+    fn test_wire_selector() {
+        assert_eq!(wire_selector("/file"), ("/file".into(), false));
+        assert_eq!(
+            wire_selector("/search\tquery"),
+            ("/search\tquery".into(), false)
+        );
+        assert_eq!(wire_selector("/file\t\t!"), ("/file\t!".into(), true));
+        assert_eq!(
+            wire_selector("/file\t\t+text/plain"),
+            ("/file\t+text/plain".into(), true)
+        );
+        assert_eq!(wire_selector("/s\tq\t+"), ("/s\tq\t+".into(), true));
+    }
+
+    #[test]
+    fn test_submit_error() {
+        let net = Memory::new().handler("example.com:70", |_| {
+            b"--1\r\nNo such form\r\n.\r\n".to_vec()
+        });
+        let url = "gopher://example.com/1/form";
+        let err = submit(url, &[], Tls::Off, &net, Timeouts::default()).unwrap_err();
+        assert!(matches!(err, Error::Server(_)));
+        assert_eq!(err.to_string(), "Gopher+ error: No such form");
+    }
+
+    #[test]
+    fn test_is_header() {
+        assert!(is_header("+-1\r\n"));
+        assert!(is_header("+-2\r\n"));
+        assert!(is_header("+12345\r\n"));
+        assert!(is_header("--1\r\n"));
+        assert!(!is_header("+INFO: 1Hi\t/\thost\t70\t+\r\n"));
+        assert!(!is_header("iHello\r\n"));
+        assert!(!is_header("+\r\n"));
+    }
+
+    #[test]
+    fn test_to_menu() {
+        let menu = Attributes::parse(ATTRS).to_menu("gopher://example.com/0/about%09%09!");
+        assert!(menu.starts_with("igopher://example.com/0/about\r\n"));
+        assert!(menu.contains("iAdmin:\r\ni  Admin: Joe Gopher <joe@example.com>\r\n"));
+        assert!(menu.contains(
+            "0Text/plain (1k)\tURL:gopher://example.com/0/about%09%09+Text/plain\texample.com\t70\r\n"
+        ));
+        assert!(menu.contains(
+            "0Text/plain De_DE (2k)\tURL:gopher://example.com/0/about%09%09+Text/plain%20De_DE\texample.com\t70\r\n"
+        ));
+        assert!(menu.contains("iAbstract:\r\ni  This is a short description\r\n"));
+        assert!(!menu.contains("INFO"));
+    }
+}
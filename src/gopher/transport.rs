@@ -0,0 +1,228 @@
+//! A `Transport` is how phetch reaches a server: it hands back a raw
+//! byte stream to `host:port`, and everything above it (TLS, Gopher+,
+//! Gemini) is built on top of that stream.
+//!
+//! phetch normally uses `Network`, which connects directly, over Tor,
+//! or through a proxy. `Memory` is a pretend network of in-memory
+//! servers, so fetching and the UI can be tested without sockets.
+//! Set `Config.transport` to use a different one.
+
+use super::{socket_addr, tcp_connect, Timeouts};
+use crate::{proxy::Proxy, Error, Result};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Cursor, Read, Write},
+    sync::Arc,
+};
+
+#[cfg(feature = "tor")]
+use std::net::ToSocketAddrs;
+#[cfg(feature = "tor")]
+use tor_stream::TorStream;
+
+/// A byte stream to a server.
+pub trait ReadWrite: Read + Write + Send {}
+impl<T: Read + Write + Send> ReadWrite for T {}
+
+/// Opens connections to servers.
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Open a byte stream to `host:port`.
+    fn open(&self, host: &str, port: &str, timeouts: Timeouts) -> Result<Box<dyn ReadWrite>>;
+}
+
+/// The real network: plain TCP, Tor, or a SOCKS5/HTTP proxy.
+#[derive(Debug, Clone, Default)]
+pub struct Network {
+    /// Connect through the Tor proxy in `$TOR_PROXY`, or
+    /// 127.0.0.1:9050.
+    pub tor: bool,
+    /// Connect through this proxy.
+    pub proxy: Option<Proxy>,
+}
+
+impl Transport for Network {
+    fn open(&self, host: &str, port: &str, timeouts: Timeouts) -> Result<Box<dyn ReadWrite>> {
+        if self.tor {
+            #[cfg(feature = "tor")]
+            {
+                let addr = format!("{}:{}", host, port);
+                let stream = std::env::var("TOR_PROXY")
+                    .unwrap_or_else(|_| "127.0.0.1:9050".into())
+                    .to_socket_addrs()?
+                    .find_map(|s| TorStream::connect_with_address(s, addr.as_ref()).ok())
+                    .ok_or_else(|| Error::Tor("Can't connect to Tor proxy".into()))?;
+                return Ok(Box::new(stream));
+            }
+            #[cfg(not(feature = "tor"))]
+            return Err(Error::Tor("phetch was compiled without Tor support".into()));
+        }
+
+        Ok(match &self.proxy {
+            Some(proxy) => Box::new(proxy.connect(host, port, timeouts)?),
+            None => Box::new(tcp_connect(&socket_addr(host, port), timeouts)?),
+        })
+    }
+}
+
+/// Builds a server's response from the request we sent it, minus the
+/// trailing CRLF.
+type Handler = Arc<dyn Fn(&str) -> Vec<u8> + Send + Sync>;
+
+/// In-memory servers, for tests. Pages are looked up by `host:port`
+/// and the selector line of the request:
+///
+/// ```
+/// use phetch::gopher::Memory;
+///
+/// let net = Memory::new()
+///     .page("example.com:70", "", "1Docs\t/docs\texample.com\t70\r\n")
+///     .page("example.com:70", "/docs", "iNothing here yet\r\n");
+/// ```
+///
+/// Requests for selectors that don't exist get a Gopher error menu.
+/// Hosts that don't exist refuse the connection.
+#[derive(Clone, Default)]
+pub struct Memory {
+    pages: HashMap<String, HashMap<String, Vec<u8>>>,
+    handlers: HashMap<String, Handler>,
+}
+
+impl Memory {
+    /// An empty network.
+    pub fn new() -> Memory {
+        Memory::default()
+    }
+
+    /// Serve `body` for `selector` on `addr` ("host:port").
+    pub fn page(mut self, addr: &str, selector: &str, body: impl Into<Vec<u8>>) -> Memory {
+        self.pages
+            .entry(addr.into())
+            .or_default()
+            .insert(selector.into(), body.into());
+        self
+    }
+
+    /// Answer every request to `addr` with whatever `f` returns. Gets
+    /// the whole request, in case there's more than a selector.
+    pub fn handler(
+        mut self,
+        addr: &str,
+        f: impl Fn(&str) -> Vec<u8> + Send + Sync + 'static,
+    ) -> Memory {
+        self.handlers.insert(addr.into(), Arc::new(f));
+        self
+    }
+
+    /// Response to `request` from the server at `addr`.
+    fn respond(&self, addr: &str, request: &str) -> Vec<u8> {
+        if let Some(handler) = self.handlers.get(addr) {
+            return handler(request);
+        }
+        let selector = request.lines().next().unwrap_or("");
+        match self.pages.get(addr).and_then(|p| p.get(selector)) {
+            Some(body) => body.clone(),
+            None => format!("3Not found: {}\t\terror.host\t1\r\n", selector).into_bytes(),
+        }
+    }
+}
+
+impl fmt::Debug for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut addrs = self
+            .pages
+            .keys()
+            .chain(self.handlers.keys())
+            .collect::<Vec<_>>();
+        addrs.sort();
+        addrs.dedup();
+        f.debug_struct("Memory").field("servers", &addrs).finish()
+    }
+}
+
+impl Transport for Memory {
+    fn open(&self, host: &str, port: &str, _: Timeouts) -> Result<Box<dyn ReadWrite>> {
+        let addr = format!("{}:{}", host, port);
+        if !self.pages.contains_key(&addr) && !self.handlers.contains_key(&addr) {
+            return Err(Error::Network {
+                addr,
+                source: io::ErrorKind::ConnectionRefused.into(),
+            });
+        }
+        Ok(Box::new(MemoryStream {
+            server: self.clone(),
+            addr,
+            request: vec![],
+            response: None,
+        }))
+    }
+}
+
+/// Connection to a `Memory` server. Collects what we write until the
+/// first read, then answers it.
+struct MemoryStream {
+    server: Memory,
+    addr: String,
+    request: Vec<u8>,
+    response: Option<Cursor<Vec<u8>>>,
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.response.is_none() {
+            let request = String::from_utf8_lossy(&self.request);
+            let request = request.strip_suffix("\r\n").unwrap_or(&request);
+            self.response = Some(Cursor::new(self.server.respond(&self.addr, request)));
+        }
+        self.response.as_mut().unwrap().read(buf)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.response.is_some() {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        self.request.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory() {
+        let net = Memory::new()
+            .page("example.com:70", "/", "iHello\r\n")
+            .handler("echo.org:70", |req| req.as_bytes().to_vec());
+
+        let mut body = String::new();
+        let mut stream = net.open("example.com", "70", Timeouts::default()).unwrap();
+        stream.write_all(b"/\r\n").unwrap();
+        stream.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "iHello\r\n");
+
+        let mut body = String::new();
+        let mut stream = net.open("example.com", "70", Timeouts::default()).unwrap();
+        stream.write_all(b"/nope\r\n").unwrap();
+        stream.read_to_string(&mut body).unwrap();
+        assert!(body.starts_with("3Not found: /nope"));
+
+        let mut body = String::new();
+        let mut stream = net.open("echo.org", "70", Timeouts::default()).unwrap();
+        stream.write_all(b"/form\t+\t1\r\n+-1\r\n").unwrap();
+        stream.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "/form\t+\t1\r\n+-1");
+
+        let err = net
+            .open("example.com", "7070", Timeouts::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.is_retryable(), true);
+    }
+}
@@ -6,7 +6,7 @@ use std::fmt;
 pub enum Type {
     Text,       // 0 | cyan
     Menu,       // 1 | blue
-    CSOEntity,  // 2 | unsupported
+    CSOEntity,  // 2 | white
     Error,      // 3 | red
     Binhex,     // 4 | download
     DOSFile,    // 5 | download
@@ -80,10 +80,7 @@ impl Type {
 
     /// Is this a type phetch supports?
     pub fn is_supported(self) -> bool {
-        !matches!(
-            self,
-            Type::CSOEntity | Type::Mirror | Type::Telnet3270 | Type::Mailbox
-        )
+        !matches!(self, Type::Mirror | Type::Telnet3270)
     }
 
     /// Gopher Item Type to RFC char.
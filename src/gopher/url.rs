@@ -0,0 +1,559 @@
+//! Gopher URLs, as described in RFC 4266:
+//!
+//!   gopher://host:port/<type><selector>%09<search>%09<gopher+ string>
+//!
+//! The selector, search, and Gopher+ string are percent-encoded in the
+//! URL form and decoded in a `Url`, so what's in a `Url` is exactly
+//! what gets sent to the server. A `?` is just another character in a
+//! selector, not the start of a search.
+//!
+//! Hostnames are normalized: lowercased, with international (IDN)
+//! labels converted to Punycode. IPv6 addresses go in brackets and may
+//! have a zone ID, like `[fe80::1%25eth0]`.
+
+use super::Type;
+use std::fmt;
+
+/// Port we assume for Gopher and telnet URLs without one.
+const DEFAULT_PORT: &str = "70";
+
+/// Port we assume for finger URLs without one.
+const DEFAULT_FINGER_PORT: &str = "79";
+
+/// Gopher URL. Returned by `parse_url()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    /// Gopher Type
+    pub typ: Type,
+    /// Hostname
+    pub host: String,
+    /// Port. Defaults to 70
+    pub port: String,
+    /// Selector
+    pub sel: String,
+    /// Search query for Type 7 items, sent after a tab.
+    pub search: Option<String>,
+    /// Gopher+ string, ex: `+`, `!`, or `+text/plain`. Sent after the
+    /// search, or after two tabs if there isn't one.
+    pub plus: Option<String>,
+}
+
+impl Url {
+    /// Creates a new Gopher Url quickly from a tuple of Url fields.
+    pub fn new(typ: Type, host: &str, port: &str, sel: &str) -> Url {
+        Url {
+            typ,
+            host: host.into(),
+            port: port.into(),
+            sel: sel.into(),
+            search: None,
+            plus: None,
+        }
+    }
+
+    /// Url for a link in a Gophermap. The selector is opaque: it's
+    /// sent to the server exactly as the menu has it, `%` and all.
+    pub fn from_menu(typ: Type, host: &str, port: &str, sel: &str) -> Url {
+        Url::new(typ, &normalize_host(host), port, sel)
+    }
+
+    /// The line we send to the server to request this URL, minus the
+    /// trailing CRLF: the selector, then the search and Gopher+ string
+    /// separated by tabs.
+    pub fn selector_line(&self) -> String {
+        let mut line = self.sel.clone();
+        if self.search.is_some() || self.plus.is_some() {
+            line.push('\t');
+            line.push_str(self.search.as_deref().unwrap_or(""));
+        }
+        if let Some(plus) = &self.plus {
+            line.push('\t');
+            line.push_str(plus);
+        }
+        line
+    }
+
+    /// `host:port`, ready to connect to.
+    pub fn addr(&self) -> String {
+        socket_addr(&self.host, &self.port)
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (scheme, default_port) = match self.typ {
+            Type::HTML | Type::Error => return write!(f, "{}", self.sel),
+            Type::Telnet => ("telnet", DEFAULT_PORT),
+            _ if self.port == DEFAULT_FINGER_PORT && self.is_finger() => {
+                ("finger", DEFAULT_FINGER_PORT)
+            }
+            _ => ("gopher", DEFAULT_PORT),
+        };
+        write!(f, "{}://", scheme)?;
+        if scheme == "finger" && !self.sel.is_empty() {
+            write!(f, "{}@", encode(&self.sel, FINGER_USER))?;
+        }
+        if self.host.contains(':') {
+            write!(f, "[{}]", self.host.replace('%', "%25"))?;
+        } else {
+            write!(f, "{}", self.host)?;
+        }
+        if self.port != default_port {
+            write!(f, ":{}", self.port)?;
+        }
+
+        match scheme {
+            "finger" => Ok(()),
+            "telnet" => write!(f, "{}", encode(&self.sel, SELECTOR)),
+            _ => {
+                if self.typ == Type::Menu
+                    && self.sel.is_empty()
+                    && self.search.is_none()
+                    && self.plus.is_none()
+                {
+                    return Ok(());
+                }
+                write!(f, "/{}{}", self.typ, encode(&self.sel, SELECTOR))?;
+                if self.search.is_some() || self.plus.is_some() {
+                    let search = self.search.as_deref().unwrap_or("");
+                    write!(f, "%09{}", encode(search, SELECTOR))?;
+                }
+                if let Some(plus) = &self.plus {
+                    write!(f, "%09{}", encode(plus, PLUS))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Url {
+    /// Finger URLs are plain Text on the finger port. Gopher Text
+    /// selectors start with a `/` (or are empty), finger users don't.
+    fn is_finger(&self) -> bool {
+        self.typ == Type::Text
+            && !self.sel.starts_with('/')
+            && !self.sel.is_empty()
+            && self.search.is_none()
+            && self.plus.is_none()
+    }
+}
+
+/// Parses gopher URL into parts.
+pub fn parse_url(url: &str) -> Url {
+    let mut url = url.trim_start_matches("gopher://");
+    let mut typ = Type::Menu;
+    let mut host;
+    let mut port = DEFAULT_PORT;
+    let mut sel = "";
+
+    // simple URLs, ex: "dog.com"
+    if !url.contains(':') && !url.contains('/') {
+        return Url::new(Type::Menu, &normalize_host(url), DEFAULT_PORT, "");
+    }
+
+    // finger urls
+    if let Some(rest) = url.strip_prefix("finger://") {
+        return parse_finger_url(rest);
+    }
+
+    // telnet urls
+    if url.starts_with("telnet://") {
+        typ = Type::Telnet;
+        url = url.trim_start_matches("telnet://");
+    } else if url.contains("://") {
+        // non-gopher URLs, stick everything in selector
+        return Url::new(Type::HTML, "", "", url);
+    }
+
+    // check selector first
+    if let Some(idx) = url.find('/') {
+        host = &url[..idx];
+        sel = &url[idx..];
+    } else {
+        host = url;
+    }
+
+    // ipv6
+    if let Some(rest) = host.strip_prefix('[') {
+        if let Some(end) = rest.find(']') {
+            if let Some(p) = rest[end + 1..].strip_prefix(':') {
+                port = p;
+            }
+            host = &rest[..end];
+        } else {
+            return Url::new(Type::Error, "Unclosed ipv6 bracket", "", url);
+        }
+    } else if let Some(idx) = host.find(':') {
+        // two :'s == probably ipv6
+        if host.len() > idx + 1 && !host[idx + 1..].contains(':') {
+            // regular hostname w/ port -- grab port
+            port = &host[idx + 1..];
+            host = &host[..idx];
+        }
+    }
+
+    // search and gopher+ string, ex: /7/search%09query%09+
+    let mut parts = sel.splitn(3, "%09");
+    let mut sel = parts.next().unwrap_or("");
+    let search = parts.next().map(decode);
+    let plus = parts.next().map(decode);
+
+    // ignore type prefix on selector
+    if typ != Type::Telnet {
+        let mut chars = sel.chars();
+        if let (Some('/'), Some(c)) = (chars.next(), chars.next()) {
+            if let Some(t) = Type::from(c) {
+                typ = t;
+                sel = &sel[1 + c.len_utf8()..];
+            }
+        }
+    }
+
+    Url {
+        typ,
+        host: normalize_host(&decode(host)),
+        port: port.into(),
+        sel: decode(sel),
+        // an empty search only exists to make room for a gopher+ string
+        search: search.filter(|s| !(s.is_empty() && plus.is_some())),
+        plus,
+    }
+}
+
+/// Parses a finger URL, minus the `finger://`. Both `user@host` and
+/// `host/user` forms are understood. The query to send to the server
+/// ends up in the selector, since that's all finger is: a Gopher
+/// request without the menus.
+fn parse_finger_url(url: &str) -> Url {
+    let (mut host, mut user) = match url.find('/') {
+        Some(idx) => (&url[..idx], &url[idx + 1..]),
+        None => (url, ""),
+    };
+    let mut port = DEFAULT_FINGER_PORT;
+
+    // forwarding, ex: user@host1@host2
+    if let Some(idx) = host.rfind('@') {
+        user = &host[..idx];
+        host = &host[idx + 1..];
+    }
+
+    if let Some(rest) = host.strip_prefix('[') {
+        if let Some(end) = rest.find(']') {
+            if let Some(p) = rest[end + 1..].strip_prefix(':') {
+                port = p;
+            }
+            host = &rest[..end];
+        } else {
+            return Url::new(Type::Error, "Unclosed ipv6 bracket", "", url);
+        }
+    } else if let Some(idx) = host.find(':') {
+        if !host[idx + 1..].contains(':') {
+            port = &host[idx + 1..];
+            host = &host[..idx];
+        }
+    }
+
+    Url::new(
+        Type::Text,
+        &normalize_host(&decode(host)),
+        port,
+        &decode(user),
+    )
+}
+
+/// Add a search query to a Type 7 URL.
+pub fn search_url(url: &str, query: &str) -> String {
+    let mut u = parse_url(url);
+    u.search = Some(query.into());
+    u.to_string()
+}
+
+/// `host:port` for connecting to, with IPv6 addresses in brackets.
+/// Zone IDs like `eth0` become interface numbers, since that's all the
+/// standard library understands.
+pub fn socket_addr(host: &str, port: &str) -> String {
+    if !host.contains(':') {
+        return format!("{}:{}", host, port);
+    }
+    match host.split_once('%') {
+        Some((ip, zone)) if !zone.chars().all(|c| c.is_ascii_digit()) => {
+            let index = std::ffi::CString::new(zone)
+                .map(|name| unsafe { libc::if_nametoindex(name.as_ptr()) })
+                .unwrap_or(0);
+            format!("[{}%{}]:{}", ip, index, port)
+        }
+        _ => format!("[{}]:{}", host, port),
+    }
+}
+
+/// Characters we leave alone in selectors and search queries: RFC
+/// 3986's unreserved characters, sub-delims, `:`, `@`, and `/`.
+const SELECTOR: &str = "-._~!$&'()*+,;=:@/";
+
+/// Gopher+ strings can also have a bare `?`, which means "+ASK".
+const PLUS: &str = "-._~!$&'()*+,;=:@/?";
+
+/// The finger user goes before the `@`, so it can't have a `/`.
+const FINGER_USER: &str = "-._~!$&'()*+,;=:@";
+
+/// Percent-encode everything in `s` except ASCII letters, numbers, and
+/// the characters in `safe`.
+fn encode(s: &str, safe: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || safe.as_bytes().contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Decode `%XX` escapes. Anything that isn't a valid escape is left
+/// as-is.
+pub(crate) fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(b) = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into()
+}
+
+/// Lowercase a hostname and convert any international labels to
+/// Punycode, ex: `Bücher.example` => `xn--bcher-kva.example`. IPv6
+/// zone IDs are left alone.
+fn normalize_host(host: &str) -> String {
+    if host.contains(':') {
+        return match host.split_once('%') {
+            Some((ip, zone)) => format!("{}%{}", ip.to_lowercase(), zone),
+            None => host.to_lowercase(),
+        };
+    }
+    host.to_lowercase()
+        .split('.')
+        .map(|label| {
+            if label.is_ascii() {
+                label.to_string()
+            } else {
+                punycode(label)
+                    .map(|p| format!("xn--{}", p))
+                    .unwrap_or_else(|| label.to_string())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Punycode, from RFC 3492. Returns None on overflow.
+fn punycode(input: &str) -> Option<String> {
+    const BASE: u32 = 36;
+    const TMIN: u32 = 1;
+    const TMAX: u32 = 26;
+
+    let chars: Vec<u32> = input.chars().map(|c| c as u32).collect();
+    let mut out: String = input.chars().filter(char::is_ascii).collect();
+    let basic = out.len() as u32;
+    if basic > 0 {
+        out.push('-');
+    }
+
+    let digit = |d: u32| -> char {
+        if d < 26 {
+            (b'a' + d as u8) as char
+        } else {
+            (b'0' + (d - 26) as u8) as char
+        }
+    };
+
+    let (mut n, mut delta, mut bias, mut handled) = (128, 0u32, 72, basic);
+    while (handled as usize) < chars.len() {
+        let m = *chars.iter().filter(|&&c| c >= n).min()?;
+        delta = delta.checked_add((m - n).checked_mul(handled + 1)?)?;
+        n = m;
+        for &c in &chars {
+            if c < n {
+                delta = delta.checked_add(1)?;
+            }
+            if c == n {
+                let mut q = delta;
+                let mut k = BASE;
+                loop {
+                    let t = if k <= bias {
+                        TMIN
+                    } else if k >= bias + TMAX {
+                        TMAX
+                    } else {
+                        k - bias
+                    };
+                    if q < t {
+                        break;
+                    }
+                    out.push(digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+                out.push(digit(q));
+                bias = adapt(delta, handled + 1, handled == basic);
+                delta = 0;
+                handled += 1;
+            }
+        }
+        delta += 1;
+        n += 1;
+    }
+    Some(out)
+}
+
+/// Punycode's bias adaptation function.
+fn adapt(delta: u32, points: u32, first: bool) -> u32 {
+    const BASE: u32 = 36;
+    const TMIN: u32 = 1;
+    const TMAX: u32 = 26;
+    const SKEW: u32 = 38;
+    const DAMP: u32 = 700;
+
+    let mut delta = if first { delta / DAMP } else { delta / 2 };
+    delta += delta / points;
+    let mut k = 0;
+    while delta > ((BASE - TMIN) * TMAX) / 2 {
+        delta /= BASE - TMIN;
+        k += BASE;
+    }
+    k + (BASE - TMIN + 1) * delta / (delta + SKEW)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        // xorshift, so failures are reproducible
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut rand = move |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as usize
+        };
+        let chars: Vec<char> = "aZ09/-._~!$&'()*+,;=:@?#%[] \t\r\né例".chars().collect();
+        let label_chars: Vec<char> = "abcxyz0189-BüÉ例".chars().collect();
+        let types = [
+            Type::Text,
+            Type::Menu,
+            Type::Search,
+            Type::Binary,
+            Type::Xml,
+        ];
+        let string = |rand: &mut dyn FnMut(usize) -> usize, chars: &[char], max: usize| {
+            let len = rand(max);
+            (0..len)
+                .map(|_| chars[rand(chars.len())])
+                .collect::<String>()
+        };
+        let host = |rand: &mut dyn FnMut(usize) -> usize| {
+            if rand(4) == 0 {
+                let zone = ["", "%eth0", "%2"][rand(3)];
+                return format!("fe80::{:x}{}", rand(0xffff), zone);
+            }
+            let labels = (0..rand(3) + 1)
+                .map(|_| format!("{}{}", label_chars[rand(6)], string(rand, &label_chars, 8)))
+                .collect::<Vec<_>>();
+            normalize_host(&labels.join("."))
+        };
+
+        for _ in 0..1000 {
+            let port = match rand(3) {
+                0 => DEFAULT_PORT.into(),
+                1 => DEFAULT_FINGER_PORT.into(),
+                _ => (rand(65535) + 1).to_string(),
+            };
+            let mut u = Url::new(
+                types[rand(types.len())],
+                &host(&mut rand),
+                &port,
+                &string(&mut rand, &chars, 12),
+            );
+            if rand(2) == 0 {
+                u.search = Some(string(&mut rand, &chars, 12));
+            }
+            if rand(2) == 0 {
+                u.plus = Some(format!("+{}", string(&mut rand, &chars, 12)));
+                if u.search.as_deref() == Some("") {
+                    u.search = None;
+                }
+            }
+            let parsed = parse_url(&u.to_string());
+            let tabbed = u.sel.contains('\t') || u.search.iter().any(|s| s.contains('\t'));
+            if tabbed {
+                // a tab is where the selector or search ends, so it
+                // can move fields around, but the request is the same
+                assert_eq!(parsed.selector_line(), u.selector_line(), "{}", u);
+                assert_eq!((parsed.typ, parsed.addr()), (u.typ, u.addr()), "{}", u);
+            } else {
+                assert_eq!(parsed, u, "{}", u);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(encode("/a b?c%d", SELECTOR), "/a%20b%3Fc%25d");
+        assert_eq!(encode("ü", SELECTOR), "%C3%BC");
+        assert_eq!(decode("/a%20b%3fc%25d"), "/a b?c%d");
+        assert_eq!(decode("%C3%BC"), "ü");
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz%4"), "%zz%4");
+    }
+
+    #[test]
+    fn test_punycode() {
+        assert_eq!(punycode("bücher").unwrap(), "bcher-kva");
+        assert_eq!(punycode("münchen").unwrap(), "mnchen-3ya");
+        assert_eq!(punycode("例え").unwrap(), "r8jz45g");
+        assert_eq!(normalize_host("Bücher.Example"), "xn--bcher-kva.example");
+        assert_eq!(normalize_host("FE80::1%eth0"), "fe80::1%eth0");
+    }
+
+    #[test]
+    fn test_socket_addr() {
+        assert_eq!(socket_addr("phkt.io", "70"), "phkt.io:70");
+        assert_eq!(socket_addr("::1", "70"), "[::1]:70");
+        assert_eq!(socket_addr("fe80::1%2", "70"), "[fe80::1%2]:70");
+        assert_eq!(socket_addr("fe80::1%lo", "70"), "[fe80::1%1]:70");
+    }
+
+    #[test]
+    fn test_selector_line() {
+        let mut u = Url::new(Type::Search, "phkt.io", "70", "/search");
+        assert_eq!(u.selector_line(), "/search");
+        u.search = Some("gopher holes".into());
+        assert_eq!(u.selector_line(), "/search\tgopher holes");
+        u.search = None;
+        u.plus = Some("!".into());
+        assert_eq!(u.selector_line(), "/search\t\t!");
+    }
+
+    #[test]
+    fn test_search_url() {
+        assert_eq!(
+            search_url("gopher://phkt.io/7/search", "what? 100%"),
+            "gopher://phkt.io/7/search%09what%3F%20100%25"
+        );
+    }
+}
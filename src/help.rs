@@ -1,7 +1,7 @@
 //! The `help` module manages all internal Gopher pages, from the help
 //! system itself to the Start and "About Phetch" pages.
 
-use crate::{bookmarks, history};
+use crate::{bookmarks, history, known_hosts};
 
 /// Find a help file/page. If found, gives the raw Gophermap.
 pub fn lookup(name: &str) -> Option<String> {
@@ -9,6 +9,7 @@ pub fn lookup(name: &str) -> Option<String> {
         "" | "/" | "home" | "home/" => format!("{}{}", HEADER, START),
         "history" => history::as_raw_menu(),
         "bookmarks" => bookmarks::as_raw_menu(),
+        "certs" => known_hosts::as_raw_menu(),
         "help/config" => format!("{}{}", HEADER, CONFIG),
         "help/themes" => format!("{}{}", HEADER, THEMES),
         "help/keys" => format!("{}{}", HEADER, KEYS),
@@ -84,6 +85,8 @@ i
 1gopher types	/help/types	phetch
 1bookmarks	/help/bookmarks	phetch
 1history	/help/history	phetch
+1downloads	/downloads	phetch
+1tls certificates	/certs	phetch
 1phetch.conf	/help/config	phetch
 1themes	/help/themes	phetch
 i
@@ -91,7 +94,7 @@ i            ~ * ~
 i
 1start screen	/home	phetch
 1about phetch	/about	phetch
-1check for updates	/phetch/latest?{platform}|v{version}	phkt.io
+1check for updates	URL:gopher://phkt.io/1/phetch/latest%09{platform}|v{version}	phkt.io
 i
 ";
 
@@ -114,6 +117,11 @@ if or /     find link in page
 ip or k     select prev link
 in or j     select next link
 i
+iin text documents:
+i/ or ?     search down/up
+in or N     next/prev match
+ictrl-r     regex search (while typing)
+i
 ig          go to gopher url
 iu          edit url
 iy          copy url
@@ -126,6 +134,9 @@ ir          view raw source
 id          download raw source
 iw          toggle wide mode
 ie          toggle encoding
+ix          toggle xml formatting
+il          show gophermap problems
+i=          gopher+ item info
 iq          quit phetch
 ih          show help
 i
@@ -230,18 +241,32 @@ i
 i# page to load when launched
 istart gopher://phetch/1/home
 i
-i# always use TLS mode
+i# always use TLS mode. set to
+i# `strict` to never fall back
+i# to plain text
 itls no
 i
 i# connect over tor proxy
 itor no
 i
+i# connect through a socks5:// or
+i# http:// proxy
+iproxy socks5://127.0.0.1:1080
+i
 i# start in wide mode
 iwide no
 i
 i# show emoji status indicators
 iemoji no
 i
+i# where to save downloads.
+i# default: current directory
+idownloads ~/Downloads
+i
+i# save what's inside uuencoded
+i# and binhex files when downloading
+idecode yes
+i
 i# cp437 or utf8 encoding
 iencoding utf8
 i
@@ -252,6 +277,29 @@ i# page up/down by N lines.
 i# 0 = full screen
 iscroll 0
 i
+i# seconds to wait when connecting
+i# to a server. 0 = forever
+itimeout 8
+i
+i# seconds to wait for a server
+i# to respond. 0 = forever
+iread-timeout 8
+i
+i# retry timed out requests N times
+iretries 0
+i
+i# keep a copy of pages you visit
+i# in ~/.config/phetch/cache/
+icache no
+i
+i# seconds until a cached page is
+i# stale. 0 = never
+icache-ttl 3600
+i
+i# max cache size in megabytes.
+i# 0 = no limit
+icache-size 50
+i
 i# path to theme file, if any
 itheme ~/.config/phetch/fun.theme
 ";
@@ -281,6 +329,7 @@ iui.cursor white bold
 iui.number magenta
 iui.menu yellow
 iui.text white
+iui.match yellowbg black
 iitem.text cyan
 iitem.menu blue
 iitem.error red
@@ -290,6 +339,12 @@ iitem.external green
 iitem.download white underline
 iitem.media green underline
 iitem.unsupported whitebg red
+ixml.tag blue
+ixml.attr cyan
+ixml.value green
+ixml.text white
+ixml.comment grey
+igemtext.quote grey
 i
 i     ** valid colors **
 i
@@ -330,7 +385,11 @@ i
 1menu items	/lawn/ascii	bitreich.org
 3errors	/help/types	phetch
 7search servers	/	forthworks.com	7001
+2cso phone books	/help/types	phetch
 8telnet links	/help/types	phetch
+Mmailboxes	/help/types	phetch
+ccalendars	/help/types	phetch
+xxml documents	/help/types	phetch
 hexternal urls	URL:https://en.wikipedia.org/wiki/Phetch	phetch
 i
 ithese download types:
@@ -343,15 +402,29 @@ gGIFs	/help/types	phetch
 Iimages downloads	/help/types	phetch
 ddocuments	/help/types	phetch
 i
+ibinhex and uuencoded files show
+iwhat's inside before you save
+ithem, and are decoded as they're
+idownloaded.
+i
+idownloads run in the background
+iwhile you keep browsing. see
+ihow they're doing, or cancel and
+iretry them, on the downloads page:
+i
+1downloads	/downloads	phetch
+i
 iand these media types:
 i
 ssound files	URL:https://freepd.com/music/Wakka%20Wakka.mp3	phetch
 ;video files	URL:https://www.youtube.com/watch?v=oHg5SJYRHA0	phetch
 i
+imirrors (+) are shown under the
+iitem they mirror, and are tried
+iin order if it can't be reached.
+i
 iphetch does not support:
 i
-2CSO Entries 	/help/types	phetch
-+Mirrors	/help/types	phetch
 TTelnet3270	/help/types	phetch
 i
 ";
@@ -2,8 +2,8 @@
 //! `HISTORY_FILE` in `~/.config/phetch/` exists. This file must be
 //! manually created by the user for history tracking to engage.
 
-use crate::phetchdir;
-use std::io::{BufRead, Result};
+use crate::{phetchdir, Result};
+use std::io::BufRead;
 
 /// History only works if you've created ~/.config/phetch/history.gph manually.
 const HISTORY_FILE: &str = "history.gph";
@@ -0,0 +1,236 @@
+//! phetch remembers the TLS certificate each host showed us the first
+//! time we connected to it ("trust on first use") and complains if it
+//! ever changes. Fingerprints are stored in `KNOWN_HOSTS_FILE` in the
+//! phetchdir if it exists, otherwise they're only kept in memory for
+//! the current session.
+//!
+//! Pinned hosts can be reviewed and revoked at `gopher://phetch/1/certs`.
+
+use crate::{phetchdir, Result};
+use std::{collections::HashMap, error, fmt, io::Read, sync::RwLock};
+
+/// Fingerprints are saved here, one `host:port fingerprint` per line.
+const KNOWN_HOSTS_FILE: &str = "known_hosts";
+
+lazy_static! {
+    /// Hosts we know about, loaded from disk on first use.
+    static ref HOSTS: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+}
+
+/// What we know about a host's certificate.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Trust {
+    /// Never seen this host before.
+    New,
+    /// Same certificate as last time.
+    Trusted,
+    /// Different certificate than last time. Contains the old
+    /// fingerprint.
+    Changed(String),
+}
+
+/// The error you get when a host's certificate doesn't match the one
+/// we pinned, wrapped in `Error::CertChanged`. The UI catches it and
+/// asks the user whether to trust the new certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertChanged {
+    /// `host:port` of the server.
+    pub host: String,
+    /// Fingerprint we pinned.
+    pub old: String,
+    /// Fingerprint the server just showed us.
+    pub new: String,
+}
+
+impl fmt::Display for CertChanged {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Certificate for {} has changed! Was {}, now {}",
+            self.host, self.old, self.new
+        )
+    }
+}
+
+impl error::Error for CertChanged {}
+
+/// SHA-256 fingerprint of a DER encoded certificate, as hex.
+#[cfg(feature = "tls")]
+pub fn fingerprint(der: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(der)
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Check a DER encoded certificate for `host:port`, pinning it if we've
+/// never seen the host before. Returns a `CertChanged` error if it
+/// doesn't match the pinned one.
+#[cfg(feature = "tls")]
+pub fn verify(host: &str, port: &str, der: &[u8]) -> Result<()> {
+    let key = format!("{}:{}", host, port);
+    let fingerprint = fingerprint(der);
+    match check(&key, &fingerprint) {
+        Trust::Trusted => Ok(()),
+        Trust::New => save(&key, &fingerprint),
+        Trust::Changed(old) => Err(crate::Error::CertChanged(CertChanged {
+            host: key,
+            old,
+            new: fingerprint,
+        })),
+    }
+}
+
+/// Check a host's certificate fingerprint against the one we saw
+/// last time, if any.
+pub fn check(host: &str, fingerprint: &str) -> Trust {
+    load();
+    match HOSTS.read().unwrap().as_ref().and_then(|h| h.get(host)) {
+        None => Trust::New,
+        Some(fp) if fp == fingerprint => Trust::Trusted,
+        Some(fp) => Trust::Changed(fp.clone()),
+    }
+}
+
+/// Have we pinned a certificate for this host? If so, it supports TLS.
+pub fn is_known(host: &str) -> bool {
+    load();
+    HOSTS
+        .read()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|hosts| hosts.contains_key(host))
+}
+
+/// Remember a host's certificate fingerprint, replacing any old one.
+pub fn save(host: &str, fingerprint: &str) -> Result<()> {
+    load();
+    let mut hosts = HOSTS.write().unwrap();
+    let hosts = hosts.get_or_insert_with(HashMap::new);
+    hosts.insert(host.into(), fingerprint.into());
+    write(hosts)
+}
+
+/// Forget a host's certificate. Returns whether we knew about it.
+pub fn revoke(host: &str) -> Result<bool> {
+    load();
+    let mut hosts = HOSTS.write().unwrap();
+    let hosts = hosts.get_or_insert_with(HashMap::new);
+    if hosts.remove(host).is_none() {
+        return Ok(false);
+    }
+    write(hosts)?;
+    Ok(true)
+}
+
+/// Returns all pinned hosts as a Gophermap, with links to revoke them.
+pub fn as_raw_menu() -> String {
+    load();
+    let hosts = HOSTS.read().unwrap();
+    let mut names = hosts.iter().flat_map(|h| h.keys()).collect::<Vec<_>>();
+    names.sort();
+
+    let mut out = vec![
+        format!("i{}{}:", phetchdir::DIR, KNOWN_HOSTS_FILE),
+        "i".to_string(),
+    ];
+    if phetchdir::path().is_err() {
+        out.push("iNot saved to disk: the phetch dir doesn't exist.".into());
+        out.push("i".into());
+    }
+    if names.is_empty() {
+        out.push("iNo pinned certificates yet.".into());
+    }
+    for name in names {
+        out.push(format!("i{}", name));
+        // SHA-256 fingerprints are too wide for most screens
+        let bytes = hosts.as_ref().unwrap()[name].split(':').collect::<Vec<_>>();
+        for half in bytes.chunks(bytes.len().div_ceil(2).max(1)) {
+            out.push(format!("i  {}", half.join(":")));
+        }
+        out.push(format!("1  revoke\t/certs/revoke/{}\tphetch", name));
+        out.push("i".into());
+    }
+    out.join("\r\n")
+}
+
+/// Load known hosts from disk, if we haven't already.
+fn load() {
+    let mut hosts = HOSTS.write().unwrap();
+    if hosts.is_some() {
+        return;
+    }
+    let mut text = String::new();
+    if let Ok(mut reader) = phetchdir::load(KNOWN_HOSTS_FILE) {
+        let _ = reader.read_to_string(&mut text);
+    }
+    *hosts = Some(parse(&text));
+}
+
+/// Write all known hosts to disk, if the phetchdir exists. Tests
+/// only pin hosts in memory.
+fn write(hosts: &HashMap<String, String>) -> Result<()> {
+    if cfg!(test) || phetchdir::path().is_err() {
+        return Ok(());
+    }
+    let mut names = hosts.keys().collect::<Vec<_>>();
+    names.sort();
+    let text = names
+        .iter()
+        .map(|host| format!("{} {}\n", host, hosts[*host]))
+        .collect::<String>();
+    phetchdir::save(KNOWN_HOSTS_FILE, &text)
+}
+
+/// Parse the known hosts file format.
+fn parse(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            Some((parts.next()?.to_string(), parts.next()?.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn test_parse() {
+        let hosts = parse("# comment\nexample.com:1965 AB:CD\n\nbad-line\nother.org:70 EF:01\n");
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts["example.com:1965"], "AB:CD");
+        assert_eq!(hosts["other.org:70"], "EF:01");
+    }
+
+    #[test]
+    fn test_changed() {
+        let err = Error::from(CertChanged {
+            host: "example.com:70".into(),
+            old: "AB".into(),
+            new: "CD".into(),
+        });
+        assert!(err.to_string().contains("has changed"));
+        match err {
+            Error::CertChanged(cert) => {
+                assert_eq!(cert.host, "example.com:70");
+                assert_eq!(cert.new, "CD");
+            }
+            e => panic!("expected CertChanged, got {:?}", e),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "tls")]
+    fn test_fingerprint() {
+        assert_eq!(
+            fingerprint(b"abc"),
+            "BA:78:16:BF:8F:01:CF:EA:41:41:40:DE:5D:AE:22:23:B0:03:61:A3:96:17:7A:9C:B4:10:FF:61:F2:00:15:AD"
+        );
+    }
+}
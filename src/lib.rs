@@ -32,6 +32,7 @@
 #![warn(missing_docs)]
 #![allow(clippy::while_let_on_iterator)]
 #![allow(clippy::write_with_newline)]
+#![allow(clippy::bool_assert_comparison)]
 
 #[macro_use]
 extern crate lazy_static;
@@ -42,16 +43,33 @@ pub mod utils;
 pub mod theme;
 pub mod args;
 pub mod bookmarks;
+pub mod cache;
+pub mod calendar;
+pub mod check;
 pub mod config;
 mod encoding;
+pub mod error;
+#[cfg(feature = "tls")]
+pub mod gemini;
+#[cfg(feature = "tls")]
+pub mod gemtext;
 pub mod gopher;
 pub mod help;
 pub mod history;
+pub mod known_hosts;
+pub mod lint;
+pub mod mailbox;
 pub mod menu;
+pub mod mirror;
 pub mod phetchdir;
+pub mod proxy;
+pub mod server;
 pub mod terminal;
 pub mod text;
 pub mod ui;
+pub mod xml;
+
+pub use error::{Error, Result};
 
 /// Current version of phetch.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
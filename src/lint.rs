@@ -0,0 +1,215 @@
+//! Gophermaps are easy to get subtly wrong, and phetch (like most
+//! clients) quietly does its best with whatever it gets: a line with
+//! no tabs is still shown, an unknown item type becomes a download.
+//! `lint()` points those mistakes out instead, one line at a time, so
+//! authors can fix them before someone with a stricter client finds
+//! them.
+//!
+//! It's used by `phetch --lint FILE` and by pressing `l` in a menu.
+
+use crate::{gopher::Type, ui::MAX_COLS};
+use std::fmt;
+
+/// Something wrong with one line of a gophermap.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Problem {
+    /// Line number, starting at 1.
+    pub line: usize,
+    /// What's wrong.
+    pub msg: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.msg)
+    }
+}
+
+/// Everything wrong with a gophermap, in line order.
+pub fn lint(raw: &str) -> Vec<Problem> {
+    let lines = raw.split_terminator('\n').collect::<Vec<_>>();
+    let mut problems = vec![];
+    let mut lf_lines = vec![];
+    let mut newlines = 0;
+    let mut ended = false;
+
+    for (i, line) in lines.iter().enumerate() {
+        let num = i + 1;
+        let has_newline = num < lines.len() || raw.ends_with('\n');
+        if has_newline {
+            newlines += 1;
+            if !line.ends_with('\r') {
+                lf_lines.push(num);
+            }
+        }
+
+        let line = line.trim_end_matches('\r');
+        if line == "." {
+            ended = true;
+            break;
+        }
+        problems.extend(
+            lint_line(line)
+                .into_iter()
+                .map(|msg| Problem { line: num, msg }),
+        );
+    }
+
+    // a file that's all LF is one mistake, not one per line
+    if lf_lines.len() > 1 && lf_lines.len() == newlines {
+        problems.push(Problem {
+            line: 1,
+            msg: "lines end in LF, not CRLF".into(),
+        });
+    } else {
+        for line in lf_lines {
+            problems.push(Problem {
+                line,
+                msg: "ends in LF, not CRLF".into(),
+            });
+        }
+    }
+
+    if !ended {
+        problems.push(Problem {
+            line: lines.len().max(1),
+            msg: "missing `.` on the last line".into(),
+        });
+    }
+
+    problems.sort_by_key(|p| p.line);
+    problems
+}
+
+/// What's wrong with one line, minus its line ending.
+fn lint_line(line: &str) -> Vec<String> {
+    let mut problems = vec![];
+    let c = match line.chars().next() {
+        Some(c) => c,
+        None => return vec!["empty line".into()],
+    };
+    let typ = Type::from(c).unwrap_or_else(|| {
+        problems.push(format!("unknown item type `{}`, shown as a download", c));
+        Type::Binary
+    });
+
+    let fields = line.split('\t').collect::<Vec<_>>();
+    let width = visible_len(&fields[0][c.len_utf8()..]);
+    if width > MAX_COLS {
+        problems.push(format!("{} columns long, over {}", width, MAX_COLS));
+    }
+
+    if typ.is_info() || typ == Type::Error {
+        if let Some(sel) = fields.get(1).filter(|s| !is_filler(s)) {
+            problems.push(format!("info line has a selector: {}", sel));
+        }
+        return problems;
+    }
+
+    match fields.len() {
+        1 => problems.push("missing selector, host, and port".into()),
+        2 => problems.push("missing host and port".into()),
+        3 => problems.push("missing port".into()),
+        _ => {}
+    }
+    if fields.get(2).is_some_and(|host| host.trim().is_empty()) {
+        problems.push("missing host".into());
+    }
+    if let Some(port) = fields.get(3) {
+        if port.is_empty() {
+            problems.push("missing port".into());
+        } else if !port.chars().all(|c| c.is_ascii_digit()) {
+            problems.push(format!("port isn't a number: {}", port));
+        } else if !matches!(port.parse::<u16>(), Ok(p) if p > 0) {
+            problems.push(format!("port out of range: {}", port));
+        }
+    }
+
+    problems
+}
+
+/// Selectors people put on info lines to fill out the fields. They
+/// don't go anywhere, and nobody expects them to.
+fn is_filler(sel: &str) -> bool {
+    matches!(
+        sel.to_lowercase().as_ref(),
+        "" | "fake" | "null" | "(null)" | "-" | "err" | "error"
+    )
+}
+
+/// How many columns `s` takes up on screen, not counting color codes.
+fn visible_len(s: &str) -> usize {
+    let mut len = 0;
+    let mut in_color = false;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_color {
+            in_color = c != 'm';
+        } else if c == '\x1b' && chars.peek() == Some(&'[') {
+            in_color = true;
+        } else {
+            len += 1;
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msgs(raw: &str) -> Vec<String> {
+        lint(raw).iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let long = format!("i{}\t\terror.host\t1", "-".repeat(80));
+        let raw = format!(
+            "iWelcome\t\terror.host\t1\r\n\
+             1Docs\t/docs\texample.com\t70\r\n\
+             0No tabs\r\n\
+             0Two\t/two\r\n\
+             0Three\t/three\texample.com\r\n\
+             9Port\t/bin\texample.com\tseventy\r\n\
+             9Port\t/bin\texample.com\t70000\r\n\
+             1Host\t/\t\t70\r\n\
+             iDangling\t/about\texample.com\t70\r\n\
+             iFiller\tfake\t(NULL)\t0\r\n\
+             QWhat\t/q\texample.com\t70\r\n\
+             {}\r\n\
+             iLF\n\
+             iColors \x1b[92m{}\x1b[0m\r\n\
+             .\r\n\
+             ignored",
+            long,
+            "-".repeat(60)
+        );
+        assert_eq!(
+            msgs(&raw),
+            [
+                "3: missing selector, host, and port",
+                "4: missing host and port",
+                "5: missing port",
+                "6: port isn't a number: seventy",
+                "7: port out of range: 70000",
+                "8: missing host",
+                "9: info line has a selector: /about",
+                "11: unknown item type `Q`, shown as a download",
+                "12: 80 columns long, over 77",
+                "13: ends in LF, not CRLF",
+            ]
+        );
+
+        assert_eq!(
+            msgs("iHello\n1Docs\tdocs\texample.com\t70\n"),
+            [
+                "1: lines end in LF, not CRLF",
+                "2: missing `.` on the last line"
+            ]
+        );
+        assert_eq!(msgs("iHello\r\n\r\n.\r\n"), ["2: empty line"]);
+        assert_eq!(msgs("iHello\r\n.\r\n"), Vec::<String>::new());
+        assert_eq!(msgs(""), ["1: missing `.` on the last line"]);
+    }
+}
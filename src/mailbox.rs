@@ -0,0 +1,553 @@
+//! Type M items are mailboxes in the classic Unix mbox format: every
+//! message one after the other, each starting with a `From ` line.
+//! phetch shows a mailbox as a Menu with a link for each message, and
+//! each message as Text with its headers unfolded and its text parts
+//! decoded from quoted-printable or base64.
+//!
+//! A message's URL is the mailbox's URL with the message number as
+//! its search string, ex: `gopher://example.com/M/list.mbox%093`.
+//! Opening it picks that message out of the mailbox the UI fetched
+//! for the list, and only fetches the mailbox again if it has to.
+
+use crate::gopher;
+
+/// Headers shown at the top of a message, in this order.
+const SHOWN_HEADERS: [&str; 5] = ["From", "To", "Cc", "Date", "Subject"];
+
+/// How wide the From column is in the message list.
+const FROM_WIDTH: usize = 20;
+
+/// One message from a mailbox.
+#[derive(Debug)]
+pub struct Message {
+    /// Headers in the order they appeared, unfolded.
+    headers: Vec<(String, String)>,
+    /// Everything after the headers, still encoded.
+    body: String,
+}
+
+/// Split an mbox into its messages.
+pub fn parse(mbox: &str) -> Vec<Message> {
+    let mut messages = vec![];
+    let mut current: Option<Vec<&str>> = None;
+    let mut blank = true;
+
+    for line in mbox.lines() {
+        let line = line.trim_end_matches('\r');
+        if blank && line.starts_with("From ") {
+            if let Some(lines) = current.take() {
+                messages.push(Message::parse(&lines));
+            }
+            current = Some(vec![]);
+        } else if let Some(lines) = current.as_mut() {
+            lines.push(line);
+        }
+        blank = line.is_empty();
+    }
+    if let Some(mut lines) = current {
+        // Gopher servers may add their own end of response marker
+        if lines.last() == Some(&".") {
+            lines.pop();
+        }
+        messages.push(Message::parse(&lines));
+    }
+    messages
+}
+
+/// Gophermap listing every message in a mailbox, linking to each one.
+pub fn to_menu(url: &str, messages: &[Message]) -> String {
+    let u = gopher::parse_url(url);
+    let mut out = vec![
+        format!(
+            "i{} message{}",
+            messages.len(),
+            if messages.len() == 1 { "" } else { "s" }
+        ),
+        "i".into(),
+    ];
+    for (i, msg) in messages.iter().enumerate() {
+        let from = msg.from().chars().take(FROM_WIDTH).collect::<String>();
+        let label = format!(
+            "{:<11}  {:<width$}  {}",
+            msg.short_date(),
+            from,
+            msg.subject(),
+            width = FROM_WIDTH
+        );
+        let mut msg = u.clone();
+        msg.search = Some((i + 1).to_string());
+        out.push(format!(
+            "M{}\tURL:{}\t{}\t{}",
+            label.replace('\t', " "),
+            msg,
+            u.host,
+            u.port
+        ));
+    }
+    out.join("\r\n")
+}
+
+/// Which message a mailbox URL points to, if any. 1-based.
+pub fn message_number(url: &str) -> Option<usize> {
+    gopher::parse_url(url).search?.trim().parse().ok()
+}
+
+/// The mailbox a message URL belongs to.
+pub fn mailbox_url(url: &str) -> String {
+    let mut u = gopher::parse_url(url);
+    u.search = None;
+    u.to_string()
+}
+
+impl Message {
+    /// Parse a message's lines, minus its `From ` line.
+    fn parse(lines: &[&str]) -> Message {
+        let end = lines
+            .iter()
+            .position(|l| l.is_empty())
+            .unwrap_or(lines.len());
+        let body = lines
+            .iter()
+            .skip(end + 1)
+            .map(|line| unescape_from(line))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Message {
+            headers: parse_headers(&lines[..end]),
+            body,
+        }
+    }
+
+    /// Value of the first header called `name`, decoded.
+    pub fn header(&self, name: &str) -> Option<String> {
+        header(&self.headers, name).map(decode_words)
+    }
+
+    /// Who sent it: their name if they gave one, otherwise their
+    /// address.
+    pub fn from(&self) -> String {
+        let from = self.header("From").unwrap_or_default();
+        match from.split_once('<') {
+            Some((name, _)) if !name.trim().is_empty() => name.trim().trim_matches('"').into(),
+            Some((_, addr)) => addr.trim_end_matches('>').into(),
+            None => from,
+        }
+    }
+
+    /// Subject line, or a placeholder.
+    pub fn subject(&self) -> String {
+        match self.header("Subject") {
+            Some(s) if !s.trim().is_empty() => s.trim().into(),
+            _ => "(no subject)".into(),
+        }
+    }
+
+    /// Date without the day of the week or time, ex: "28 Jul 1993".
+    pub fn short_date(&self) -> String {
+        let date = self.header("Date").unwrap_or_default();
+        let mut words = date.split_whitespace().peekable();
+        if words.peek().is_some_and(|w| w.ends_with(',')) {
+            words.next();
+        }
+        words.take(3).collect::<Vec<_>>().join(" ")
+    }
+
+    /// The message as it should be read: the interesting headers, then
+    /// the text parts of the body, decoded, then a list of anything
+    /// else that was attached.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for name in SHOWN_HEADERS.iter() {
+            if let Some(value) = self.header(name) {
+                out.push_str(&format!("{}: {}\n", name, value));
+            }
+        }
+        out.push('\n');
+
+        let mut attachments = vec![];
+        out.push_str(&body_text(&self.headers, &self.body, &mut attachments));
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+        for attachment in attachments {
+            out.push_str(&format!("\n[Attachment: {}]", attachment));
+        }
+        out
+    }
+}
+
+/// Parse header lines, joining folded lines onto the one they
+/// continue.
+fn parse_headers(lines: &[&str]) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = vec![];
+    for line in lines {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().into(), value.trim().into()));
+        }
+    }
+    headers
+}
+
+/// Value of the first header called `name`, ignoring case.
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// mbox files escape body lines starting with `From ` by adding a `>`.
+fn unescape_from(line: &str) -> &str {
+    match line.strip_prefix('>') {
+        Some(rest) if rest.trim_start_matches('>').starts_with("From ") => rest,
+        _ => line,
+    }
+}
+
+/// Content-Type of a message or part: just the lowercased MIME type.
+/// Use `param()` for things like `boundary` or `charset`.
+fn content_type(headers: &[(String, String)]) -> String {
+    header(headers, "Content-Type")
+        .unwrap_or("text/plain")
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_lowercase()
+}
+
+/// A parameter from a header like Content-Type, ex: `charset`.
+fn param(headers: &[(String, String)], name: &str, key: &str) -> Option<String> {
+    header(headers, name)?.split(';').skip(1).find_map(|p| {
+        let (k, v) = p.split_once('=')?;
+        if k.trim().eq_ignore_ascii_case(key) {
+            Some(v.trim().trim_matches('"').to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Readable text from a message or MIME part. Parts that aren't text
+/// get added to `attachments` instead.
+fn body_text(headers: &[(String, String)], body: &str, attachments: &mut Vec<String>) -> String {
+    let typ = content_type(headers);
+    if typ.starts_with("multipart/") {
+        let boundary = match param(headers, "Content-Type", "boundary") {
+            Some(b) => b,
+            None => return body.into(),
+        };
+        let parts = split_multipart(body, &boundary);
+        // alternatives are the same thing in different formats, so
+        // only show one, plain text if we can
+        if typ == "multipart/alternative" {
+            let best = parts
+                .iter()
+                .find(|(h, _)| content_type(h) == "text/plain")
+                .or_else(|| parts.first());
+            return match best {
+                Some((h, b)) => body_text(h, b, attachments),
+                None => String::new(),
+            };
+        }
+        return parts
+            .iter()
+            .map(|(h, b)| body_text(h, b, attachments))
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    let is_attachment = header(headers, "Content-Disposition")
+        .is_some_and(|d| d.to_lowercase().starts_with("attachment"));
+    if !typ.starts_with("text/") || is_attachment {
+        let name = param(headers, "Content-Disposition", "filename")
+            .or_else(|| param(headers, "Content-Type", "name"))
+            .unwrap_or_else(|| "unnamed".into());
+        attachments.push(format!("{} ({})", decode_words(&name), typ));
+        return String::new();
+    }
+
+    let encoding = header(headers, "Content-Transfer-Encoding")
+        .unwrap_or("")
+        .to_lowercase();
+    let bytes = match encoding.trim() {
+        "quoted-printable" => decode_qp(body, false),
+        "base64" => decode_base64(body),
+        _ => body.as_bytes().to_vec(),
+    };
+    let charset = param(headers, "Content-Type", "charset").unwrap_or_default();
+    decode_charset(&bytes, &charset)
+}
+
+/// Split a multipart body into its parts' headers and bodies.
+fn split_multipart(body: &str, boundary: &str) -> Vec<(Vec<(String, String)>, String)> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = vec![];
+    let mut current: Option<Vec<&str>> = None;
+    for line in body.lines() {
+        if line.starts_with(&delimiter) {
+            if let Some(lines) = current.take() {
+                parts.push(lines);
+            }
+            if line.trim_end() == format!("{}--", delimiter) {
+                break;
+            }
+            current = Some(vec![]);
+        } else if let Some(lines) = current.as_mut() {
+            lines.push(line);
+        }
+    }
+    parts
+        .into_iter()
+        .map(|lines| {
+            let end = lines
+                .iter()
+                .position(|l| l.is_empty())
+                .unwrap_or(lines.len());
+            let body = lines.iter().skip(end + 1).copied().collect::<Vec<_>>();
+            (parse_headers(&lines[..end]), body.join("\n"))
+        })
+        .collect()
+}
+
+/// Turn bytes in `charset` into a String. We only know UTF-8 and
+/// Latin-1, which covers most old mail. Anything else gets treated as
+/// UTF-8 and hopefully mostly works.
+fn decode_charset(bytes: &[u8], charset: &str) -> String {
+    match charset.to_lowercase().as_ref() {
+        "iso-8859-1" | "iso-8859-15" | "latin1" | "windows-1252" => {
+            bytes.iter().map(|&b| b as char).collect()
+        }
+        _ => String::from_utf8_lossy(bytes).into(),
+    }
+}
+
+/// Decode quoted-printable text. In headers, `_` means a space.
+fn decode_qp(text: &str, header: bool) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'=' if bytes.get(i + 1) == Some(&b'\n') => i += 2,
+            b'=' if bytes.get(i + 1) == Some(&b'\r') && bytes.get(i + 2) == Some(&b'\n') => i += 3,
+            b'=' => match text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(b) => {
+                    out.push(b);
+                    i += 3;
+                }
+                None => {
+                    out.push(b'=');
+                    i += 1;
+                }
+            },
+            b'_' if header => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Decode base64, skipping line breaks and anything else that isn't
+/// part of the alphabet.
+fn decode_base64(text: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut nbits) = (0u32, 0);
+    for c in text.bytes() {
+        let val = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => continue,
+        };
+        bits = bits << 6 | u32::from(val);
+        nbits += 6;
+        if nbits >= 8 {
+            nbits -= 8;
+            out.push((bits >> nbits) as u8);
+            bits &= (1 << nbits) - 1;
+        }
+    }
+    out
+}
+
+/// Decode RFC 2047 encoded words in a header, like
+/// `=?UTF-8?Q?caf=C3=A9?=`. Whitespace between two encoded words is
+/// dropped.
+fn decode_words(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let decoded = encoded_word(&rest[start..]);
+        let before = &rest[..start];
+        match decoded {
+            Some((text, len)) => {
+                if !(after_word && before.trim().is_empty()) {
+                    out.push_str(before);
+                }
+                out.push_str(&text);
+                rest = &rest[start + len..];
+                after_word = true;
+            }
+            None => {
+                out.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_word = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Decode the encoded word at the start of `s`, returning the text and
+/// how long the encoded word was.
+fn encoded_word(s: &str) -> Option<(String, usize)> {
+    let mut fields = s[2..].splitn(3, '?');
+    let charset = fields.next()?;
+    let encoding = fields.next()?;
+    let rest = fields.next()?;
+    let end = rest.find("?=")?;
+    let text = &rest[..end];
+    let bytes = match encoding {
+        "Q" | "q" => decode_qp(text, true),
+        "B" | "b" => decode_base64(text),
+        _ => return None,
+    };
+    let len = 2 + charset.len() + 1 + encoding.len() + 1 + end + 2;
+    Some((decode_charset(&bytes, charset), len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MBOX: &str = "From joe@example.com Wed Jul 28 17:02:01 1993\r
+From: Joe Gopher <joe@example.com>\r
+To: list@example.com\r
+Subject: Gopher+ is\r
+ here\r
+Date: Wed, 28 Jul 1993 17:02:01 -0500\r
+\r
+Hello everyone,\r
+>From now on, menus have attributes.\r
+\r
+From mary@example.com Thu Jul 29 09:00:00 1993\r
+From: =?ISO-8859-1?Q?Mar=EDa?= <mary@example.com>\r
+Subject: =?UTF-8?B?Y2Fmw6k=?= =?UTF-8?Q?_time?=\r
+Date: 29 Jul 1993 09:00:00 -0500\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"XYZ\"\r
+\r
+This is a multi-part message in MIME format.\r
+--XYZ\r
+Content-Type: multipart/alternative; boundary=ALT\r
+\r
+--ALT\r
+Content-Type: text/plain; charset=utf-8\r
+Content-Transfer-Encoding: quoted-printable\r
+\r
+Caf=C3=A9 at noon? It's a long line that gets wrapped by the =\r
+encoder.\r
+--ALT\r
+Content-Type: text/html\r
+\r
+<p>Caf&eacute; at noon?</p>\r
+--ALT--\r
+--XYZ\r
+Content-Type: image/gif; name=\"map.gif\"\r
+Content-Transfer-Encoding: base64\r
+\r
+R0lGODlhAQABAAAAACw=\r
+--XYZ\r
+Content-Type: text/plain\r
+Content-Transfer-Encoding: base64\r
+\r
+U2VlIHlvdSB0aGVyZSE=\r
+--XYZ--\r
+.\r
+";
+
+    #[test]
+    fn test_parse() {
+        let messages = parse(MBOX);
+        assert_eq!(messages.len(), 2);
+
+        let joe = &messages[0];
+        assert_eq!(joe.from(), "Joe Gopher");
+        assert_eq!(joe.subject(), "Gopher+ is here");
+        assert_eq!(joe.short_date(), "28 Jul 1993");
+        assert_eq!(
+            joe.to_text(),
+            "From: Joe Gopher <joe@example.com>
+To: list@example.com
+Date: Wed, 28 Jul 1993 17:02:01 -0500
+Subject: Gopher+ is here
+
+Hello everyone,
+From now on, menus have attributes.
+"
+        );
+
+        let mary = &messages[1];
+        assert_eq!(mary.from(), "María");
+        assert_eq!(mary.subject(), "café time");
+        assert_eq!(mary.short_date(), "29 Jul 1993");
+        assert_eq!(
+            mary.to_text(),
+            "From: María <mary@example.com>
+Date: 29 Jul 1993 09:00:00 -0500
+Subject: café time
+
+Café at noon? It's a long line that gets wrapped by the encoder.
+See you there!
+
+[Attachment: map.gif (image/gif)]"
+        );
+    }
+
+    #[test]
+    fn test_to_menu() {
+        let url = "gopher://example.com/M/lists/gopher.mbox";
+        let menu = to_menu(url, &parse(MBOX));
+        assert_eq!(
+            menu,
+            "i2 messages\r
+i\r
+M28 Jul 1993  Joe Gopher            Gopher+ is here\tURL:gopher://example.com/M/lists/gopher.mbox%091\texample.com\t70\r
+M29 Jul 1993  María                 café time\tURL:gopher://example.com/M/lists/gopher.mbox%092\texample.com\t70"
+        );
+
+        let msg_url = "gopher://example.com/M/lists/gopher.mbox%092";
+        assert_eq!(message_number(msg_url), Some(2));
+        assert_eq!(mailbox_url(msg_url), url);
+        assert_eq!(message_number(url), None);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode_base64("aGVsbG8gd29y\nbGQ="), b"hello world");
+        assert_eq!(decode_qp("a=3Db=\nc =ZZ", false), b"a=bc =ZZ");
+        assert_eq!(decode_words("=?utf-8?q?a_b?= =?utf-8?q?c?= d"), "a bc d");
+        assert_eq!(decode_words("no =?words here"), "no =?words here");
+    }
+}
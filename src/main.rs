@@ -1,14 +1,18 @@
 use phetch::{
-    args,
+    args, cache, calendar, check,
     config::{Config, SharedConfig},
-    gopher, menu, terminal, theme,
+    gopher, lint, menu, mirror, server, terminal, theme,
     ui::{Mode, UI},
+    xml,
 };
+#[cfg(feature = "tls")]
+use phetch::{gemini, gemtext};
 use std::{
     env,
     error::Error,
+    fs,
     io::{self, stdout, Write},
-    panic, process, str,
+    panic, process,
 };
 
 fn main() {
@@ -25,11 +29,15 @@ fn run() -> Result<(), Box<dyn Error>> {
 
     // check for simple modes
     match cfg.mode {
-        Mode::Raw => return print_raw(&cfg.start, cfg.tls, cfg.tor),
+        Mode::Raw => return print_raw(&cfg),
         Mode::Version => return print_version(),
         Mode::Help => return print_usage(),
         Mode::PrintTheme => return print_theme(cfg),
-        Mode::NoTTY => return print_plain(&cfg.start, cfg.tls, cfg.tor),
+        Mode::NoTTY => return print_plain(&cfg),
+        Mode::Serve => return Ok(server::serve(&cfg.server)?),
+        Mode::Mirror => return mirror_site(&cfg),
+        Mode::Check => return check_links(&cfg),
+        Mode::Lint => return lint_file(&cfg),
         Mode::Print => cfg.wide = true,
         Mode::Run => {}
     }
@@ -72,13 +80,16 @@ fn print_usage() -> Result<(), Box<dyn Error>> {
 Usage:
 
     phetch [options]       Launch phetch in interactive mode
-    phetch [options] url   Open Gopher URL in interactive mode
+    phetch [options] url   Open Gopher or Gemini URL in interactive mode
 
 Options:
 
     -s, --tls              Try to open Gopher URLs securely w/ TLS
+    --tls-strict           Only use TLS, never fall back to plain text
     -o, --tor              Use local Tor proxy to open all pages
     -S, -O                 Disable TLS or Tor
+    --proxy URL            Connect through a socks5:// or http:// proxy
+    --no-proxy             Disable the proxy
 
     -w, --wrap COLUMN      Wrap long lines in \"text\" views at COLUMN.
     -m, --media PROGRAM    Use to open media files. Default: mpv
@@ -87,11 +98,27 @@ Options:
     -a, --autoplay         Autoplay media without prompting.
     -A, --no-autoplay      Prompt before playing media.
 
-    -r, --raw              Print raw Gopher response only
-    -p, --print            Print rendered Gopher response only
+    -r, --raw              Print raw Gopher or Gemini response only
+    -p, --print            Print rendered Gopher or Gemini response only
     -l, --local            Connect to 127.0.0.1:7070
+    --serve DIR            Serve DIR over Gopher on 127.0.0.1:7070
+    --port N               Serve on port N instead
+    --mirror URL           Save a copy of the gopherhole at URL
+    --output DIR           Save the mirror to DIR. Default: .
+    --depth N              Follow menus N levels deep. 0 = no limit
+    --delay SECONDS        Wait SECONDS between requests. Default: 1
+    --all-hosts            Mirror links to other servers too
+    --check URL            Look for broken links in the menus at URL
+    --slow SECONDS         Report links slower than SECONDS. Default: 2
+    --external             Check that URL: links' servers are up too
+    --lint FILE            Look for mistakes in the gophermap FILE
     -e, --encoding         Render text documents in CP437 or UTF8.
 
+    --timeout SECONDS      Give up connecting to a server after SECONDS.
+    --read-timeout SECONDS Give up waiting on a server after SECONDS.
+    --retries N            Retry requests that time out N times.
+    --offline              Only show pages from the cache.
+
     -c, --config FILE      Use instead of ~/.config/phetch/phetch.conf
     -C, --no-config        Don't use any config file
     -t, --theme FILE       Use FILE for color theme or print current theme.
@@ -107,20 +134,116 @@ Once you've launched phetch, use `ctrl-h` to view the on-line help."
     Ok(())
 }
 
+/// Save a copy of a gopherhole, printing each URL as we go.
+fn mirror_site(cfg: &Config) -> Result<(), Box<dyn Error>> {
+    let report = mirror::mirror(
+        &cfg.start,
+        &cfg.mirror,
+        cfg.tls_mode(),
+        &*cfg.transport(),
+        cfg.timeouts,
+        |entry| println!("{}", entry),
+    )?;
+    println!("\n{}", report.summary());
+    Ok(())
+}
+
+/// Look for broken links, and exit with status 1 if there are any.
+fn check_links(cfg: &Config) -> Result<(), Box<dyn Error>> {
+    let report = check::check(
+        &cfg.start,
+        &cfg.check,
+        cfg.tls_mode(),
+        &*cfg.transport(),
+        cfg.timeouts,
+    )?;
+    print!("{}", report);
+    if report.broken() > 0 {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Print any problems with a gophermap, like a compiler would, and
+/// exit with status 1 if there are any.
+fn lint_file(cfg: &Config) -> Result<(), Box<dyn Error>> {
+    let path = &cfg.start;
+    let raw = fs::read(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+    let problems = lint::lint(&String::from_utf8_lossy(&raw));
+    for problem in &problems {
+        println!("{}:{}", path, problem);
+    }
+    if !problems.is_empty() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
 /// Print just the raw Gopher response.
-fn print_raw(url: &str, tls: bool, tor: bool) -> Result<(), Box<dyn Error>> {
-    let (_, out) = gopher::fetch_url(url, tls, tor)?;
+fn print_raw(cfg: &Config) -> Result<(), Box<dyn Error>> {
+    let url = cfg.start.as_str();
+    #[cfg(feature = "tls")]
+    if url.starts_with("gemini://") {
+        let res = gemini::fetch_url(url, &*cfg.transport(), cfg.timeouts)?;
+        println!("{}", String::from_utf8_lossy(&res.body));
+        return Ok(());
+    }
+    let (_, out, _) = cache::fetch_url(
+        url,
+        cfg.tls_mode(),
+        &*cfg.transport(),
+        cfg.timeouts,
+        cfg.cache,
+    )?;
     println!("{}", gopher::response_to_string(&out));
     Ok(())
 }
 
 /// Print a colorless, plain version of the response for a non-tty
 /// (like a pipe).
-fn print_plain(url: &str, tls: bool, tor: bool) -> Result<(), Box<dyn Error>> {
-    let mut out = String::new();
+fn print_plain(cfg: &Config) -> Result<(), Box<dyn Error>> {
+    let url = cfg.start.as_str();
+    #[cfg(feature = "tls")]
+    if url.starts_with("gemini://") {
+        let res = gemini::fetch_url(url, &*cfg.transport(), cfg.timeouts)?;
+        let body = String::from_utf8_lossy(&res.body);
+        if !res.is_gemtext() {
+            println!("{}", body);
+            return Ok(());
+        }
+        let mut out = String::new();
+        for line in gemtext::parse(&res.url, &body) {
+            match line {
+                gemtext::Line::Link { label, .. } => out.push_str(&label),
+                gemtext::Line::Heading(_, text)
+                | gemtext::Line::Text(text)
+                | gemtext::Line::Item(text)
+                | gemtext::Line::Quote(text)
+                | gemtext::Line::Pre(text) => out.push_str(&text),
+            }
+            out.push('\n');
+        }
+        print!("{}", out);
+        return Ok(());
+    }
     let typ = gopher::type_for_url(url);
-    let (_, response) = gopher::fetch_url(url, tls, tor)?;
-    let response = gopher::response_to_string(&response);
+    let (_, response, _) = cache::fetch_url(
+        url,
+        cfg.tls_mode(),
+        &*cfg.transport(),
+        cfg.timeouts,
+        cfg.cache,
+    )?;
+    print!(
+        "{}",
+        plain_gopher(url, typ, gopher::response_to_string(&response))?
+    );
+    Ok(())
+}
+
+/// Plain version of a Gopher response of type `typ`.
+fn plain_gopher(url: &str, typ: gopher::Type, response: String) -> Result<String, Box<dyn Error>> {
+    let mut out = String::new();
     match typ {
         gopher::Type::Menu => {
             let menu = menu::parse(url, response, SharedConfig::default());
@@ -129,16 +252,22 @@ fn print_plain(url: &str, tls: bool, tor: bool) -> Result<(), Box<dyn Error>> {
                 out.push('\n');
             }
         }
-        gopher::Type::Text => println!("{}", response.trim_end_matches(".\r\n")),
+        gopher::Type::Text => out = format!("{}\n", response.trim_end_matches(".\r\n")),
+        // not every type c item is really iCalendar, so print anything
+        // else as it is, like the UI does
+        gopher::Type::Calendar => match calendar::to_agenda(&response) {
+            Ok(agenda) => out.push_str(&agenda),
+            Err(_) => out = format!("{}\n", response.trim_end_matches(".\r\n")),
+        },
+        gopher::Type::Xml => out.push_str(&xml::pretty(response.trim_end_matches(".\r\n"))),
         _ => {
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::Other,
-                format!("can't print gopher type: {:?}", typ),
-            )));
+            return Err(Box::new(io::Error::other(format!(
+                "can't print gopher type: {:?}",
+                typ
+            ))));
         }
     };
-    print!("{}", out);
-    Ok(())
+    Ok(out)
 }
 
 /// Print current theme as plaintext
@@ -177,3 +306,21 @@ fn cleanup_terminal() {
     stdout.flush().expect("Fatal Error cleaning up terminal.");
     terminal::disable_raw_mode().expect("Fatal Error leaving Raw Mode.");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_calendar() {
+        let url = "gopher://example.com/c/events.ics";
+        let ics = "BEGIN:VCALENDAR\r\n\
+                   X-WR-CALNAME:Gopher Events\r\n\
+                   END:VCALENDAR\r\n";
+        let out = plain_gopher(url, gopher::Type::Calendar, ics.into()).unwrap();
+        assert!(out.starts_with("Gopher Events\n"));
+
+        let out = plain_gopher(url, gopher::Type::Calendar, "Free Sunday?\r\n".into()).unwrap();
+        assert_eq!(out, "Free Sunday?\r\n\n");
+    }
+}
@@ -9,10 +9,10 @@
 use crate::{
     config::SharedConfig as Config,
     gopher::{self, Type},
-    terminal,
+    lint, terminal,
     ui::{self, Action, Key, View, MAX_COLS},
 };
-use std::fmt;
+use std::{collections::HashMap, fmt};
 
 /// The Menu holds our Gopher Lines, a list of links, and maintains
 /// both where the cursor is on screen and which lines need to be
@@ -46,12 +46,23 @@ pub struct Menu {
     tls: bool,
     /// Retrieved via Tor?
     tor: bool,
+    /// Served from the cache?
+    cached: bool,
     /// Size of the screen currently, cols and rows
     pub size: (usize, usize),
     /// Wide mode?
     wide: bool,
     /// Scroll by how many lines?
     scroll: usize,
+    /// Lint problems, by where their line starts in `raw`. Only set
+    /// while they're being shown.
+    lint: Option<HashMap<usize, String>>,
+    /// How much of `raw` has been parsed into `spans`, in bytes.
+    parsed: usize,
+    /// Last link that `+` mirror lines would belong to.
+    primary: Option<usize>,
+    /// Have we seen Gopher's "end of response" line?
+    ended: bool,
     /// Global config
     config: Config,
 }
@@ -78,6 +89,12 @@ impl<'line, 'txt> Line<'line, 'txt> {
         }
     }
 
+    /// The whole line, exactly as it appeared in the raw Gopher
+    /// response, minus the line ending.
+    pub fn raw(&self) -> &str {
+        self.text[self.start..self.end].trim_end_matches('\r')
+    }
+
     /// Truncated version of the line, according to visible characters
     /// and MAX_COLS.
     pub fn text_truncated(&self) -> String {
@@ -109,17 +126,14 @@ impl<'line, 'txt> Line<'line, 'txt> {
                 .trim_start_matches("URL:")
                 .to_string()
         } else if self.typ.is_telnet() {
-            format!("telnet://{}:{}", host, port)
+            gopher::Url::new(self.typ, host, port, "").to_string()
+        } else if let Some(url) = sel.strip_prefix("URL:") {
+            // full URLs, like the ones phetch puts in menus it builds
+            // when a link needs a search or Gopher+ string
+            url.to_string()
         } else {
-            let mut path = format!("/{}{}", self.typ, sel);
-            if sel.is_empty() || sel == "/" {
-                path.clear();
-            }
-            if port == "70" {
-                format!("gopher://{}{}", host, path)
-            } else {
-                format!("gopher://{}:{}{}", host, port, path)
-            }
+            let sel = if sel == "/" { "" } else { sel };
+            gopher::Url::from_menu(self.typ, host, port, sel).to_string()
         }
     }
 }
@@ -159,6 +173,14 @@ pub struct LineSpan {
     /// Index of this link in the Menu::links vector, if it's a
     /// `gopher::Type.is_link()`
     pub link: usize,
+    /// Is this a Gopher+ item? They have a fifth `+` or `?` field.
+    pub plus: bool,
+    /// Does this Gopher+ item have an +ASK form? (`?` field)
+    pub ask: bool,
+    /// For `+` lines, the index in Menu.spans of the item this is a
+    /// mirror of. Mirrors take on their item's type, but aren't links
+    /// of their own.
+    pub mirror_of: Option<usize>,
 }
 
 impl LineSpan {
@@ -221,6 +243,19 @@ impl View for Menu {
         self.tor
     }
 
+    fn is_cached(&self) -> bool {
+        self.cached
+    }
+
+    fn set_cached(&mut self, cached: bool) {
+        self.cached = cached;
+    }
+
+    fn append(&mut self, chunk: &[u8]) {
+        self.raw.push_str(&gopher::response_to_string(chunk));
+        self.parse_lines();
+    }
+
     fn raw(&self) -> &str {
         self.raw.as_ref()
     }
@@ -248,6 +283,21 @@ impl View for Menu {
     fn url(&self) -> &str {
         self.url.as_ref()
     }
+
+    fn mirrors(&self, url: &str) -> Vec<String> {
+        let primary = match self
+            .links
+            .iter()
+            .find(|&&i| self.line(i).map(|l| l.url()).as_deref() == Some(url))
+        {
+            Some(&i) => i,
+            None => return vec![],
+        };
+        self.lines()
+            .filter(|line| line.mirror_of == Some(primary))
+            .map(|line| line.url())
+            .collect()
+    }
 }
 
 impl Menu {
@@ -264,13 +314,64 @@ impl Menu {
         }
     }
 
+    /// Parse the lines in `raw` we haven't gotten to yet. Loaders only
+    /// append whole lines, so this picks up right where it left off.
+    fn parse_lines(&mut self) {
+        if self.ended {
+            return;
+        }
+        let raw = std::mem::take(&mut self.raw);
+        let mut start = self.parsed;
+
+        for line in raw[start..].split_terminator('\n') {
+            // Check for Gopher's weird "end of response" message.
+            if line == ".\r" || line == "." {
+                self.ended = true;
+                break;
+            }
+
+            if line.is_empty() {
+                start += 1;
+                continue;
+            }
+
+            if let Some(mut span) = parse_line(start, &raw) {
+                if span.text_len() > self.longest {
+                    self.longest = span.text_len();
+                }
+                if span.typ == Type::Mirror {
+                    if let Some(idx) = self.primary {
+                        span.typ = self.spans[idx].typ;
+                        span.mirror_of = Some(idx);
+                        self.spans.push(span);
+                        start += line.len() + 1;
+                        continue;
+                    }
+                } else if span.typ.is_info() {
+                    self.primary = None;
+                } else {
+                    self.primary = Some(self.spans.len());
+                }
+                if span.typ.is_link() {
+                    span.link = self.links.len();
+                    self.links.push(self.spans.len());
+                }
+                self.spans.push(span);
+            }
+
+            start += line.len() + 1;
+        }
+
+        self.parsed = start.min(raw.len());
+        self.raw = raw;
+    }
     /// Lines in this menu. Main iterator for getting Line with text.
-    pub fn lines(&self) -> LinesIter {
+    pub fn lines(&self) -> LinesIter<'_> {
         LinesIter::new(&self.spans, &self.raw)
     }
 
     /// Get a single Line in this menu by index.
-    pub fn line(&self, idx: usize) -> Option<Line> {
+    pub fn line(&self, idx: usize) -> Option<Line<'_, '_>> {
         if idx >= self.spans.len() {
             None
         } else {
@@ -279,7 +380,7 @@ impl Menu {
     }
 
     /// Find a link by its link index.
-    pub fn link(&self, idx: usize) -> Option<Line> {
+    pub fn link(&self, idx: usize) -> Option<Line<'_, '_>> {
         let line = self.links.get(idx)?;
         self.line(*line)
     }
@@ -315,11 +416,7 @@ impl Menu {
             0
         } else {
             let left = (cols - longest) / 2;
-            if left > 6 {
-                left - 6
-            } else {
-                0
-            }
+            left.saturating_sub(6)
         }
     }
 
@@ -375,6 +472,11 @@ impl Menu {
 
             if line.typ == Type::Info {
                 out.push_str("      ");
+            } else if line.mirror_of.is_some() {
+                out.push_str("      ");
+                out.push_str(&config.theme.ui_number);
+                out.push_str("+ ");
+                out.push_str(reset_color!());
             } else {
                 if line.link == self.link && self.show_cursor() {
                     out.push_str(&config.theme.ui_cursor);
@@ -406,19 +508,36 @@ impl Menu {
                 out.push_str(&self.config.read().unwrap().theme.item_unsupported);
             } else {
                 out.push_str(match line.typ {
-                    Type::Text => &config.theme.item_text,
+                    Type::Text | Type::Xml => &config.theme.item_text,
                     Type::Menu => &config.theme.item_menu,
                     Type::Info => &config.theme.ui_menu,
                     Type::HTML => &config.theme.item_external,
                     Type::Error => &config.theme.item_error,
                     Type::Telnet => &config.theme.item_telnet,
-                    Type::Search => &config.theme.item_search,
+                    Type::Search | Type::CSOEntity => &config.theme.item_search,
                     _ => &config.theme.item_error,
                 });
             }
             out.push_str(&text);
             out.push_str(reset_color!());
 
+            // lint problems go after the line, if there's room
+            if let Some(msg) = self.lint.as_ref().and_then(|l| l.get(&line.start)) {
+                let used = indent + 6 + line.visible_len + 4;
+                let room = if self.mode == ui::Mode::Run {
+                    self.cols().saturating_sub(used)
+                } else {
+                    msg.len()
+                };
+                if room > 0 {
+                    out.push_str("  ");
+                    out.push_str(&config.theme.item_error);
+                    out.push_str("! ");
+                    out.push_str(&msg.chars().take(room).collect::<String>());
+                    out.push_str(reset_color!());
+                }
+            }
+
             // clear rest of line
             out.push_str(terminal::ClearUntilNewline.as_ref());
             out.push_str("\r\n");
@@ -809,14 +928,15 @@ impl Menu {
             let url = line.url();
             let typ = gopher::type_for_url(&url);
             match typ {
-                Type::Search => {
+                _ if line.ask => Action::Open(line.text().to_string(), gopher::plus::ask_url(&url)),
+                Type::Search | Type::CSOEntity => {
                     let prompt = format!("{}> ", line.text());
                     Action::Prompt(
                         prompt.clone(),
                         Box::new(move |query| {
                             Action::Open(
                                 format!("{}{}", prompt, query),
-                                format!("{}?{}", url, query),
+                                gopher::search_url(&url, &query),
                             )
                         }),
                     )
@@ -830,6 +950,53 @@ impl Menu {
         }
     }
 
+    /// Open the Gopher+ item info page for the selected link.
+    fn action_item_info(&self) -> Action {
+        if let Some(line) = self.link(self.link) {
+            if line.plus {
+                Action::Open(
+                    format!("{} (info)", line.text()),
+                    gopher::plus::attributes_url(&line.url()),
+                )
+            } else {
+                Action::Error("Not a Gopher+ item.".into())
+            }
+        } else {
+            Action::None
+        }
+    }
+
+    /// Show or hide problems with this menu's gophermap.
+    fn action_lint(&mut self) -> Action {
+        if self.lint.take().is_some() {
+            return Action::Redraw;
+        }
+
+        let problems = lint::lint(&self.raw);
+        let starts = std::iter::once(0)
+            .chain(self.raw.match_indices('\n').map(|(i, _)| i + 1))
+            .collect::<Vec<_>>();
+        let mut lint = HashMap::new();
+        for problem in &problems {
+            if let Some(&start) = starts.get(problem.line - 1) {
+                lint.entry(start)
+                    .and_modify(|msg: &mut String| {
+                        msg.push_str("; ");
+                        msg.push_str(&problem.msg);
+                    })
+                    .or_insert_with(|| problem.msg.clone());
+            }
+        }
+        self.lint = Some(lint);
+
+        let status = match problems.len() {
+            0 => "No problems in this gophermap.".into(),
+            1 => "1 problem in this gophermap.".into(),
+            n => format!("{} problems in this gophermap.", n),
+        };
+        Action::List(vec![Action::Redraw, Action::Status(status)])
+    }
+
     /// self.searching == true
     fn process_search_mode_char(&mut self, c: char) -> Action {
         if c == '\n' {
@@ -881,6 +1048,8 @@ impl Menu {
                 }
                 Action::Redraw
             }
+            Key::Char('=') => self.action_item_info(),
+            Key::Char('l') | Key::Ctrl('l') => self.action_lint(),
             Key::Char('f') | Key::Ctrl('f') | Key::Char('/') | Key::Char('i') | Key::Ctrl('i') => {
                 self.searching = true;
                 self.input.clear();
@@ -937,41 +1106,11 @@ impl Menu {
 
 /// Parse gopher response into a Menu object.
 pub fn parse(url: &str, raw: String, config: Config) -> Menu {
-    let mut spans = vec![];
-    let mut links = vec![];
-    let mut longest = 0;
-    let mut start = 0;
-
-    for line in raw.split_terminator('\n') {
-        // Check for Gopher's weird "end of response" message.
-        if line == ".\r" || line == "." {
-            break;
-        }
-
-        if line.is_empty() {
-            start += 1;
-            continue;
-        }
-
-        if let Some(mut span) = parse_line(start, &raw) {
-            if span.text_len() > longest {
-                longest = span.text_len();
-            }
-            if span.typ.is_link() {
-                span.link = links.len();
-                links.push(spans.len());
-            }
-            spans.push(span);
-        }
-
-        start += line.len() + 1;
-    }
-
-    Menu {
+    let mut menu = Menu {
         url: url.into(),
-        spans,
-        links,
-        longest,
+        spans: vec![],
+        links: vec![],
+        longest: 0,
         raw,
         input: String::new(),
         link: 0,
@@ -981,10 +1120,17 @@ pub fn parse(url: &str, raw: String, config: Config) -> Menu {
         size: (0, 0),
         tls: false,
         tor: false,
+        cached: false,
         wide: false,
         scroll: 0,
+        lint: None,
+        parsed: 0,
+        primary: None,
+        ended: false,
         config,
-    }
+    };
+    menu.parse_lines();
+    menu
 }
 
 /// Parses a single line from a Gopher menu into a `LineSpan` struct.
@@ -1039,6 +1185,11 @@ pub fn parse_line(start: usize, raw: &str) -> Option<LineSpan> {
         }
     }
 
+    // Gopher+ items have an extra field after the port
+    let plus_field = line.trim_end_matches('\r').split('\t').nth(4);
+    let ask = plus_field == Some("?");
+    let plus = ask || plus_field == Some("+");
+
     Some(LineSpan {
         start,
         end,
@@ -1047,6 +1198,9 @@ pub fn parse_line(start: usize, raw: &str) -> Option<LineSpan> {
         visible_len,
         typ,
         link: 0,
+        plus,
+        ask,
+        mirror_of: None,
     })
 }
 
@@ -1177,6 +1331,156 @@ i	Err	bitreich.org	70
         assert_eq!(menu.link(menu.link).unwrap().link, 0);
     }
 
+    #[test]
+    fn test_append() {
+        let mut menu = parse!(
+            "iLoading...		error.host	1
+1First link	/one	gopher.example.com	70
+"
+        );
+        menu.term_size(80, 40);
+        assert_eq!(menu.links.len(), 1);
+
+        menu.append(
+            b"1Second link\t/two\tgopher.example.com\t70\r\n0Third\t/3\tgopher.example.com\t70\r\n",
+        );
+        assert_eq!(menu.links.len(), 3);
+        assert_eq!(menu.lines().count(), 4);
+        assert_eq!(
+            menu.link(1).unwrap().url(),
+            "gopher://gopher.example.com/1/two"
+        );
+        assert_eq!(menu.link(2).unwrap().text(), "Third");
+
+        // same as parsing it all at once
+        let whole = parse("", menu.raw.clone(), Config::default());
+        assert_eq!(menu.longest, whole.longest);
+        assert_eq!(menu.links, whole.links);
+        assert_eq!(
+            menu.lines()
+                .map(|l| l.text().to_string())
+                .collect::<Vec<_>>(),
+            whole
+                .lines()
+                .map(|l| l.text().to_string())
+                .collect::<Vec<_>>()
+        );
+
+        menu.append(b".\r\n1Ignored\t/x\tgopher.example.com\t70\r\n");
+        assert_eq!(menu.links.len(), 3);
+    }
+
+    #[test]
+    fn test_opaque_selectors() {
+        let menu = parse!(
+            "0Percent\t/100%09done?\texample.com\t70
+MMessage\tURL:gopher://example.com/M/list.mbox%093\texample.com\t70
+"
+        );
+        let url = menu.link(0).unwrap().url();
+        assert_eq!(url, "gopher://example.com/0/100%2509done%3F");
+        assert_eq!(gopher::parse_url(&url).selector_line(), "/100%09done?");
+
+        let url = menu.link(1).unwrap().url();
+        assert_eq!(url, "gopher://example.com/M/list.mbox%093");
+        assert_eq!(gopher::parse_url(&url).search.as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn test_gopher_plus() {
+        let mut menu = parse!(
+            "iGopher+ menu\t\terror.host\t1
+0About this server\t/about\tgopher.example.com\t70\t+
+1Plain old menu\t/plain\tgopher.example.com\t70
+7Search form\t/form\tgopher.example.com\t70\t?\r
+"
+        );
+        menu.term_size(80, 40);
+        assert_eq!(menu.links.len(), 3);
+
+        let about = menu.link(0).unwrap();
+        assert!(about.plus);
+        assert!(!about.ask);
+        assert_eq!(about.url(), "gopher://gopher.example.com/0/about");
+        assert!(!menu.link(1).unwrap().plus);
+        let form = menu.link(2).unwrap();
+        assert!(form.plus);
+        assert!(form.ask);
+
+        match menu.action_item_info() {
+            Action::Open(_, url) => {
+                assert_eq!(url, "gopher://gopher.example.com/0/about%09%09!")
+            }
+            a => panic!("expected Open, got {:?}", a),
+        }
+
+        menu.action_down();
+        assert!(matches!(menu.action_item_info(), Action::Error(_)));
+
+        menu.action_down();
+        match menu.action_open() {
+            Action::Open(_, url) => assert_eq!(url, "gopher://gopher.example.com/7/form%09%09?"),
+            a => panic!("expected Open, got {:?}", a),
+        }
+    }
+
+    #[test]
+    fn test_mirrors() {
+        let menu = parse!(
+            "1Software archive	/pub	ftp.example.edu	70
++Software archive	/mirror/pub	mirror.example.org	7070
++Software archive	/pub	backup.example.net	70
+0Readme	/README	ftp.example.edu	70
+iOrphaned mirror:		error.host	1
++Somewhere	/x	other.example.com	70
+"
+        );
+        assert_eq!(menu.spans.len(), 6);
+        assert_eq!(menu.links.len(), 3);
+        assert_eq!(menu.link(1).unwrap().text(), "Readme");
+
+        let mirror = menu.line(1).unwrap();
+        assert_eq!(mirror.mirror_of, Some(0));
+        assert_eq!(mirror.typ, Type::Menu);
+        assert_eq!(
+            mirror.url(),
+            "gopher://mirror.example.org:7070/1/mirror/pub"
+        );
+        assert_eq!(
+            menu.mirrors("gopher://ftp.example.edu/1/pub"),
+            vec![
+                "gopher://mirror.example.org:7070/1/mirror/pub",
+                "gopher://backup.example.net/1/pub"
+            ]
+        );
+        assert!(menu.mirrors("gopher://ftp.example.edu/0/README").is_empty());
+
+        let orphan = menu.link(2).unwrap();
+        assert_eq!(orphan.typ, Type::Mirror);
+        assert_eq!(orphan.mirror_of, None);
+    }
+
+    #[test]
+    fn test_lint() {
+        let mut menu = parse!("iHello\r\n0Broken\t/x\r\n1Fine\t/\texample.com\t70\r\n.\r\n");
+        menu.size = (80, 24);
+        assert!(!menu.render().contains("missing"));
+
+        match menu.respond(Key::Char('l')) {
+            Action::List(actions) => match &actions[1] {
+                Action::Status(msg) => assert_eq!(msg, "1 problem in this gophermap."),
+                _ => panic!("expected status"),
+            },
+            _ => panic!("expected list"),
+        }
+        let out = menu.render();
+        assert!(out.contains("Broken\x1b[0m  \x1b[91m! missing host and port\x1b[0m"));
+        assert_eq!(out.matches("! ").count(), 1);
+
+        menu.respond(Key::Ctrl('l'));
+        assert!(!menu.render().contains("missing"));
+    }
+
     #[test]
     fn test_color_lines() {
         let long_color_line = "ihi there. \x1b[1mthis\x1b[0m is a preeeeeety long line with \x1b[93mcolors \x1b[92mthat make it \x1b[91mseem longer than it is\x1b[0m	/kiosk	bitreich.org	70";
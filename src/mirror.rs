@@ -0,0 +1,796 @@
+//! `phetch --mirror URL` saves a copy of a gopherhole before it
+//! disappears. It crawls menus starting at URL, saving every menu,
+//! text file, and binary it finds into a directory tree, one folder
+//! per server:
+//!
+//!   example.com/gophermap           gopher://example.com/1/
+//!   example.com/docs/gophermap      gopher://example.com/1/docs
+//!   example.com/docs/notes.txt      gopher://example.com/0/docs/notes.txt
+//!
+//! Links to anything that was saved are rewritten to point at the
+//! copy, so `phetch --serve DIR` serves the mirror just like the
+//! original. A report of everything fetched, skipped, or broken goes
+//! in `report.txt`. Both are written as the crawl goes, so a mirror
+//! that gets interrupted still has everything it got to, with links
+//! to the original server.
+//!
+//! The crawler is polite: it stays on the starting server unless told
+//! otherwise, waits between requests, and follows each server's
+//! `robots.txt`. If a server has a `caps.txt`, its `PathDelimiter` is
+//! used to split selectors into folders.
+
+use crate::{
+    config::SharedConfig,
+    gopher::{self, clean_filename, Timeouts, Tls, Transport, Type, Url},
+    menu,
+    server::GOPHERMAP,
+    utils, Result,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How many menus deep to crawl by default.
+pub const DEFAULT_DEPTH: usize = 10;
+
+/// How long to wait between requests by default.
+pub const DEFAULT_DELAY: Duration = Duration::from_secs(1);
+
+/// Name we go by in robots.txt.
+const USER_AGENT: &str = "phetch";
+
+/// Where the report goes, inside the mirror directory.
+const REPORT: &str = "report.txt";
+
+/// `--mirror` options from the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// Directory to save the mirror in.
+    pub dir: PathBuf,
+    /// How many menus deep to go. The start menu is 0 deep, the menus
+    /// it links to are 1 deep, and so on. 0 = no limit
+    pub depth: usize,
+    /// How long to wait between requests.
+    pub delay: Duration,
+    /// Follow links to other servers?
+    pub all_hosts: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            dir: PathBuf::from("."),
+            depth: DEFAULT_DEPTH,
+            delay: DEFAULT_DELAY,
+            all_hosts: false,
+        }
+    }
+}
+
+/// What happened to one URL.
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    /// Saved to this path inside the mirror directory.
+    Saved {
+        /// Where it went, relative to the mirror directory.
+        path: PathBuf,
+        /// How big it was.
+        bytes: usize,
+    },
+    /// Not fetched, for this reason.
+    Skipped(String),
+    /// Couldn't be fetched.
+    Failed(String),
+}
+
+/// One URL the crawler came across.
+#[derive(Debug)]
+pub struct Entry {
+    /// Gopher URL.
+    pub url: String,
+    /// What happened to it.
+    pub status: Status,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.status {
+            Status::Saved { path, bytes } => write!(
+                f,
+                "saved    {} -> {} ({})",
+                self.url,
+                path.display(),
+                utils::human_bytes(*bytes)
+            ),
+            Status::Skipped(why) => write!(f, "skipped  {} ({})", self.url, why),
+            Status::Failed(why) => write!(f, "failed   {} ({})", self.url, why),
+        }
+    }
+}
+
+/// Everything the crawler did.
+#[derive(Debug)]
+pub struct Report {
+    /// Where it started.
+    pub url: String,
+    /// Every URL it came across, in order.
+    pub entries: Vec<Entry>,
+    /// How long it took.
+    pub elapsed: Duration,
+}
+
+impl Report {
+    /// How many entries were saved, skipped, and failed, and how many
+    /// bytes were saved.
+    pub fn summary(&self) -> String {
+        let (mut saved, mut skipped, mut failed, mut bytes) = (0, 0, 0, 0);
+        for entry in &self.entries {
+            match entry.status {
+                Status::Saved { bytes: b, .. } => {
+                    saved += 1;
+                    bytes += b;
+                }
+                Status::Skipped(_) => skipped += 1,
+                Status::Failed(_) => failed += 1,
+            }
+        }
+        format!(
+            "{} saved ({}), {} skipped, {} failed in {}s",
+            saved,
+            utils::human_bytes(bytes),
+            skipped,
+            failed,
+            self.elapsed.as_secs()
+        )
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "phetch mirror of {}\n", self.url)?;
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        writeln!(f, "\n{}", self.summary())
+    }
+}
+
+/// Mirror everything reachable from `url` into `settings.dir`, calling
+/// `log` with each URL as it's done.
+pub fn mirror(
+    url: &str,
+    settings: &Settings,
+    tls: Tls,
+    transport: &dyn Transport,
+    timeouts: Timeouts,
+    log: impl FnMut(&Entry),
+) -> Result<Report> {
+    let started = Instant::now();
+    let start = canonical(url);
+    fs::create_dir_all(&settings.dir)?;
+    let mut report = File::create(settings.dir.join(REPORT))?;
+    writeln!(report, "phetch mirror of {}\n", start)?;
+
+    let mut crawler = Crawler {
+        settings,
+        tls,
+        transport,
+        timeouts,
+        start,
+        hosts: HashMap::new(),
+        seen: HashSet::new(),
+        saved: HashMap::new(),
+        files: HashSet::new(),
+        dirs: HashSet::new(),
+        menus: vec![],
+        entries: vec![],
+        report,
+        last_request: None,
+        log,
+    };
+    let crawled = crawler.crawl();
+    let rewritten = crawler.rewrite_menus();
+
+    let report = Report {
+        url: crawler.start.to_string(),
+        entries: crawler.entries,
+        elapsed: started.elapsed(),
+    };
+    fs::write(settings.dir.join(REPORT), report.to_string())?;
+    crawled.and(rewritten)?;
+    Ok(report)
+}
+
+/// What we know about a server.
+#[derive(Debug, Default)]
+struct Host {
+    robots: Robots,
+    /// From caps.txt. Usually `/`.
+    delimiter: String,
+}
+
+/// State for one run of `mirror()`.
+struct Crawler<'a, F: FnMut(&Entry)> {
+    settings: &'a Settings,
+    tls: Tls,
+    transport: &'a dyn Transport,
+    timeouts: Timeouts,
+    start: Url,
+    /// Servers we've seen, by `host:port`.
+    hosts: HashMap<String, Host>,
+    /// URLs already crawled or waiting to be.
+    seen: HashSet<String>,
+    /// URLs we saved, and their selectors in the mirror.
+    saved: HashMap<String, String>,
+    /// Files and folders we've put in the mirror, so no two URLs end
+    /// up in the same place.
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+    /// Menus we saved, and their URLs, so their links can be rewritten
+    /// once we know what got saved.
+    menus: Vec<(PathBuf, String)>,
+    entries: Vec<Entry>,
+    /// `report.txt`, filled in as we go.
+    report: File,
+    last_request: Option<Instant>,
+    log: F,
+}
+
+impl<F: FnMut(&Entry)> Crawler<'_, F> {
+    /// Fetch everything, breadth first.
+    fn crawl(&mut self) -> Result<()> {
+        let start = self.start.to_string();
+        self.seen.insert(start.clone());
+        let mut queue = VecDeque::from(vec![(start, 0)]);
+
+        while let Some((url, depth)) = queue.pop_front() {
+            let u = canonical(&url);
+            let status = match self.skip_reason(&u, depth) {
+                Some(why) => Status::Skipped(why.into()),
+                None => match self.save(&u) {
+                    Ok((status, links)) => {
+                        for link in links {
+                            if self.seen.insert(link.clone()) {
+                                queue.push_back((link, depth + 1));
+                            }
+                        }
+                        status
+                    }
+                    Err(e) => Status::Failed(e.to_string()),
+                },
+            };
+            let entry = Entry { url, status };
+            (self.log)(&entry);
+            writeln!(self.report, "{}", entry)?;
+            self.entries.push(entry);
+        }
+        Ok(())
+    }
+
+    /// Why we shouldn't fetch `u`, if we shouldn't.
+    fn skip_reason(&mut self, u: &Url, depth: usize) -> Option<&'static str> {
+        if !self.settings.all_hosts && u.addr() != self.start.addr() {
+            Some("other server")
+        } else if u.typ == Type::Menu && self.settings.depth > 0 && depth > self.settings.depth {
+            // files in the deepest menus still get saved
+            Some("too deep")
+        } else if !self.host(u).robots.allows(&u.sel) {
+            Some("robots.txt")
+        } else {
+            None
+        }
+    }
+
+    /// What we know about `u`'s server, asking it for its robots.txt
+    /// and caps.txt the first time.
+    fn host(&mut self, u: &Url) -> &Host {
+        let addr = u.addr();
+        if !self.hosts.contains_key(&addr) {
+            let mut url = Url::new(Type::Text, &u.host, &u.port, "/robots.txt");
+            let robots = self.fetch(&url.to_string()).unwrap_or_default();
+            url.sel = "/caps.txt".into();
+            let caps = self.fetch(&url.to_string()).unwrap_or_default();
+            let host = Host {
+                robots: Robots::parse(&String::from_utf8_lossy(&robots)),
+                delimiter: caps_delimiter(&String::from_utf8_lossy(&caps)),
+            };
+            self.hosts.insert(addr.clone(), host);
+        }
+        &self.hosts[&addr]
+    }
+
+    /// Fetch a URL, waiting first if we asked its server for something
+    /// too recently.
+    fn fetch(&mut self, url: &str) -> Result<Vec<u8>> {
+        let addr = gopher::parse_url(url).addr();
+        let mut delay = self.settings.delay;
+        if let Some(crawl_delay) = self.hosts.get(&addr).and_then(|h| h.robots.delay) {
+            delay = delay.max(crawl_delay);
+        }
+        if let Some(last) = self.last_request {
+            if let Some(wait) = delay.checked_sub(last.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+        self.last_request = Some(Instant::now());
+        let (_, body) = gopher::fetch_url(url, self.tls, self.transport, self.timeouts)?;
+        Ok(body)
+    }
+
+    /// Fetch and save `u`. Returns what happened, and the links to
+    /// crawl next if it was a menu.
+    fn save(&mut self, u: &Url) -> Result<(Status, Vec<String>)> {
+        let url = u.to_string();
+        let mut body = self.fetch(&url)?;
+        if let Some(err) = gopher::error_response(&body) {
+            return Ok((Status::Failed(err), vec![]));
+        }
+
+        let (path, sel) = self.local_path(u);
+        let mut links = vec![];
+        if u.typ == Type::Menu {
+            let raw = String::from_utf8_lossy(&body).to_string();
+            let menu = menu::parse(&url, raw, SharedConfig::default());
+            for line in menu.lines() {
+                if line.typ.is_link() && line.mirror_of.is_none() && is_mirrorable(line.typ) {
+                    let link = canonical(&line.url());
+                    if link.search.is_none() && link.plus.is_none() {
+                        links.push(link.to_string());
+                    }
+                }
+            }
+            self.menus.push((path.clone(), url.clone()));
+        } else if u.typ.is_text() || matches!(u.typ, Type::Calendar | Type::Mailbox) {
+            strip_terminator(&mut body);
+        }
+        write_file(&self.settings.dir.join(&path), &body)?;
+
+        self.saved.insert(url, sel);
+        let bytes = body.len();
+        Ok((Status::Saved { path, bytes }, links))
+    }
+
+    /// Where `u` goes in the mirror, as a path relative to the mirror
+    /// directory and as a selector for `phetch --serve`.
+    fn local_path(&mut self, u: &Url) -> (PathBuf, String) {
+        let delimiter = self.host(u).delimiter.clone();
+        let host = if u.port == "70" {
+            clean_filename(&u.host)
+        } else {
+            clean_filename(&format!("{}_{}", u.host, u.port))
+        };
+        let parts = u
+            .sel
+            .split(delimiter.as_str())
+            .map(clean_filename)
+            .filter(|p| !p.is_empty())
+            .collect::<Vec<_>>();
+
+        let (dirs, file) = match (u.typ, parts.split_last()) {
+            (Type::Menu, _) => (&parts[..], None),
+            (_, Some((file, dirs))) => (dirs, Some(file.as_str())),
+            (_, None) => (&parts[..], Some("index")),
+        };
+
+        let mut path = PathBuf::from(&host);
+        let mut sel = format!("/{}", host);
+        self.dirs.insert(path.clone());
+        for part in dirs {
+            let part = self.free_name(&path, part, true);
+            path.push(&part);
+            sel.push('/');
+            sel.push_str(&part);
+            self.dirs.insert(path.clone());
+        }
+        match file {
+            None => path.push(GOPHERMAP),
+            Some(file) => {
+                let file = self.free_name(&path, file, false);
+                path.push(&file);
+                sel.push('/');
+                sel.push_str(&file);
+            }
+        }
+        self.files.insert(path.clone());
+        (path, sel)
+    }
+
+    /// `name`, or `name.2`, `name.3`, and so on, whichever is free in
+    /// `dir`. A text file `/foo` and a menu `/foo/bar` can't both have
+    /// `foo`, and only menus get to be a gophermap.
+    fn free_name(&self, dir: &Path, name: &str, is_dir: bool) -> String {
+        let mut free = name.to_string();
+        for n in 2.. {
+            let path = dir.join(&free);
+            let taken = if is_dir {
+                self.files.contains(&path)
+            } else {
+                free == GOPHERMAP || self.files.contains(&path) || self.dirs.contains(&path)
+            };
+            if !taken {
+                break;
+            }
+            free = format!("{}.{}", name, n);
+        }
+        free
+    }
+
+    /// Point the links in every menu we saved at our copies of
+    /// whatever they link to.
+    fn rewrite_menus(&mut self) -> Result<()> {
+        for (path, url) in &self.menus {
+            let full = self.settings.dir.join(path);
+            let raw = String::from_utf8_lossy(&fs::read(&full)?).to_string();
+            let menu = menu::parse(url, raw, SharedConfig::default());
+            let mut out = String::new();
+            for line in menu.lines() {
+                let raw_line = line.raw();
+                let url = line
+                    .typ
+                    .is_link()
+                    .then(|| canonical(&line.url()).to_string());
+                match url.and_then(|url| self.saved.get(&url)) {
+                    Some(sel) => {
+                        let name = raw_line.split('\t').next().unwrap_or("");
+                        out.push_str(&format!("{}\t{}\t\t\r\n", name, sel));
+                    }
+                    _ => {
+                        out.push_str(raw_line);
+                        out.push_str("\r\n");
+                    }
+                }
+            }
+
+            fs::write(full, out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `body` to `path`, making its folder first.
+fn write_file(path: &Path, body: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, body)?;
+    Ok(())
+}
+
+/// Parse a URL, treating a `/` selector for a menu the same as an
+/// empty one, so every link to the same page looks the same.
+fn canonical(url: &str) -> Url {
+    let mut u = gopher::parse_url(url);
+    if u.typ == Type::Menu && u.sel == "/" {
+        u.sel.clear();
+    }
+    u
+}
+
+/// Can we save this kind of item? Searches, telnet, and web links
+/// don't have anything to save.
+fn is_mirrorable(typ: Type) -> bool {
+    typ == Type::Menu
+        || typ.is_text()
+        || typ.is_download()
+        || matches!(typ, Type::Calendar | Type::Mailbox)
+}
+
+/// Drop Gopher's "end of response" line from a text file.
+fn strip_terminator(body: &mut Vec<u8>) {
+    if body.ends_with(b"\r\n.\r\n") || body.ends_with(b"\n.\n") {
+        body.truncate(body.len() - if body.ends_with(b"\r\n") { 3 } else { 2 });
+    } else if body == b".\r\n" || body == b".\n" {
+        body.clear();
+    }
+}
+
+/// The `PathDelimiter` from a caps.txt file, or `/`.
+fn caps_delimiter(caps: &str) -> String {
+    let mut lines = caps.lines().map(str::trim);
+    if lines.next() != Some("CAPS") {
+        return "/".into();
+    }
+    lines
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| l.split_once('='))
+        .find(|(k, _)| k.trim() == "PathDelimiter")
+        .map(|(_, v)| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "/".into())
+}
+
+/// The rules from a robots.txt file that apply to us.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Robots {
+    /// (selector prefix, allowed?)
+    rules: Vec<(String, bool)>,
+    /// How long to wait between requests.
+    delay: Option<Duration>,
+}
+
+impl Robots {
+    /// Parse a robots.txt file, keeping the rules for phetch if it
+    /// has any, otherwise the rules for everyone.
+    pub(crate) fn parse(txt: &str) -> Robots {
+        let mut ours = Robots::default();
+        let mut everyone = Robots::default();
+        let mut found_ours = false;
+        // which groups the current lines belong to: (us, everyone)
+        let mut group = (false, false);
+        let mut in_agents = false;
+
+        for line in txt.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let (key, value) = match line.split_once(':') {
+                Some((k, v)) => (k.trim().to_lowercase(), v.trim()),
+                None => continue,
+            };
+            if key == "user-agent" {
+                if !in_agents {
+                    group = (false, false);
+                }
+                in_agents = true;
+                if value.to_lowercase().contains(USER_AGENT) {
+                    group.0 = true;
+                    found_ours = true;
+                } else if value == "*" {
+                    group.1 = true;
+                }
+                continue;
+            }
+            in_agents = false;
+
+            let rule = match key.as_ref() {
+                "disallow" if !value.is_empty() => Some((normalize(value), false)),
+                "allow" => Some((normalize(value), true)),
+                "crawl-delay" => {
+                    let delay = value.parse::<f64>().ok().map(Duration::from_secs_f64);
+                    for (yes, robots) in [(group.0, &mut ours), (group.1, &mut everyone)] {
+                        if yes {
+                            robots.delay = delay;
+                        }
+                    }
+                    None
+                }
+                _ => None,
+            };
+            if let Some(rule) = rule {
+                for (yes, robots) in [(group.0, &mut ours), (group.1, &mut everyone)] {
+                    if yes {
+                        robots.rules.push(rule.clone());
+                    }
+                }
+            }
+        }
+
+        if found_ours {
+            ours
+        } else {
+            everyone
+        }
+    }
+
+    /// Can we fetch this selector? The longest matching rule wins.
+    pub(crate) fn allows(&self, sel: &str) -> bool {
+        let sel = normalize(sel);
+        self.rules
+            .iter()
+            .filter(|(prefix, _)| sel.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, allow)| (prefix.len(), *allow))
+            .is_none_or(|(_, allow)| *allow)
+    }
+}
+
+/// Selectors with and without a leading `/` usually mean the same
+/// thing, so always use one.
+fn normalize(sel: &str) -> String {
+    format!("/{}", sel.trim_start_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gopher::Memory, utils::TempDir};
+
+    #[test]
+    fn test_robots() {
+        let robots = Robots::parse(
+            "# hi\n\
+             User-agent: *\n\
+             Disallow: /cgi-bin\n\
+             Crawl-delay: 2\n\
+             \n\
+             User-agent: googlebot\n\
+             User-agent: phetch/1.2\n\
+             Disallow: /\n\
+             Allow: /pub # just this\n\
+             Allow: /pub/private/ok\n\
+             Disallow: /pub/private\n",
+        );
+        assert_eq!(robots.allows("/"), false);
+        assert_eq!(robots.allows("/pub/notes.txt"), true);
+        assert_eq!(robots.allows("pub"), true);
+        assert_eq!(robots.allows("/pub/private/diary"), false);
+        assert_eq!(robots.allows("/pub/private/ok"), true);
+        assert_eq!(robots.delay, None);
+
+        let robots = Robots::parse("User-agent: *\nDisallow: /cgi-bin\nCrawl-delay: 2\n");
+        assert_eq!(robots.allows("/"), true);
+        assert_eq!(robots.allows("cgi-bin/search"), false);
+        assert_eq!(robots.delay, Some(Duration::from_secs(2)));
+
+        assert_eq!(
+            Robots::parse("3Not found\t\terror.host\t1\r\n"),
+            Robots::default()
+        );
+        assert_eq!(
+            Robots::parse("User-agent: *\nDisallow:\n").allows("/"),
+            true
+        );
+    }
+
+    #[test]
+    fn test_caps_delimiter() {
+        assert_eq!(
+            caps_delimiter("CAPS\nCapsVersion=1\nPathDelimiter=\\\n"),
+            "\\"
+        );
+        assert_eq!(caps_delimiter("CAPS\n# PathDelimiter=:\n"), "/");
+        assert_eq!(caps_delimiter("PathDelimiter=:\n"), "/");
+        assert_eq!(caps_delimiter(""), "/");
+    }
+
+    #[test]
+    fn test_mirror() {
+        let dir = TempDir::new("mirror");
+        let net = Memory::new()
+            .page(
+                "example.com:70",
+                "",
+                "iWelcome\t\texample.com\t70\r\n\
+                 1Docs\t/docs\texample.com\t70\r\n\
+                 0Private\t/private/diary.txt\texample.com\t70\r\n\
+                 1Elsewhere\t/\tother.org\t70\r\n\
+                 7Search\t/search\texample.com\t70\r\n\
+                 .\r\n",
+            )
+            .page(
+                "example.com:70",
+                "/docs",
+                "0Notes\t/docs/notes.txt\texample.com\t70\r\n\
+                 9Game\t/docs/game.zip\texample.com\t70\r\n\
+                 0Gone\t/docs/gone.txt\texample.com\t70\r\n\
+                 1Home\t/\texample.com\t70\r\n",
+            )
+            .page("example.com:70", "/docs/notes.txt", "Some notes.\r\n.\r\n")
+            .page("example.com:70", "/docs/game.zip", "PK\u{3}\u{4}")
+            .page(
+                "example.com:70",
+                "/robots.txt",
+                "User-agent: *\nDisallow: /private\n",
+            );
+        let settings = Settings {
+            dir: dir.to_path_buf(),
+            delay: Duration::from_secs(0),
+            ..Default::default()
+        };
+
+        let mut logged = 0;
+        let url = "gopher://example.com/";
+        let report = mirror(url, &settings, Tls::Off, &net, Timeouts::default(), |_| {
+            logged += 1
+        })
+        .unwrap();
+        let lines = report
+            .entries
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "saved    gopher://example.com -> example.com/gophermap (160 bytes)",
+                "saved    gopher://example.com/1/docs -> example.com/docs/gophermap (137 bytes)",
+                "skipped  gopher://example.com/0/private/diary.txt (robots.txt)",
+                "skipped  gopher://other.org (other server)",
+                "saved    gopher://example.com/0/docs/notes.txt -> example.com/docs/notes.txt (13 bytes)",
+                "saved    gopher://example.com/9/docs/game.zip -> example.com/docs/game.zip (4 bytes)",
+                "failed   gopher://example.com/0/docs/gone.txt (Not found: /docs/gone.txt)",
+            ]
+        );
+        assert_eq!(logged, 7);
+        assert!(report
+            .summary()
+            .starts_with("4 saved (314 bytes), 2 skipped, 1 failed"));
+
+        let read = |path: &str| fs::read_to_string(dir.join(path)).unwrap();
+        assert_eq!(
+            read("example.com/gophermap"),
+            "iWelcome\t\texample.com\t70\r\n\
+             1Docs\t/example.com/docs\t\t\r\n\
+             0Private\t/private/diary.txt\texample.com\t70\r\n\
+             1Elsewhere\t/\tother.org\t70\r\n\
+             7Search\t/search\texample.com\t70\r\n"
+        );
+        assert_eq!(
+            read("example.com/docs/gophermap"),
+            "0Notes\t/example.com/docs/notes.txt\t\t\r\n\
+             9Game\t/example.com/docs/game.zip\t\t\r\n\
+             0Gone\t/docs/gone.txt\texample.com\t70\r\n\
+             1Home\t/example.com\t\t\r\n"
+        );
+        assert_eq!(read("example.com/docs/notes.txt"), "Some notes.\r\n");
+        assert!(read(REPORT).contains("skipped  gopher://other.org (other server)"));
+
+        // one level deep still saves what the first menus link to
+        let _ = fs::remove_dir_all(&dir);
+        let deep = Memory::new()
+            .page("example.com:70", "", "1One\t/1\texample.com\t70\r\n")
+            .page(
+                "example.com:70",
+                "/1",
+                "1Two\t/2\texample.com\t70\r\n0File\t/f\texample.com\t70\r\n",
+            )
+            .page("example.com:70", "/2", "iToo deep\r\n")
+            .page("example.com:70", "/f", "file\r\n");
+        let settings = Settings {
+            depth: 1,
+            ..settings
+        };
+        let report = mirror(url, &settings, Tls::Off, &deep, Timeouts::default(), |_| {}).unwrap();
+        let lines = report
+            .entries
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(lines[2], "skipped  gopher://example.com/1/2 (too deep)");
+        assert!(lines[3].starts_with("saved    gopher://example.com/0/f"));
+    }
+
+    #[test]
+    fn test_collisions() {
+        let dir = TempDir::new("collide");
+        let net = Memory::new()
+            .page(
+                "example.com:70",
+                "",
+                "0Foo\t/foo\texample.com\t70\r\n\
+                 1Foo menu\t/foo/bar\texample.com\t70\r\n\
+                 0Map\t/gophermap\texample.com\t70\r\n\
+                 0Slash\t/foo/\texample.com\t70\r\n",
+            )
+            .page("example.com:70", "/foo", "foo\r\n")
+            .page("example.com:70", "/foo/bar", "iBar\t\texample.com\t70\r\n")
+            .page("example.com:70", "/gophermap", "not a menu\r\n")
+            .page("example.com:70", "/foo/", "slash\r\n");
+        let settings = Settings {
+            dir: dir.to_path_buf(),
+            delay: Duration::from_secs(0),
+            ..Default::default()
+        };
+
+        let url = "gopher://example.com/";
+        mirror(url, &settings, Tls::Off, &net, Timeouts::default(), |_| {}).unwrap();
+        let read = |path: &str| fs::read_to_string(dir.join(path)).unwrap();
+        assert_eq!(read("example.com/foo"), "foo\r\n");
+        assert_eq!(
+            read("example.com/foo.2/bar/gophermap"),
+            "iBar\t\texample.com\t70\r\n"
+        );
+        assert_eq!(read("example.com/gophermap.2"), "not a menu\r\n");
+        assert_eq!(read("example.com/foo.3"), "slash\r\n");
+        assert_eq!(
+            read("example.com/gophermap"),
+            "0Foo\t/example.com/foo\t\t\r\n\
+             1Foo menu\t/example.com/foo.2/bar\t\t\r\n\
+             0Map\t/example.com/gophermap.2\t\t\r\n\
+             0Slash\t/example.com/foo.3\t\t\r\n"
+        );
+    }
+}
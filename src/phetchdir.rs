@@ -6,10 +6,10 @@
 //! This module provides helpers for working with the phetchdir:
 //! checking its existence, saving to files inside it, and the sort.
 
-use crate::gopher;
+use crate::{gopher, Result};
 use std::{
     fs::{File, OpenOptions},
-    io::{prelude::*, BufReader, Result, Write},
+    io::{prelude::*, BufReader, Write},
 };
 
 /// The directory where phetch stores its files. Ex: bookmarks file
@@ -42,16 +42,7 @@ pub fn append(filename: &str, label: &str, url: &str) -> Result<()> {
     path().and_then(|dotdir| {
         let path = dotdir.join(filename);
         if let Ok(mut file) = OpenOptions::new().append(true).create(true).open(path) {
-            let u = gopher::parse_url(url);
-            write!(
-                file,
-                "{}{}\t{}\t{}\t{}\r\n",
-                u.typ.to_char(),
-                label,
-                u.sel,
-                u.host,
-                u.port
-            )?;
+            file.write_all(menu_line(label, url).as_bytes())?;
             Ok(())
         } else {
             Err(error!("Can't open file for writing: {:?}", filename))
@@ -67,21 +58,13 @@ pub fn prepend(filename: &str, label: &str, url: &str) -> Result<()> {
             .write(true)
             .read(true)
             .create(true)
+            .truncate(false)
             .open(path)
         {
-            let url = gopher::parse_url(url);
             let mut buf = vec![];
             file.read_to_end(&mut buf)?;
             file.seek(std::io::SeekFrom::Start(0))?;
-            write!(
-                file,
-                "{}{}\t{}\t{}\t{}\r\n",
-                url.typ.to_char(),
-                label,
-                url.sel,
-                url.host,
-                url.port
-            )?;
+            file.write_all(menu_line(label, url).as_bytes())?;
             file.write_all(&buf)?;
             Ok(())
         } else {
@@ -90,6 +73,42 @@ pub fn prepend(filename: &str, label: &str, url: &str) -> Result<()> {
     })
 }
 
+/// Gophermap line linking to `url`. Finger URLs are saved as `URL:`
+/// links so they don't get mistaken for Gopher text files later, and
+/// so are URLs with a search or Gopher+ string, since a selector can't
+/// hold the tabs they need.
+fn menu_line(label: &str, url: &str) -> String {
+    let u = gopher::parse_url(url);
+    if url.starts_with("finger://") || u.search.is_some() || u.plus.is_some() {
+        return format!("h{}\tURL:{}\t\t\r\n", label, url);
+    }
+    format!(
+        "{}{}\t{}\t{}\t{}\r\n",
+        u.typ.to_char(),
+        label,
+        u.sel,
+        u.host,
+        u.port
+    )
+}
+
+/// Replace the contents of a file in the phetchdir.
+pub fn save(filename: &str, contents: &str) -> Result<()> {
+    path().and_then(|dotdir| {
+        let path = dotdir.join(filename);
+        if let Ok(mut file) = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+        {
+            Ok(file.write_all(contents.as_bytes())?)
+        } else {
+            Err(error!("Can't open file for writing: {:?}", filename))
+        }
+    })
+}
+
 /// Returns the full, expanded PathBuf of the phetchdir only if it exists.
 /// Returns None otherwise.
 /// If you just want the phetchdir path whether or not it exists, use
@@ -0,0 +1,401 @@
+//! `phetch --serve DIR` runs a little Gopher server for a directory,
+//! so you can preview gophermaps as you write them with `phetch -l`
+//! in another terminal. It only listens on 127.0.0.1.
+//!
+//! Directories with a `gophermap` file are served as that menu, in
+//! the Bucktooth/Gophernicus style: lines without a tab are info
+//! lines, selectors that don't start with `/` are relative to the
+//! gophermap's directory, missing hosts and ports mean this server,
+//! `.` ends the menu, and `*` ends it with a listing of the
+//! directory. Directories without one get a generated menu, with item
+//! types guessed from each file's extension. Everything else is sent
+//! as it is.
+
+use crate::{gopher::Type, Result};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+/// The port `phetch -l` expects a local server to be on.
+pub const DEFAULT_PORT: u16 = 7070;
+
+/// Directories with this file in them are served as its menu.
+pub(crate) const GOPHERMAP: &str = "gophermap";
+
+/// How long to wait for a client to send its selector.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest request we'll read, in bytes.
+const MAX_REQUEST: u64 = 4096;
+
+/// `--serve` options from the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// Directory to serve.
+    pub dir: PathBuf,
+    /// Port to listen on.
+    pub port: u16,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            dir: PathBuf::from("."),
+            port: DEFAULT_PORT,
+        }
+    }
+}
+
+/// Serve `settings.dir` on 127.0.0.1 until we're killed.
+pub fn serve(settings: &Settings) -> Result<()> {
+    let server = Server::bind(&settings.dir, &format!("127.0.0.1:{}", settings.port))?;
+    println!("Serving {} at {}", server.root.display(), server.url());
+    server.run()
+}
+
+/// A Gopher server for one directory.
+#[derive(Debug)]
+pub struct Server {
+    /// Directory we're serving, canonicalized.
+    root: PathBuf,
+    listener: TcpListener,
+    /// Host and port to put in menus.
+    host: String,
+    port: String,
+}
+
+impl Server {
+    /// Start listening on `addr` for requests for files in `dir`. Use
+    /// port 0 to pick any open port.
+    pub fn bind(dir: &Path, addr: &str) -> Result<Server> {
+        let root = dir
+            .canonicalize()
+            .map_err(|e| error!("Can't serve {}: {}", dir.display(), e))?;
+        if !root.is_dir() {
+            return Err(error!("Can't serve {}: Not a directory", dir.display()));
+        }
+        let listener =
+            TcpListener::bind(addr).map_err(|e| error!("Can't listen on {}: {}", addr, e))?;
+        let local = listener.local_addr()?;
+        Ok(Server {
+            root,
+            listener,
+            host: local.ip().to_string(),
+            port: local.port().to_string(),
+        })
+    }
+
+    /// Gopher URL for our root menu.
+    pub fn url(&self) -> String {
+        format!("gopher://{}:{}/", self.host, self.port)
+    }
+
+    /// Answer requests, each in its own thread, forever.
+    pub fn run(self) -> Result<()> {
+        let server = Arc::new(self);
+        for stream in server.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            };
+            let server = server.clone();
+            thread::spawn(move || {
+                if let Err(e) = server.handle(stream) {
+                    eprintln!("{}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Read one request from a client and send back the response.
+    fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut request = String::new();
+        BufReader::new(Read::by_ref(&mut stream).take(MAX_REQUEST)).read_line(&mut request)?;
+        let request = request.trim_end_matches(['\r', '\n']);
+        println!("{} {}", stream.peer_addr()?, request);
+        stream.write_all(&self.respond(request))?;
+        Ok(())
+    }
+
+    /// The response to a request: a menu, a file, or an error.
+    pub fn respond(&self, request: &str) -> Vec<u8> {
+        // ignore searches and Gopher+ strings
+        let sel = request.split('\t').next().unwrap_or("");
+        let path = match self.resolve(sel) {
+            Some(path) => path,
+            None => return error_menu(&format!("Not found: {}", sel)),
+        };
+        if path.is_dir() {
+            return self.menu(&path).into_bytes();
+        }
+        fs::read(&path).unwrap_or_else(|e| error_menu(&format!("{}: {}", sel, e)))
+    }
+
+    /// Find the file a selector points to, as long as it's inside
+    /// `root` and isn't hidden.
+    fn resolve(&self, sel: &str) -> Option<PathBuf> {
+        let mut path = self.root.clone();
+        for part in sel.split(['/', '\\']) {
+            match part {
+                "" | "." => {}
+                _ if part.starts_with('.') => return None,
+                _ => path.push(part),
+            }
+        }
+        // symlinks could point anywhere
+        let path = path.canonicalize().ok()?;
+        Some(path).filter(|p| p.starts_with(&self.root))
+    }
+
+    /// Selector for a file or directory inside `root`.
+    fn selector(&self, path: &Path) -> String {
+        let rel = path.strip_prefix(&self.root).unwrap_or(path);
+        rel.components()
+            .map(|c| format!("/{}", c.as_os_str().to_string_lossy()))
+            .collect()
+    }
+
+    /// The menu for a directory: its gophermap if it has one,
+    /// otherwise a listing of what's in it.
+    fn menu(&self, dir: &Path) -> String {
+        let mut out = match fs::read_to_string(dir.join(GOPHERMAP)) {
+            Ok(map) => self.gophermap(&map, dir),
+            Err(_) => self.listing(dir),
+        };
+        out.push_str(".\r\n");
+        out
+    }
+
+    /// Fill in the blanks in a gophermap.
+    fn gophermap(&self, map: &str, dir: &Path) -> String {
+        let dir_sel = self.selector(dir);
+        let mut out = String::new();
+
+        for line in map.lines() {
+            let line = line.trim_end_matches('\r');
+            if line == "." {
+                break;
+            } else if line == "*" {
+                out.push_str(&self.listing(dir));
+                break;
+            } else if !line.contains('\t') {
+                out.push_str(&format!("i{}\t\t{}\t{}\r\n", line, self.host, self.port));
+                continue;
+            }
+
+            let mut fields = line.splitn(5, '\t');
+            let name = fields.next().unwrap_or("");
+            let mut sel = fields.next().unwrap_or("").to_string();
+            let host = fields.next().unwrap_or("").trim();
+            let port = fields.next().unwrap_or("").trim();
+            let rest = fields.next();
+
+            let ours = host.is_empty();
+            let (host, port) = match (ours, port.is_empty()) {
+                (true, _) => (self.host.as_str(), self.port.as_str()),
+                (false, true) => (host, "70"),
+                (false, false) => (host, port),
+            };
+
+            // Bucktooth uses the name as the selector if there isn't one
+            let typ = name.chars().next().unwrap_or('i');
+            if sel.is_empty() && !matches!(typ, 'i' | '3') {
+                sel = name.chars().skip(1).collect();
+            }
+            if ours && !sel.is_empty() && !sel.starts_with('/') && !sel.starts_with("URL:") {
+                sel = format!("{}/{}", dir_sel, sel);
+            }
+
+            out.push_str(&format!("{}\t{}\t{}\t{}", name, sel, host, port));
+            if let Some(rest) = rest {
+                out.push_str(&format!("\t{}", rest));
+            }
+            out.push_str("\r\n");
+        }
+
+        out
+    }
+
+    /// A menu listing the files in a directory, folders first.
+    fn listing(&self, dir: &Path) -> String {
+        let mut entries = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| {
+                        let name = p.file_name().unwrap_or_default().to_string_lossy();
+                        !name.starts_with('.') && name != GOPHERMAP
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        entries.sort_by_key(|p| (!p.is_dir(), p.file_name().map(|n| n.to_ascii_lowercase())));
+
+        if entries.is_empty() {
+            return format!("i(empty)\t\t{}\t{}\r\n", self.host, self.port);
+        }
+        let mut out = String::new();
+        for path in entries {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let typ = type_for_file(&path);
+            out.push_str(&format!(
+                "{}{}{}\t{}\t{}\t{}\r\n",
+                typ.to_char(),
+                name,
+                if typ == Type::Menu { "/" } else { "" },
+                self.selector(&path),
+                self.host,
+                self.port
+            ));
+        }
+        out
+    }
+}
+
+/// A menu with just an error in it.
+fn error_menu(msg: &str) -> Vec<u8> {
+    format!("3{}\t\terror.host\t1\r\n.\r\n", msg).into_bytes()
+}
+
+/// Guess a file's Gopher type from its extension, or by peeking at
+/// it if that doesn't say.
+fn type_for_file(path: &Path) -> Type {
+    if path.is_dir() {
+        return Type::Menu;
+    }
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_ref() {
+        "txt" | "md" | "text" | "htm" | "html" | "csv" | "json" => Type::Text,
+        "xml" | "rss" | "atom" | "svg" => Type::Xml,
+        "gif" => Type::GIF,
+        "png" => Type::PNG,
+        "jpg" | "jpeg" | "bmp" | "webp" | "ico" | "tif" | "tiff" => Type::Image,
+        "mp3" | "wav" | "ogg" | "flac" | "m4a" | "mid" | "midi" => Type::Sound,
+        "mp4" | "mkv" | "webm" | "avi" | "mov" => Type::Video,
+        "pdf" | "ps" | "doc" | "docx" | "odt" | "rtf" | "epub" => Type::Document,
+        "ics" => Type::Calendar,
+        "mbox" => Type::Mailbox,
+        "uu" | "uue" => Type::UUEncoded,
+        "hqx" => Type::Binhex,
+        "exe" | "com" | "bat" | "zip" => Type::DOSFile,
+        _ if looks_like_text(path) => Type::Text,
+        _ => Type::Binary,
+    }
+}
+
+/// Is the start of this file UTF-8 without any NULs?
+fn looks_like_text(path: &Path) -> bool {
+    let mut buf = vec![];
+    match fs::File::open(path).and_then(|f| f.take(1024).read_to_end(&mut buf)) {
+        Ok(_) => match std::str::from_utf8(&buf) {
+            Ok(s) => !s.contains('\0'),
+            // the last character might be cut off
+            Err(e) => e.error_len().is_none() && !buf.contains(&0),
+        },
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gopher::{self, Network, Timeouts, Tls},
+        utils::TempDir,
+    };
+
+    /// A directory to serve, with a gophermap at the top and a
+    /// generated menu in `docs/`.
+    fn setup(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("serve-{}", name));
+        fs::create_dir_all(dir.join("docs/more")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(
+            dir.join(GOPHERMAP),
+            "Welcome!\n\
+             1Docs\tdocs\n\
+             0About\t/docs/about.txt\n\
+             0notes.txt\t\n\
+             1Elsewhere\t/\texample.com\n\
+             hWeb\tURL:https://example.com\n\
+             .\n\
+             ignored\n",
+        )
+        .unwrap();
+        fs::write(dir.join("docs/about.txt"), "All about it.\n").unwrap();
+        fs::write(dir.join("docs/map.xml"), "<a/>").unwrap();
+        fs::write(dir.join("docs/blob"), [0u8, 159, 146, 150]).unwrap();
+        fs::write(dir.join("docs/.secret"), "shh").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_respond() {
+        let dir = setup("respond");
+        let server = Server::bind(&dir, "127.0.0.1:0").unwrap();
+        let port = server.port.clone();
+        let get = |sel: &str| String::from_utf8_lossy(&server.respond(sel)).to_string();
+
+        assert_eq!(
+            get(""),
+            format!(
+                "iWelcome!\t\t127.0.0.1\t{port}\r\n\
+                 1Docs\t/docs\t127.0.0.1\t{port}\r\n\
+                 0About\t/docs/about.txt\t127.0.0.1\t{port}\r\n\
+                 0notes.txt\t/notes.txt\t127.0.0.1\t{port}\r\n\
+                 1Elsewhere\t/\texample.com\t70\r\n\
+                 hWeb\tURL:https://example.com\t127.0.0.1\t{port}\r\n\
+                 .\r\n",
+                port = port
+            )
+        );
+        assert_eq!(
+            get("/docs\tsearch"),
+            format!(
+                "1more/\t/docs/more\t127.0.0.1\t{port}\r\n\
+                 0about.txt\t/docs/about.txt\t127.0.0.1\t{port}\r\n\
+                 9blob\t/docs/blob\t127.0.0.1\t{port}\r\n\
+                 xmap.xml\t/docs/map.xml\t127.0.0.1\t{port}\r\n\
+                 .\r\n",
+                port = port
+            )
+        );
+        assert_eq!(get("/docs/more").starts_with("i(empty)"), true);
+        assert_eq!(get("/docs/about.txt"), "All about it.\n");
+        assert_eq!(get("docs/about.txt"), "All about it.\n");
+
+        for sel in ["/docs/../../etc/passwd", "/docs/.secret", "/.git", "/nope"] {
+            assert_eq!(get(sel).starts_with("3Not found"), true, "{}", sel);
+        }
+    }
+
+    #[test]
+    fn test_serve() {
+        let dir = setup("serve");
+        let server = Server::bind(&dir, "127.0.0.1:0").unwrap();
+        let url = format!("{}0/docs/about.txt", server.url());
+        thread::spawn(move || server.run());
+
+        let (_, res) =
+            gopher::fetch_url(&url, Tls::Off, &Network::default(), Timeouts::default()).unwrap();
+        assert_eq!(res, b"All about it.\n");
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(Server::bind(&dir, "127.0.0.1:0").is_err());
+    }
+}
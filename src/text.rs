@@ -5,11 +5,16 @@
 use crate::{
     config::SharedConfig as Config,
     encoding::Encoding,
+    gopher::{self, Type},
     terminal,
     ui::{self, Action, Key, View, MAX_COLS},
+    xml,
 };
 use std::{borrow::Cow, fmt, str};
 
+mod search;
+use self::search::{Prompt, Search};
+
 /// The Text View holds the raw Gopher response as well as information
 /// about which lines should currently be displayed on screen.
 pub struct Text {
@@ -19,6 +24,9 @@ pub struct Text {
     url: String,
     /// Gopher response
     raw_response: Vec<u8>,
+    /// What the server actually sent, if we're showing something
+    /// made from it instead, like a calendar's agenda.
+    source: Option<String>,
     /// Encoded response
     encoded_response: String,
     /// Current scroll offset, in rows
@@ -33,14 +41,24 @@ pub struct Text {
     pub tls: bool,
     /// Retrieved via Tor?
     pub tor: bool,
+    /// Served from the cache?
+    pub cached: bool,
     /// UI mode. Interactive (Run), Printing, Raw mode...
     mode: ui::Mode,
     /// Text Encoding of Response
     encoding: Encoding,
     /// Currently in wide mode?
     pub wide: bool,
+    /// Showing an XML document indented and highlighted?
+    pretty: bool,
     /// How many lines to scroll by. 0 = full screen
     scroll: usize,
+    /// Search being typed in with `/` or `?`, if any.
+    prompt: Option<Prompt>,
+    /// Last search. Its matches are highlighted until Esc.
+    search: Option<Search>,
+    /// Line we last jumped to with the search.
+    matched: Option<usize>,
 }
 
 impl fmt::Display for Text {
@@ -58,12 +76,33 @@ impl View for Text {
         self.tor
     }
 
+    fn is_cached(&self) -> bool {
+        self.cached
+    }
+
+    fn set_cached(&mut self, cached: bool) {
+        self.cached = cached;
+    }
+
+    fn append(&mut self, chunk: &[u8]) {
+        self.raw_response.extend_from_slice(chunk);
+        // pretty XML needs the whole document
+        if self.pretty {
+            self.encode_response();
+        } else {
+            self.encode_chunk(chunk);
+        }
+    }
+
     fn url(&self) -> &str {
         self.url.as_ref()
     }
 
     fn raw(&self) -> &str {
-        str::from_utf8(&self.raw_response).unwrap_or_default()
+        match &self.source {
+            Some(source) => source,
+            None => str::from_utf8(&self.raw_response).unwrap_or_default(),
+        }
     }
 
     fn term_size(&mut self, cols: usize, rows: usize) {
@@ -83,6 +122,29 @@ impl View for Text {
     }
 
     fn respond(&mut self, c: Key) -> Action {
+        if let Some(prompt) = &mut self.prompt {
+            match c {
+                Key::Char('\n') => return self.action_search(),
+                Key::Char(c) => {
+                    prompt.input.push(c);
+                    return Action::Status(prompt.render());
+                }
+                Key::Ctrl('r') => {
+                    prompt.regex = !prompt.regex;
+                    return Action::Status(prompt.render());
+                }
+                Key::Backspace | Key::Delete if !prompt.input.is_empty() => {
+                    prompt.input.pop();
+                    return Action::Status(prompt.render());
+                }
+                Key::Backspace | Key::Delete | Key::Esc | Key::Ctrl('c') => {
+                    self.prompt = None;
+                    return Action::Status(terminal::HideCursor.to_string());
+                }
+                _ => {}
+            }
+        }
+
         match c {
             Key::Home => {
                 self.offset = 0;
@@ -93,6 +155,23 @@ impl View for Text {
                 Action::Redraw
             }
             Key::Ctrl('e') | Key::Char('e') => self.toggle_encoding(),
+            Key::Ctrl('x') | Key::Char('x') if gopher::type_for_url(&self.url) == Type::Xml => {
+                self.set_pretty(!self.pretty);
+                Action::Redraw
+            }
+            Key::Char('/') | Key::Char('?') => {
+                let prompt = Prompt::new(c == Key::Char('?'));
+                let status = prompt.render();
+                self.prompt = Some(prompt);
+                Action::Status(status)
+            }
+            Key::Char('n') if self.search.is_some() => self.action_next(false),
+            Key::Char('N') if self.search.is_some() => self.action_next(true),
+            Key::Esc if self.search.is_some() => {
+                self.search = None;
+                self.matched = None;
+                Action::Redraw
+            }
             Key::Down | Key::Ctrl('n') | Key::Char('n') | Key::Ctrl('j') | Key::Char('j') => {
                 if self.offset < self.final_scroll() {
                     self.offset += 1;
@@ -135,7 +214,8 @@ impl View for Text {
     fn render(&mut self) -> String {
         let (_cols, rows) = self.size;
         let mut out = String::new();
-        let wrap = self.config.read().unwrap().wrap;
+        let config = self.config.read().unwrap();
+        let wrap = config.wrap;
         let indent = self.indent_str(wrap);
         let limit = if self.mode == ui::Mode::Run {
             rows - 1
@@ -143,12 +223,16 @@ impl View for Text {
             self.lines
         };
 
-        let iter = wrap_text(&self.encoded_response, wrap)
-            .into_iter()
-            .skip(self.offset)
-            .take(limit);
+        let lines = wrap_text(&self.encoded_response, wrap);
+        // highlighting a line depends on the ones before it
+        let mut highlighter = xml::Highlighter::default();
+        if self.pretty {
+            for line in lines.iter().take(self.offset) {
+                highlighter.line(line, &config.theme);
+            }
+        }
 
-        for line in iter {
+        for line in lines.into_iter().skip(self.offset).take(limit) {
             // Check for Gopher's weird "end of response" line.
             if line == ".\r" || line == "." {
                 continue;
@@ -157,7 +241,17 @@ impl View for Text {
                 out.push_str(&indent);
             }
             let line = line.trim_end_matches('\r').replace('\t', "    ");
-            out.push_str(&line);
+            let matches = self
+                .search
+                .as_ref()
+                .and_then(|search| search.highlight(&line, &config.theme.ui_match));
+            if self.pretty {
+                // keep feeding the highlighter, even when a match wins
+                let pretty = highlighter.line(&line, &config.theme);
+                out.push_str(matches.as_ref().unwrap_or(&pretty));
+            } else {
+                out.push_str(matches.as_ref().unwrap_or(&line));
+            }
 
             // clear rest of line
             out.push_str(&format!("{}", terminal::ClearUntilNewline));
@@ -186,6 +280,7 @@ impl Text {
             url: url.into(),
             encoded_response: String::new(),
             raw_response: response,
+            source: None,
             offset: 0,
             lines: 0,
             longest: 0,
@@ -193,14 +288,121 @@ impl Text {
             mode,
             tls,
             tor,
+            cached: false,
             encoding,
             wide,
+            pretty: false,
             scroll,
+            prompt: None,
+            search: None,
+            matched: None,
         };
         new.encode_response();
         new
     }
 
+    /// Show `response` like `from()`, but keep the server's original
+    /// `source` around for the raw view (`r`).
+    pub fn from_source(
+        url: &str,
+        response: Vec<u8>,
+        source: String,
+        config: Config,
+        tls: bool,
+    ) -> Text {
+        let mut text = Text::from(url, response, config, tls);
+        text.source = Some(source);
+        text
+    }
+
+    /// Indent and highlight an XML document, or show it as it came.
+    pub fn set_pretty(&mut self, pretty: bool) {
+        self.pretty = pretty;
+        self.matched = None;
+        self.encode_response();
+        self.offset = self.offset.min(self.final_scroll());
+    }
+
+    /// Run the search that was just typed in. Searching for nothing
+    /// searches for the last thing again, in the new direction.
+    fn action_search(&mut self) -> Action {
+        let prompt = match self.prompt.take() {
+            Some(prompt) => prompt,
+            None => return Action::None,
+        };
+        let search = match (&self.search, prompt.input.is_empty()) {
+            (Some(last), true) => Search::new(&last.query, last.regex, prompt.backward),
+            (None, true) => return Action::Status(terminal::HideCursor.to_string()),
+            (_, false) => Search::new(&prompt.input, prompt.regex, prompt.backward),
+        };
+        match search {
+            Ok(search) => {
+                self.search = Some(search);
+                self.matched = None;
+                self.action_next(false)
+            }
+            Err(e) => Action::Error(e),
+        }
+    }
+
+    /// Jump to the next match, or the previous one if `reverse`. The
+    /// first jump of a search starts from what's on screen.
+    fn action_next(&mut self, reverse: bool) -> Action {
+        let search = match &self.search {
+            Some(search) => search,
+            None => return Action::None,
+        };
+        let lines = self.search_lines();
+        let from = match self.matched {
+            Some(line) => Some(line),
+            None if search.backward != reverse => Some(self.offset + self.rows()),
+            None => self.offset.checked_sub(1),
+        };
+
+        let (line, wrapped) = match search.next_line(&lines, from, reverse) {
+            Some(found) => found,
+            None => {
+                let msg = format!("No matches for: {}", search.query);
+                self.search = None;
+                self.matched = None;
+                return Action::List(vec![Action::Redraw, Action::Error(msg)]);
+            }
+        };
+        let status = if wrapped {
+            if search.backward == reverse {
+                "Search hit bottom, continuing at top.".into()
+            } else {
+                "Search hit top, continuing at bottom.".into()
+            }
+        } else {
+            let (n, total) = search.count(&lines, line);
+            format!("{}  ({} of {})", search, n, total)
+        };
+
+        self.matched = Some(line);
+        if line < self.offset || line >= self.offset + self.rows() {
+            self.offset = line.min(self.final_scroll());
+        }
+        Action::List(vec![Action::Redraw, Action::Status(status)])
+    }
+
+    /// The lines we search, wrapped and cleaned up just like we draw
+    /// them, so a match's line is a row on screen.
+    fn search_lines(&self) -> Vec<String> {
+        wrap_text(&self.encoded_response, self.config.read().unwrap().wrap)
+            .into_iter()
+            .map(|line| match line {
+                ".\r" | "." => String::new(),
+                _ => line.trim_end_matches('\r').replace('\t', "    "),
+            })
+            .collect()
+    }
+
+    /// How many rows of text fit on screen.
+    fn rows(&self) -> usize {
+        self.size.1.saturating_sub(1)
+    }
+
     /// Toggle between our two encodings.
     fn toggle_encoding(&mut self) -> Action {
         if matches!(self.encoding, Encoding::UTF8) {
@@ -216,23 +418,43 @@ impl Text {
     /// Convert the response to a Rust String and cache metadata like
     /// the number of lines.
     fn encode_response(&mut self) {
-        self.encoded_response = self.encoding.encode(&self.raw_response).into();
+        let encoded = self.encoding.encode(&self.raw_response);
+        self.encoded_response = if self.pretty {
+            xml::pretty(&encoded)
+        } else {
+            encoded.into()
+        };
         let wrapped = wrap_text(
             self.encoded_response.as_ref(),
             self.config.read().unwrap().wrap,
         );
         self.lines = wrapped.len();
-        self.longest = wrapped.iter().map(|line| line.len()).max().unwrap_or(0) as usize;
+        self.longest = wrapped.iter().map(|line| line.len()).max().unwrap_or(0);
+    }
+
+    /// Encode just the newly arrived `chunk` and add it to the end of
+    /// the response. Loaders only send whole lines, so the lines before
+    /// it don't change.
+    fn encode_chunk(&mut self, chunk: &[u8]) {
+        let encoded = self.encoding.encode(chunk);
+        let wrap = self.config.read().unwrap().wrap;
+        let wrapped = wrap_text(&encoded, wrap);
+        // splitting on \n always leaves one more piece than there are
+        // line breaks, and we've already counted that one
+        self.lines += if wrap == 0 {
+            encoded.matches('\n').count()
+        } else {
+            wrapped.len()
+        };
+        let longest = wrapped.iter().map(|line| line.len()).max().unwrap_or(0);
+        self.longest = self.longest.max(longest);
+        self.encoded_response.push_str(&encoded);
     }
 
     /// Final `self.scroll` value.
     fn final_scroll(&self) -> usize {
         let padding = (self.size.1 as f64 * 0.9) as usize;
-        if self.lines > padding {
-            self.lines - padding
-        } else {
-            0
-        }
+        self.lines.saturating_sub(padding)
     }
 
     /// How many lines to scroll by when paging up or down.
@@ -262,7 +484,7 @@ impl Text {
 
     /// Produce the string to use for indentation, or the left margin,
     /// for a given text document.
-    fn indent_str(&self, wrap: usize) -> Cow<str> {
+    fn indent_str(&self, wrap: usize) -> Cow<'_, str> {
         let (cols, _) = self.size;
         let longest = self.longest_line_with_wrap(wrap);
 
@@ -279,7 +501,7 @@ impl Text {
 /// Splits a chunk of text into a vector of strings with at most
 /// `wrap` characters each. Tries to be smart and wrap at punctuation,
 /// otherwise just wraps at `wrap`.
-fn wrap_text(lines: &str, wrap: usize) -> Vec<&str> {
+pub(crate) fn wrap_text(lines: &str, wrap: usize) -> Vec<&str> {
     if wrap == 0 {
         return lines.split('\n').collect();
     }
@@ -351,6 +573,120 @@ mod test {
         assert!(res.contains("Θ"));
     }
 
+    #[test]
+    fn test_append() {
+        let mut text = Text::from("", b"one\ntwo\n".to_vec(), Config::default(), false);
+        text.mode = ui::Mode::Print;
+        let lines = text.lines;
+
+        text.append(b"three\n");
+        assert_eq!(text.lines, lines + 1);
+        assert_eq!(text.raw(), "one\ntwo\nthree\n");
+        assert!(text.render().contains("three"));
+
+        // same as loading it all at once, wrapped or not
+        for wrap in [0, 4] {
+            let config = Config::default();
+            config.write().unwrap().wrap = wrap;
+            let mut text = Text::from("", b"one\n".to_vec(), config.clone(), false);
+            text.append(b"two\nthree\n");
+            text.append(b"fourteen\n");
+            let whole = Text::from("", text.raw_response.clone(), config, false);
+            assert_eq!(text.encoded_response, whole.encoded_response);
+            assert_eq!(text.lines, whole.lines);
+            assert_eq!(text.longest, whole.longest);
+        }
+    }
+
+    #[test]
+    fn test_pretty() {
+        let xml = b"<a><b>c</b></a>\n".to_vec();
+        let url = "gopher://example.com/x/map.xml";
+        let mut text = Text::from(url, xml, Config::default(), false);
+        text.mode = ui::Mode::Print;
+        assert_eq!(text.lines, 2);
+
+        text.set_pretty(true);
+        assert_eq!(text.lines, 4);
+        assert_eq!(text.raw(), "<a><b>c</b></a>\n");
+        assert!(text.encoded_response.contains("\n  <b>c</b>\n"));
+        let res = text.render();
+        assert!(res.contains(&text.config.read().unwrap().theme.xml_tag));
+
+        text.respond(Key::Char('x'));
+        assert_eq!(text.lines, 2);
+    }
+
+    #[test]
+    fn test_search() {
+        let body = (1..=30)
+            .map(|i| match i {
+                3 => "a Gopher hole\n".to_string(),
+                20 => "two gophers, one GOPHER\n".to_string(),
+                _ => format!("line {}\n", i),
+            })
+            .collect::<String>();
+        let mut text = Text::from("", body.into_bytes(), Config::default(), false);
+        text.term_size(80, 10);
+        let color = text.config.read().unwrap().theme.ui_match.clone();
+
+        for key in "/gopher".chars() {
+            text.respond(Key::Char(key));
+        }
+        assert_eq!(text.search.is_none(), true);
+        text.respond(Key::Char('\n'));
+        assert_eq!(text.matched, Some(2));
+        assert_eq!(text.offset, 0);
+        assert!(text
+            .render()
+            .contains(&format!("a {}Gopher\x1b[0m hole", color)));
+
+        // jumps the screen to matches that aren't on it
+        text.respond(Key::Char('n'));
+        assert_eq!(text.matched, Some(19));
+        assert_eq!(text.offset, 19);
+        let res = text.render();
+        assert_eq!(res.matches(&color).count(), 2);
+        assert!(!res.contains("Gopher hole"));
+
+        text.respond(Key::Char('n'));
+        assert_eq!(text.matched, Some(2));
+        assert_eq!(text.offset, 2);
+        text.respond(Key::Char('N'));
+        assert_eq!(text.matched, Some(19));
+
+        // uppercase matches exactly, ? searches up
+        for key in "?Gopher\n".chars() {
+            text.respond(Key::Char(key));
+        }
+        assert_eq!(text.matched, Some(2));
+        assert_eq!(text.search.as_ref().unwrap().backward, true);
+
+        // ctrl-r for a regex
+        text.respond(Key::Char('/'));
+        text.respond(Key::Ctrl('r'));
+        for key in r"line \d5".chars() {
+            text.respond(Key::Char(key));
+        }
+        text.respond(Key::Char('\n'));
+        assert_eq!(text.matched, Some(14));
+        assert_eq!(text.search.as_ref().unwrap().regex, true);
+        assert!(matches!(text.respond(Key::Char('n')), Action::List(_)));
+        assert_eq!(text.matched, Some(24));
+
+        // esc clears the search, and n scrolls again
+        text.respond(Key::Esc);
+        assert_eq!(text.render().contains(&color), false);
+        text.respond(Key::Home);
+        text.respond(Key::Char('n'));
+        assert_eq!(text.offset, 1);
+
+        for key in "/nope\n".chars() {
+            text.respond(Key::Char(key));
+        }
+        assert_eq!(text.search.is_none(), true);
+    }
+
     #[test]
     fn test_wrapping() {
         let text = "regular line
@@ -0,0 +1,237 @@
+//! Searching a Text view with `/` and `?`. A search is plain text
+//! unless you ask for a regex, and it's "smart case": all lowercase
+//! ignores case, any uppercase matches it exactly.
+
+use crate::terminal;
+use regex_lite::{Regex, RegexBuilder};
+use std::fmt;
+
+/// A search being typed in at the bottom of the screen.
+#[derive(Debug, Default)]
+pub(crate) struct Prompt {
+    /// What they've typed so far.
+    pub(crate) input: String,
+    /// Typing a regex? Ctrl-r toggles it.
+    pub(crate) regex: bool,
+    /// Started with `?` instead of `/`.
+    pub(crate) backward: bool,
+}
+
+impl Prompt {
+    /// Start typing a search.
+    pub(crate) fn new(backward: bool) -> Prompt {
+        Prompt {
+            backward,
+            ..Default::default()
+        }
+    }
+
+    /// The status line, with a cursor to type at.
+    pub(crate) fn render(&self) -> String {
+        format!(
+            "{}{}",
+            label(&self.input, self.regex, self.backward),
+            terminal::ShowCursor
+        )
+    }
+}
+
+/// A search the user typed in, ready to find things with.
+#[derive(Debug)]
+pub(crate) struct Search {
+    /// What they typed.
+    pub(crate) query: String,
+    /// Was it a regex?
+    pub(crate) regex: bool,
+    /// `?` searches up the page, `/` searches down.
+    pub(crate) backward: bool,
+    pattern: Regex,
+}
+
+impl Search {
+    /// Compile a search. Errors if it's a regex that doesn't parse.
+    pub(crate) fn new(query: &str, regex: bool, backward: bool) -> Result<Search, String> {
+        let pattern = if regex {
+            query.to_string()
+        } else {
+            regex_lite::escape(query)
+        };
+        let ignore_case = !query.chars().any(char::is_uppercase);
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|e| format!("Bad regex: {}", e.to_string().lines().last().unwrap_or("")))?;
+        Ok(Search {
+            query: query.into(),
+            regex,
+            backward,
+            pattern,
+        })
+    }
+
+    /// Where this search matches `line`, as byte ranges. Empty matches
+    /// (like `^`) don't count, since there'd be nothing to highlight.
+    pub(crate) fn find(&self, line: &str) -> Vec<(usize, usize)> {
+        self.pattern
+            .find_iter(line)
+            .filter(|m| !m.is_empty())
+            .map(|m| (m.start(), m.end()))
+            .collect()
+    }
+
+    /// Does this search match `line` at all?
+    pub(crate) fn is_match(&self, line: &str) -> bool {
+        !self.find(line).is_empty()
+    }
+
+    /// The next line that matches after `from` (or before it, going
+    /// up), wrapping around the ends, and whether we wrapped to get
+    /// there. `from` of None starts from the top (or bottom, going up).
+    /// `reverse` goes the other way, for `N`.
+    pub(crate) fn next_line(
+        &self,
+        lines: &[String],
+        from: Option<usize>,
+        reverse: bool,
+    ) -> Option<(usize, bool)> {
+        let len = lines.len();
+        let from = from.filter(|&i| i < len);
+        let down = self.backward == reverse;
+        let start = match (down, from) {
+            (true, Some(i)) => i + 1,
+            (true, None) => 0,
+            (false, Some(i)) => i + len - 1,
+            (false, None) => len.saturating_sub(1),
+        };
+        (0..len)
+            .map(|i| {
+                if down {
+                    (start + i) % len
+                } else {
+                    (start % len + len - i) % len
+                }
+            })
+            .find(|&i| self.is_match(&lines[i]))
+            .map(|i| {
+                let wrapped = from.is_some_and(|from| if down { i <= from } else { i >= from });
+                (i, wrapped)
+            })
+    }
+
+    /// Which match this is and how many there are, counting every match
+    /// on every line: (3, 12) is "3 of 12". Counts from the first match
+    /// on `line`.
+    pub(crate) fn count(&self, lines: &[String], line: usize) -> (usize, usize) {
+        let before = lines[..line]
+            .iter()
+            .map(|l| self.find(l).len())
+            .sum::<usize>();
+        let after = lines[line..]
+            .iter()
+            .map(|l| self.find(l).len())
+            .sum::<usize>();
+        (before + 1, before + after)
+    }
+
+    /// Draw `line` with every match wrapped in `color`.
+    pub(crate) fn highlight(&self, line: &str, color: &str) -> Option<String> {
+        let matches = self.find(line);
+        if matches.is_empty() {
+            return None;
+        }
+        let mut out = String::new();
+        let mut last = 0;
+        for (start, end) in matches {
+            out.push_str(&line[last..start]);
+            out.push_str(color);
+            out.push_str(&line[start..end]);
+            out.push_str(reset_color!());
+            last = end;
+        }
+        out.push_str(&line[last..]);
+        Some(out)
+    }
+}
+
+impl fmt::Display for Search {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", label(&self.query, self.regex, self.backward))
+    }
+}
+
+/// How a search looks on the status line: `/gopher`, `?gopher`, or
+/// `regex /go+pher`.
+fn label(query: &str, regex: bool, backward: bool) -> String {
+    format!(
+        "{}{}{}",
+        if regex { "regex " } else { "" },
+        if backward { '?' } else { '/' },
+        query
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let search = Search::new("gopher", false, false).unwrap();
+        assert_eq!(
+            search.find("Gopher, gopher, GOPHER!"),
+            [(0, 6), (8, 14), (16, 22)]
+        );
+
+        // any uppercase means match case exactly
+        let search = Search::new("Gopher", false, false).unwrap();
+        assert_eq!(search.find("Gopher, gopher, GOPHER!"), [(0, 6)]);
+
+        // plain text searches don't have special characters
+        let search = Search::new("a.c (1)", false, false).unwrap();
+        assert_eq!(search.is_match("abc (1)"), false);
+        assert_eq!(search.is_match("a.c (1)"), true);
+
+        let search = Search::new(r"\d+ (cats|dogs)", true, false).unwrap();
+        assert_eq!(search.find("1 cat, 22 dogs, 333 cats"), [(7, 14), (16, 24)]);
+        assert_eq!(Search::new("^", true, false).unwrap().find("hi"), []);
+        assert!(Search::new("(oops", true, false)
+            .unwrap_err()
+            .starts_with("Bad regex: "));
+
+        let search = Search::new("x", false, false).unwrap();
+        assert_eq!(
+            search.highlight("axbx", "<>").unwrap(),
+            "a<>x\x1b[0mb<>x\x1b[0m"
+        );
+        assert_eq!(search.highlight("abc", "<>"), None);
+    }
+
+    #[test]
+    fn test_next_line() {
+        let text = lines("x\n-\nx\n-\n-\nx");
+        let down = Search::new("x", false, false).unwrap();
+        assert_eq!(down.next_line(&text, None, false), Some((0, false)));
+        assert_eq!(down.next_line(&text, Some(0), false), Some((2, false)));
+        assert_eq!(down.next_line(&text, Some(2), false), Some((5, false)));
+        assert_eq!(down.next_line(&text, Some(5), false), Some((0, true)));
+        assert_eq!(down.next_line(&text, Some(5), true), Some((2, false)));
+        assert_eq!(down.next_line(&text, Some(0), true), Some((5, true)));
+        assert_eq!(down.count(&text, 2), (2, 3));
+
+        let up = Search::new("x", false, true).unwrap();
+        assert_eq!(up.next_line(&text, None, false), Some((5, false)));
+        assert_eq!(up.next_line(&text, Some(5), false), Some((2, false)));
+        assert_eq!(up.next_line(&text, Some(0), false), Some((5, true)));
+        assert_eq!(up.next_line(&text, Some(0), true), Some((2, false)));
+        assert_eq!(up.to_string(), "?x");
+
+        let none = Search::new("y", true, false).unwrap();
+        assert_eq!(none.next_line(&text, Some(1), false), None);
+        assert_eq!(none.next_line(&[], None, false), None);
+        assert_eq!(none.to_string(), "regex /y");
+    }
+}
@@ -35,6 +35,8 @@ pub struct Theme {
     pub ui_menu: String,
     /// The color of the text content in a document.
     pub ui_text: String,
+    /// Search matches in a document.
+    pub ui_match: String,
 
     // Menu Item Colors
     /// Text document.
@@ -55,6 +57,22 @@ pub struct Theme {
     pub item_media: String,
     /// An unknown or unsupported Gopher type.
     pub item_unsupported: String,
+
+    // XML Colors
+    /// Tag names and brackets in an XML document.
+    pub xml_tag: String,
+    /// Attribute names.
+    pub xml_attr: String,
+    /// Attribute values.
+    pub xml_value: String,
+    /// Text between tags.
+    pub xml_text: String,
+    /// `<!-- comments -->`
+    pub xml_comment: String,
+
+    // Gemtext Colors
+    /// `>` quote lines.
+    pub gemtext_quote: String,
 }
 
 impl Default for Theme {
@@ -64,6 +82,7 @@ impl Default for Theme {
             ui_number: to_color("magenta"),
             ui_menu: to_color("yellow"),
             ui_text: to_color("white"),
+            ui_match: to_color("yellowbg black"),
 
             item_text: to_color("cyan"),
             item_menu: to_color("blue"),
@@ -74,6 +93,14 @@ impl Default for Theme {
             item_download: to_color("white underline"),
             item_media: to_color("green underline"),
             item_unsupported: to_color("whitebg red"),
+
+            xml_tag: to_color("blue"),
+            xml_attr: to_color("cyan"),
+            xml_value: to_color("green"),
+            xml_text: to_color("white"),
+            xml_comment: to_color("grey"),
+
+            gemtext_quote: to_color("grey"),
         }
     }
 }
@@ -87,6 +114,7 @@ ui.cursor {ui_cursor}
 ui.number {ui_number}
 ui.menu {ui_menu}
 ui.text {ui_text}
+ui.match {ui_match}
 
 item.text {item_text}
 item.menu {item_menu}
@@ -96,11 +124,20 @@ item.telnet {item_telnet}
 item.external {item_external}
 item.download {item_download}
 item.media {item_media}
-item.unsupported {item_unsupported}",
+item.unsupported {item_unsupported}
+
+xml.tag {xml_tag}
+xml.attr {xml_attr}
+xml.value {xml_value}
+xml.text {xml_text}
+xml.comment {xml_comment}
+
+gemtext.quote {gemtext_quote}",
             ui_cursor = to_words(&self.ui_cursor),
             ui_number = to_words(&self.ui_number),
             ui_menu = to_words(&self.ui_menu),
             ui_text = to_words(&self.ui_text),
+            ui_match = to_words(&self.ui_match),
             item_text = to_words(&self.item_text),
             item_menu = to_words(&self.item_menu),
             item_error = to_words(&self.item_error),
@@ -110,6 +147,12 @@ item.unsupported {item_unsupported}",
             item_download = to_words(&self.item_download),
             item_media = to_words(&self.item_media),
             item_unsupported = to_words(&self.item_unsupported),
+            xml_tag = to_words(&self.xml_tag),
+            xml_attr = to_words(&self.xml_attr),
+            xml_value = to_words(&self.xml_value),
+            xml_text = to_words(&self.xml_text),
+            xml_comment = to_words(&self.xml_comment),
+            gemtext_quote = to_words(&self.gemtext_quote),
         )
     }
 }
@@ -221,10 +264,12 @@ mod tests {
 
     #[test]
     fn test_color_scheme() {
-        let mut theme = Theme::default();
-        theme.ui_cursor = to_color("bold").into();
-        theme.ui_menu = to_color("red").into();
-        theme.item_menu = to_color("blue underline").into();
+        let theme = Theme {
+            ui_cursor: to_color("bold"),
+            ui_menu: to_color("red"),
+            item_menu: to_color("blue underline"),
+            ..Default::default()
+        };
 
         assert_eq!("\u{1b}[1m", theme.ui_cursor);
         assert_eq!("\u{1b}[91m", theme.ui_menu);
@@ -12,26 +12,38 @@
 //! renders its content to a String. The UI is what draws it.
 
 mod action;
+mod downloads;
+mod loader;
 mod mode;
 mod view;
 pub use self::{action::Action, mode::Mode, view::View};
+use self::{
+    downloads::Downloads,
+    loader::{Chunk, Loader},
+};
 
 use crate::{
-    bookmarks,
+    bookmarks, cache, calendar,
     config::{Config, SharedConfig},
     encoding::Encoding,
-    gopher::{self, Type},
-    help, history,
+    gopher::{
+        self,
+        plus::{AskKind, Attributes, Question},
+        Transport, Type,
+    },
+    help, history, known_hosts, mailbox,
     menu::Menu,
     terminal,
     text::Text,
-    theme, utils, BUG_URL,
+    theme, utils, Error, Result, BUG_URL,
 };
+#[cfg(feature = "tls")]
+use crate::{gemini, gemtext::Gemtext};
 use std::{
-    io::{stdin, stdout, Result, Write},
+    io::{stdin, stdout, Write},
     process::{self, Stdio},
     sync::{
-        mpsc::{channel, Receiver, Sender},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
         Arc, Mutex, RwLock,
     },
     thread,
@@ -50,6 +62,9 @@ pub type KeyReceiver = Arc<Mutex<Receiver<Key>>>;
 /// wrapping in text views.
 pub const MAX_COLS: usize = 77;
 
+/// How often to check on a page that's loading in the background.
+const LOADER_POLL: Duration = Duration::from_millis(100);
+
 /// Fatal errors. In general we want to try and catch any errors
 /// (network, parsing gopher response, etc) and just show an error
 /// message in the status bar, but if we can't write to STDOUT or
@@ -97,6 +112,19 @@ pub struct UI {
     config: SharedConfig,
     /// Channel where UI events are sent.
     keys: KeyReceiver,
+    /// Views that are still loading in the background, one Loader
+    /// each.
+    loaders: Vec<Loader>,
+    /// Loader for the View that `load()` just returned, until it's
+    /// added with `add_view()`.
+    next_loader: Option<Loader>,
+    /// Skip the cache on the next `load()`, because we're reloading.
+    refresh: bool,
+    /// Files downloading in the background.
+    downloads: Downloads,
+    /// Last mailbox we fetched: its URL, whether it came over TLS, and
+    /// its messages. Reading them doesn't download it again.
+    mailbox: Option<(String, bool, Vec<mailbox::Message>)>,
 }
 
 impl UI {
@@ -107,15 +135,21 @@ impl UI {
             size = (cols as usize, rows as usize);
         };
 
+        let config = Arc::new(RwLock::new(config));
         UI {
             views: vec![],
             focused: 0,
             dirty: true,
             running: true,
             size,
-            config: Arc::new(RwLock::new(config)),
+            downloads: Downloads::new(config.clone()),
+            config,
             status: String::new(),
             keys: Self::spawn_keyboard_listener(),
+            loaders: vec![],
+            next_loader: None,
+            refresh: false,
+            mailbox: None,
         }
     }
 
@@ -165,10 +199,19 @@ impl UI {
                 terminal::HideCursor
             ));
         }
+        self.poll_loaders();
+        self.poll_downloads();
     }
 
     /// Reload the currently focused view while preserving history.
     pub fn reload(&mut self, title: &str, url: &str) -> Result<()> {
+        // views after this one keep loading where they are
+        let focused = self.focused;
+        let (mut ahead, behind) = self
+            .loaders
+            .drain(..)
+            .partition(|loader| loader.view > focused);
+        self.loaders = behind;
         let mut rest = if self.views.len() > self.focused + 1 {
             self.views.drain(self.focused..).collect()
         } else {
@@ -177,29 +220,112 @@ impl UI {
         if self.focused > 0 {
             self.focused -= 1;
         }
-        self.open(title, url)?;
+        self.refresh = true;
+        let opened = self.open(title, url);
+        self.refresh = false;
+        opened?;
         if rest.len() > 1 {
             rest.remove(0); // drop the view we're reloading
             self.views.append(&mut rest);
+            self.loaders.append(&mut ahead);
         }
         Ok(())
     }
 
     /// Open a URL - Gopher, internal, telnet, or something else.
+    /// If a server's TLS certificate has changed since last time, asks
+    /// whether to trust the new one before trying again. If the server
+    /// can't be reached, tries the link's mirrors, if it has any.
     pub fn open(&mut self, title: &str, url: &str) -> Result<()> {
+        match self.open_url(title, url) {
+            Err(e) if is_unreachable(&e) => self.open_mirror(title, url, e),
+            Err(Error::CertChanged(cert)) if self.config.read().unwrap().mode == Mode::Run => {
+                self.dirty = true;
+                let warning = format!(
+                    "{}WARNING: {}'s certificate has CHANGED! Trust the new one?{}",
+                    self.config.read().unwrap().theme.item_error,
+                    cert.host,
+                    reset_color!(),
+                );
+                if self.confirm(&warning) {
+                    known_hosts::save(&cert.host, &cert.new)?;
+                    self.open_url(title, url)
+                } else {
+                    Err(Error::CertChanged(cert))
+                }
+            }
+            res => res,
+        }
+    }
+
+    /// Try each of `url`'s mirrors in turn after it failed with `err`.
+    fn open_mirror(&mut self, title: &str, url: &str, err: Error) -> Result<()> {
+        let mirrors = match self.views.get(self.focused) {
+            Some(view) => view.mirrors(url),
+            None => vec![],
+        };
+        if mirrors.is_empty() {
+            return Err(err);
+        }
+        for mirror in &mirrors {
+            match self.open_url(title, mirror) {
+                Ok(()) => {
+                    self.set_status(&format!(
+                        "{} is unreachable, using mirror {}",
+                        gopher::parse_url(url).addr(),
+                        gopher::parse_url(mirror).addr()
+                    ));
+                    return Ok(());
+                }
+                Err(e) if is_unreachable(&e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(error!("{} (tried {} mirrors)", err, mirrors.len()))
+    }
+
+    /// Does the actual work for `open()`.
+    fn open_url(&mut self, title: &str, url: &str) -> Result<()> {
         if let Some(view) = self.views.get(self.focused) {
             if view.url() == url {
                 return self.reload(title, url);
             }
         }
 
+        // revoke a pinned certificate
+        if let Some(host) = url.strip_prefix("gopher://phetch/1/certs/revoke/") {
+            return self.revoke(host);
+        }
+
+        // cancel or retry a download
+        if let Some(id) = url.strip_prefix("gopher://phetch/1/downloads/cancel/") {
+            return self.manage_download(id, Downloads::cancel);
+        }
+        if let Some(id) = url.strip_prefix("gopher://phetch/1/downloads/retry/") {
+            return self.manage_download(id, Downloads::retry);
+        }
+
         // telnet
         if url.starts_with("telnet://") {
             return self.telnet(url);
         }
 
+        // gopher+ forms and item info
+        if gopher::plus::is_ask_url(url) {
+            return self.ask(title, url);
+        } else if gopher::plus::is_attributes_url(url) {
+            return self.load(title, url).map(|view| {
+                self.add_view(view);
+            });
+        }
+
+        // gemini
+        if url.starts_with("gemini://") {
+            return self.gemini(title, url);
+        }
+
         // non-gopher URL
-        if url.contains("://") && !url.starts_with("gopher://") {
+        if url.contains("://") && !url.starts_with("gopher://") && !url.starts_with("finger://") {
             self.dirty = true;
             return if self.confirm(&format!("Open external URL? {}", url)) {
                 utils::open_external(url)
@@ -208,8 +334,18 @@ impl UI {
             };
         }
 
-        // binary downloads
+        // CSO directory lookups
         let typ = gopher::type_for_url(url);
+        if typ == Type::CSOEntity {
+            return self.cso(title, url);
+        }
+
+        // mailboxes and the messages in them
+        if typ == Type::Mailbox {
+            return self.mailbox(title, url);
+        }
+
+        // binary downloads
 
         if typ.is_media() && self.config.read().unwrap().media.is_some() {
             self.dirty = true;
@@ -222,10 +358,24 @@ impl UI {
             };
         }
 
+        // show what's inside UUEncoded and BinHex files first
+        if gopher::decode::is_encoded(typ) && self.config.read().unwrap().decode {
+            return self.load(title, url).map(|view| {
+                self.add_view(view);
+            });
+        }
+
+        // show calendars as an agenda instead of downloading them
+        if typ == Type::Calendar {
+            return self.load(title, url).map(|view| {
+                self.add_view(view);
+            });
+        }
+
         if typ.is_download() {
             self.dirty = true;
             return if self.confirm(&format!("Download {}?", url)) {
-                self.download(url)
+                self.download(url, None)
             } else {
                 Ok(())
             };
@@ -236,52 +386,47 @@ impl UI {
         })
     }
 
-    /// Used to download content of the current view with a provided filename
-    fn download_file_with_filename(&mut self, url: &str, filename: String) -> Result<()> {
-        let url = url.to_string();
-        let (tls, tor) = (
-            self.config.read().unwrap().tls,
-            self.config.read().unwrap().tor,
-        );
-        let chan = self.keys.clone();
-        self.spinner(&format!("Downloading {}", url), move || {
-            gopher::download_url_with_filename(&url, tls, tor, chan, &filename)
-        })
-        .and_then(|res| res)
-        .map(|(path, bytes)| {
-            self.set_status(
-                format!(
-                    "Download complete! {} saved to {}",
-                    utils::human_bytes(bytes),
-                    path
-                )
-                .as_ref(),
-            );
-        })
+    /// Start downloading a file in the background, saving it as
+    /// `filename` or after its selector. Used by `open()` and `d`.
+    fn download(&mut self, url: &str, filename: Option<String>) -> Result<()> {
+        self.check_online(url)?;
+        self.downloads.add(url, filename);
+        self.refresh_downloads()
     }
 
-    /// Download a binary file. Used by `open()` internally.
-    fn download(&mut self, url: &str) -> Result<()> {
-        let url = url.to_string();
-        let (tls, tor) = (
-            self.config.read().unwrap().tls,
-            self.config.read().unwrap().tor,
-        );
-        let chan = self.keys.clone();
-        self.spinner(&format!("Downloading {}", url), move || {
-            gopher::download_url(&url, tls, tor, chan)
-        })
-        .and_then(|res| res)
-        .map(|(path, bytes)| {
-            self.set_status(
-                format!(
-                    "Download complete! {} saved to {}",
-                    utils::human_bytes(bytes),
-                    path
-                )
-                .as_ref(),
-            );
-        })
+    /// Cancel or retry the download with the given id, then show the
+    /// downloads page.
+    fn manage_download(
+        &mut self,
+        id: &str,
+        action: fn(&mut Downloads, usize) -> Result<()>,
+    ) -> Result<()> {
+        let id = id.parse().map_err(|_| error!("No such download: {}", id))?;
+        action(&mut self.downloads, id)?;
+        if self.views.get(self.focused).map(|v| v.url()) == Some(downloads::URL) {
+            self.refresh_downloads()
+        } else {
+            self.open("Downloads", downloads::URL)
+        }
+    }
+
+    /// Redraw the downloads page, if that's what we're looking at.
+    fn refresh_downloads(&mut self) -> Result<()> {
+        if self.views.get(self.focused).map(|v| v.url()) == Some(downloads::URL) {
+            self.reload("Downloads", downloads::URL)?;
+        }
+        Ok(())
+    }
+
+    /// Let the user know about downloads that have finished.
+    fn poll_downloads(&mut self) {
+        let msgs = self.downloads.poll();
+        if msgs.is_empty() {
+            return;
+        }
+        let _ = self.refresh_downloads();
+        let color = self.config.read().unwrap().theme.item_error.clone();
+        self.set_status(&download_status(msgs, &color));
     }
 
     /// Fetches a URL and returns a View for its content.
@@ -296,16 +441,137 @@ impl UI {
         thread::spawn(move || history::save(&hname, &hurl));
         // request thread
         let thread_url = url.to_string();
-        let (tls, tor) = (
-            self.config.read().unwrap().tls,
-            self.config.read().unwrap().tor,
+        let refresh = std::mem::take(&mut self.refresh);
+        let (tls, transport, timeouts, settings) = (
+            self.config.read().unwrap().tls_mode(),
+            self.config.read().unwrap().transport(),
+            self.config.read().unwrap().timeouts,
+            self.config.read().unwrap().cache,
         );
+        // show big pages while they load
+        if self.config.read().unwrap().mode == Mode::Run
+            && !settings.offline
+            && !gopher::plus::is_attributes_url(url)
+            && matches!(
+                gopher::type_for_url(url),
+                Type::Menu | Type::Search | Type::Text | Type::HTML
+            )
+            && (refresh || cache::get(url, settings).is_none())
+        {
+            return self.load_streaming(url, tls, transport, timeouts, settings);
+        }
+        let fetch = move || {
+            if refresh && !settings.offline {
+                cache::refresh_url(&thread_url, tls, &*transport, timeouts, settings)
+                    .map(|(tls, res)| (tls, res, false))
+            } else {
+                cache::fetch_url(&thread_url, tls, &*transport, timeouts, settings)
+            }
+        };
         // don't spin on first ever request
-        let (tls, res) = if self.views.is_empty() {
-            gopher::fetch_url(&thread_url, tls, tor)?
+        let (tls, res, cached) = if self.views.is_empty() {
+            fetch()?
+        } else {
+            self.spinner("", fetch)??
+        };
+        let mut view: Box<dyn View> = if gopher::plus::is_attributes_url(url) {
+            let attrs = Attributes::parse(&gopher::response_to_string(&res));
+            Box::new(Menu::from(
+                url,
+                attrs.to_menu(url),
+                self.config.clone(),
+                tls,
+            ))
+        } else {
+            self.view_for(url, res, tls)?
+        };
+        view.set_cached(cached);
+        Ok(view)
+    }
+
+    /// Fetches the first screenful of a URL and returns a View for it,
+    /// leaving the rest to load in the background.
+    fn load_streaming(
+        &mut self,
+        url: &str,
+        tls: gopher::Tls,
+        transport: Arc<dyn Transport>,
+        timeouts: gopher::Timeouts,
+        settings: cache::Settings,
+    ) -> Result<Box<dyn View>> {
+        let thread_url = url.to_string();
+        let lines = self.rows() as usize;
+        // don't spin on first ever request
+        let (tls, body, stream) = if self.views.is_empty() {
+            gopher::fetch_start(&thread_url, tls, &*transport, timeouts, lines)?
         } else {
-            self.spinner("", move || gopher::fetch_url(&thread_url, tls, tor))??
+            self.spinner("", move || {
+                gopher::fetch_start(&thread_url, tls, &*transport, timeouts, lines)
+            })??
+        };
+
+        let stream = match stream {
+            Some(stream) => stream,
+            None => {
+                if settings.enabled {
+                    let _ = cache::put(url, tls, &body, settings);
+                }
+                return self.view_for(url, body, tls);
+            }
         };
+
+        let sent = body
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |end| end + 1);
+        let view = self.view_for(url, body[..sent].to_vec(), tls)?;
+        self.next_loader = Some(Loader::spawn(
+            url, stream, body, sent, tls, timeouts, settings,
+        ));
+        Ok(view)
+    }
+
+    /// Hand any newly arrived chunks of loading pages to their Views.
+    fn poll_loaders(&mut self) {
+        let mut i = 0;
+        while i < self.loaders.len() {
+            let idx = self.loaders[i].view;
+            let mut finished = false;
+            // hand everything that arrived over at once
+            let mut data = vec![];
+            for chunk in self.loaders[i].recv() {
+                match chunk {
+                    Chunk::Data(chunk) => data.extend_from_slice(&chunk),
+                    Chunk::Done => finished = true,
+                    Chunk::Error(e) => {
+                        finished = true;
+                        self.set_status(&format!(
+                            "{}{}{}",
+                            &self.config.read().unwrap().theme.item_error,
+                            e,
+                            terminal::HideCursor
+                        ));
+                    }
+                }
+            }
+            if !data.is_empty() {
+                if let Some(view) = self.views.get_mut(idx) {
+                    view.append(&data);
+                    if idx == self.focused {
+                        self.dirty = true;
+                    }
+                }
+            }
+            if finished {
+                self.loaders.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Turn a raw Gopher response into the right kind of View.
+    fn view_for(&self, url: &str, res: Vec<u8>, tls: bool) -> Result<Box<dyn View>> {
         let typ = gopher::type_for_url(url);
         match typ {
             Type::Menu | Type::Search => Ok(Box::new(Menu::from(
@@ -315,12 +581,275 @@ impl UI {
                 tls,
             ))),
             Type::Text | Type::HTML => Ok(Box::new(Text::from(url, res, self.config.clone(), tls))),
+            Type::Xml => {
+                let mut text = Text::from(url, res, self.config.clone(), tls);
+                text.set_pretty(true);
+                Ok(Box::new(text))
+            }
+            Type::UUEncoded | Type::Binhex => Ok(Box::new(Text::from(
+                url,
+                gopher::decode::preview(typ, &res),
+                self.config.clone(),
+                tls,
+            ))),
+            Type::Calendar => {
+                let ics = gopher::response_to_string(&res);
+                // not every type c item is really iCalendar, so show
+                // anything else as it is
+                Ok(Box::new(match calendar::to_agenda(&ics) {
+                    Ok(agenda) => {
+                        Text::from_source(url, agenda.into_bytes(), ics, self.config.clone(), tls)
+                    }
+                    Err(_) => Text::from(url, res, self.config.clone(), tls),
+                }))
+            }
             _ => Err(error!("Unsupported Gopher Response: {:?}", typ)),
         }
     }
 
+    /// Fetch a Gopher+ item's +ASK form and prompt the user to fill
+    /// it out, one question at a time.
+    fn ask(&mut self, title: &str, url: &str) -> Result<()> {
+        let attr_url = gopher::plus::attributes_url(url);
+        let (tls, transport, timeouts, settings) = (
+            self.config.read().unwrap().tls_mode(),
+            self.config.read().unwrap().transport(),
+            self.config.read().unwrap().timeouts,
+            self.config.read().unwrap().cache,
+        );
+        let (_, res, _) = self.spinner("", move || {
+            cache::fetch_url(&attr_url, tls, &*transport, timeouts, settings)
+        })??;
+        let questions = Attributes::parse(&gopher::response_to_string(&res)).questions();
+        if questions.is_empty() {
+            return Err(error!("No +ASK form found: {}", url));
+        }
+        self.process_action(ask_prompt(title.into(), url.into(), questions, vec![]))
+    }
+
+    /// Submit a filled out Gopher+ +ASK form and show the response.
+    fn submit(&mut self, url: &str, answers: Vec<String>) -> Result<()> {
+        self.check_online(url)?;
+        let thread_url = url.to_string();
+        let (tls, transport, timeouts) = (
+            self.config.read().unwrap().tls_mode(),
+            self.config.read().unwrap().transport(),
+            self.config.read().unwrap().timeouts,
+        );
+        let (tls, res) = self.spinner("", move || {
+            gopher::plus::submit(&thread_url, &answers, tls, &*transport, timeouts)
+        })??;
+        let view = self.view_for(gopher::plus::base_url(url), res, tls)?;
+        self.add_view(view);
+        Ok(())
+    }
+
+    /// Look something up in a CSO directory, asking what to look up
+    /// if the URL doesn't say.
+    fn cso(&mut self, title: &str, url: &str) -> Result<()> {
+        self.check_online(url)?;
+        if gopher::parse_url(url).search.is_none() {
+            let (title, url) = (title.to_string(), url.to_string());
+            return self.process_action(Action::Prompt(
+                format!("{}> ", title),
+                Box::new(move |query| {
+                    Action::Open(
+                        format!("{}> {}", title, query),
+                        gopher::search_url(&url, &query),
+                    )
+                }),
+            ));
+        }
+
+        let thread_url = url.to_string();
+        let (tls, transport, timeouts) = (
+            self.config.read().unwrap().tls_mode(),
+            self.config.read().unwrap().transport(),
+            self.config.read().unwrap().timeouts,
+        );
+        // don't spin on first ever request
+        let (tls, res) = if self.views.is_empty() {
+            gopher::cso::fetch_url(&thread_url, tls, &*transport, timeouts)?
+        } else {
+            self.spinner("", move || {
+                gopher::cso::fetch_url(&thread_url, tls, &*transport, timeouts)
+            })??
+        };
+        let text = gopher::cso::to_text(&gopher::response_to_string(&res))?;
+
+        let hurl = url.to_string();
+        let hname = title.to_string();
+        thread::spawn(move || history::save(&hname, &hurl));
+
+        let view = Text::from(url, text.into_bytes(), self.config.clone(), tls);
+        self.add_view(Box::new(view));
+        Ok(())
+    }
+
+    /// Show a mailbox as a menu of its messages, or one message if the
+    /// URL has a message number.
+    fn mailbox(&mut self, title: &str, url: &str) -> Result<()> {
+        let mbox_url = mailbox::mailbox_url(url);
+        let number = mailbox::message_number(url);
+        // messages come from the mailbox we already have, but the
+        // list of them is always fresh
+        let (tls, messages) = match self.mailbox.take() {
+            Some((last, tls, messages)) if last == mbox_url && number.is_some() => (tls, messages),
+            _ => {
+                self.check_online(url)?;
+                let thread_url = mbox_url.clone();
+                let (tls, transport, timeouts, settings) = (
+                    self.config.read().unwrap().tls_mode(),
+                    self.config.read().unwrap().transport(),
+                    self.config.read().unwrap().timeouts,
+                    self.config.read().unwrap().cache,
+                );
+                // don't spin on first ever request
+                let (tls, res, _) = if self.views.is_empty() {
+                    cache::fetch_url(&thread_url, tls, &*transport, timeouts, settings)?
+                } else {
+                    self.spinner("", move || {
+                        cache::fetch_url(&thread_url, tls, &*transport, timeouts, settings)
+                    })??
+                };
+                (tls, mailbox::parse(&gopher::response_to_string(&res)))
+            }
+        };
+        let messages = &self.mailbox.insert((mbox_url, tls, messages)).2;
+
+        let view: Box<dyn View> = match number {
+            Some(n) => {
+                let msg = n
+                    .checked_sub(1)
+                    .and_then(|i| messages.get(i))
+                    .ok_or_else(|| error!("No message {} in this mailbox", n))?;
+                Box::new(Text::from(
+                    url,
+                    msg.to_text().into_bytes(),
+                    self.config.clone(),
+                    tls,
+                ))
+            }
+            None => Box::new(Menu::from(
+                url,
+                mailbox::to_menu(url, messages),
+                self.config.clone(),
+                tls,
+            )),
+        };
+
+        let hurl = url.to_string();
+        let hname = title.to_string();
+        thread::spawn(move || history::save(&hname, &hurl));
+
+        self.add_view(view);
+        Ok(())
+    }
+
+    /// Fetch a Gemini URL and show it, prompting for input if the
+    /// server asks for it.
+    #[cfg(feature = "tls")]
+    fn gemini(&mut self, title: &str, url: &str) -> Result<()> {
+        self.fetch_gemini(title, url, true)
+    }
+
+    /// Does the work for `gemini()`. URLs with sensitive input in
+    /// them, like passwords, are never saved to history.
+    #[cfg(feature = "tls")]
+    fn fetch_gemini(&mut self, title: &str, url: &str, save: bool) -> Result<()> {
+        self.check_online(url)?;
+        let thread_url = url.to_string();
+        let (transport, timeouts) = (
+            self.config.read().unwrap().transport(),
+            self.config.read().unwrap().timeouts,
+        );
+        // don't spin on first ever request
+        let res = if self.views.is_empty() {
+            gemini::fetch_url(&thread_url, &*transport, timeouts)?
+        } else {
+            self.spinner("", move || {
+                gemini::fetch_url(&thread_url, &*transport, timeouts)
+            })??
+        };
+
+        if res.is_sensitive() {
+            return match self.prompt_secret(&res.meta) {
+                Some(query) => {
+                    self.fetch_gemini(title, &gemini::with_query(&res.url, &query), false)
+                }
+                None => Ok(()),
+            };
+        } else if res.is_input() {
+            let title = title.to_string();
+            let url = res.url.clone();
+            return self.process_action(Action::Prompt(
+                res.meta.clone(),
+                Box::new(move |query| Action::Open(title, gemini::with_query(&url, &query))),
+            ));
+        }
+
+        if save {
+            let hurl = res.url.clone();
+            let hname = title.to_string();
+            thread::spawn(move || history::save(&hname, &hurl));
+        }
+
+        let view: Box<dyn View> = if res.is_gemtext() {
+            let body = String::from_utf8_lossy(&res.body).to_string();
+            Box::new(Gemtext::from(&res.url, body, self.config.clone()))
+        } else if res.is_text() {
+            Box::new(Text::from(&res.url, res.body, self.config.clone(), true))
+        } else {
+            return Err(error!("Unsupported Gemini content: {}", res.mime()));
+        };
+        self.add_view(view);
+        Ok(())
+    }
+
+    /// Gemini always needs TLS.
+    #[cfg(not(feature = "tls"))]
+    fn gemini(&mut self, _title: &str, _url: &str) -> Result<()> {
+        Err(error!("phetch was compiled without TLS support"))
+    }
+
+    /// Forget a host's pinned certificate, then show what's left.
+    fn revoke(&mut self, host: &str) -> Result<()> {
+        self.dirty = true;
+        if !self.confirm(&format!("Revoke certificate for {}?", host)) {
+            return Ok(());
+        }
+        if !known_hosts::revoke(host)? {
+            return Err(error!("No certificate pinned for {}", host));
+        }
+        let certs = "gopher://phetch/1/certs";
+        if self.views.get(self.focused).map(|v| v.url()) == Some(certs) {
+            self.reload("Certificates", certs)?;
+        } else {
+            self.open("Certificates", certs)?;
+        }
+        self.set_status(&format!("Revoked certificate for {}", host));
+        Ok(())
+    }
+
+    /// Refuse to touch the network in `--offline` mode.
+    fn check_online(&self, url: &str) -> Result<()> {
+        if self.config.read().unwrap().cache.offline {
+            Err(error!("Offline, can't open {}", url))
+        } else {
+            Ok(())
+        }
+    }
+
     /// Get Menu for on-line help, home page, etc, ex: gopher://phetch/1/help/types
     fn load_internal(&mut self, url: &str) -> Result<Box<dyn View>> {
+        if url == downloads::URL {
+            return Ok(Box::new(Menu::from(
+                url,
+                self.downloads.as_raw_menu(),
+                self.config.clone(),
+                false,
+            )));
+        }
         if let Some(source) = help::lookup(
             url.trim_start_matches("gopher://phetch/")
                 .trim_start_matches("1/"),
@@ -362,7 +891,7 @@ impl UI {
 
         let (tx, rx) = channel();
         let label = label.to_string();
-        let rows = self.rows() as u16;
+        let rows = self.rows();
         thread::spawn(move || loop {
             for i in 0..=3 {
                 if rx.try_recv().is_ok() {
@@ -426,6 +955,14 @@ impl UI {
             status.push("CP439");
         }
 
+        if view.is_cached() {
+            if self.config.read().unwrap().emoji {
+                status.push("💾");
+            } else {
+                status.push("CACHE");
+            }
+        }
+
         if view.is_tls() {
             if self.config.read().unwrap().emoji {
                 status.push("🔐");
@@ -459,12 +996,20 @@ impl UI {
 
     /// Render the status line.
     fn render_status(&self) -> String {
+        let mut status = self.status.clone();
+        if status.is_empty() {
+            if let Some(loader) = self.loaders.iter().find(|l| l.view == self.focused) {
+                status = format!("Loading... {}", utils::human_bytes(loader.bytes));
+            } else if let Some(downloads) = self.downloads.status() {
+                status = downloads;
+            }
+        }
         format!(
             "{}{}{}{}{}{}",
             terminal::HideCursor,
             terminal::Goto(1, self.rows()),
             terminal::ClearCurrentLine,
-            self.status,
+            status,
             self.render_conn_status().unwrap_or_else(|| "".into()),
             theme::color::Reset,
         )
@@ -473,6 +1018,17 @@ impl UI {
     /// Add a View to the app's currently opened Views.
     fn add_view(&mut self, view: Box<dyn View>) {
         self.dirty = true;
+        // whatever was loading in this spot is being replaced
+        let idx = if self.views.is_empty() {
+            0
+        } else {
+            self.focused + 1
+        };
+        self.loaders.retain(|loader| loader.view < idx);
+        if let Some(mut loader) = self.next_loader.take() {
+            loader.view = idx;
+            self.loaders.push(loader);
+        }
         if !self.views.is_empty() && self.focused < self.views.len() - 1 {
             self.views.truncate(self.focused + 1);
         }
@@ -508,8 +1064,27 @@ impl UI {
 
     /// Prompt user for input and return what was entered, if anything.
     fn prompt(&self, prompt: &str, value: &str) -> Option<String> {
+        self.read_input(prompt, value, false)
+    }
+
+    /// Prompt user for a password or something else that shouldn't be
+    /// on screen. What they type is shown as `*`s.
+    #[cfg(feature = "tls")]
+    fn prompt_secret(&self, prompt: &str) -> Option<String> {
+        self.read_input(prompt, "", true)
+    }
+
+    /// Does the work for `prompt()` and `prompt_secret()`.
+    fn read_input(&self, prompt: &str, value: &str, masked: bool) -> Option<String> {
         let rows = self.rows();
         let mut input = value.to_string();
+        let shown = |input: &str| {
+            if masked {
+                "*".repeat(input.chars().count())
+            } else {
+                input.to_string()
+            }
+        };
 
         let mut out = stdout();
         write!(
@@ -519,7 +1094,7 @@ impl UI {
             terminal::Goto(1, rows),
             terminal::ClearCurrentLine,
             prompt,
-            input,
+            shown(&input),
             terminal::ShowCursor,
         )
         .expect(ERR_STDOUT);
@@ -563,7 +1138,7 @@ impl UI {
                 terminal::Goto(1, rows),
                 terminal::ClearCurrentLine,
                 prompt,
-                input,
+                shown(&input),
             )
             .expect(ERR_STDOUT);
             out.flush().expect(ERR_STDOUT);
@@ -597,7 +1172,19 @@ impl UI {
     /// Asks the current View to process user input and produce an Action.
     fn process_view_input(&mut self) -> Action {
         if let Some(view) = self.views.get_mut(self.focused) {
-            if let Ok(key) = self.keys.lock().unwrap().recv() {
+            let keys = self.keys.lock().unwrap();
+            // wake up now and then to check on pages and downloads
+            // that are loading
+            let key = if !self.loaders.is_empty() || self.downloads.is_busy() {
+                match keys.recv_timeout(LOADER_POLL) {
+                    Ok(key) => Ok(key),
+                    Err(RecvTimeoutError::Timeout) => return Action::None,
+                    Err(e) => Err(e),
+                }
+            } else {
+                keys.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            };
+            if let Ok(key) = key {
                 return view.respond(key);
             }
         }
@@ -612,9 +1199,9 @@ impl UI {
         // Give our resize handler a channel to send events on.
         *RESIZE_SENDER.lock().unwrap() = Some(sender.clone());
         unsafe {
-            libc::signal(libc::SIGWINCH, resize_handler as usize);
-            libc::signal(libc::SIGINT, sigint_handler as usize);
-            libc::signal(libc::SIGCONT, sigcont_handler as usize);
+            libc::signal(libc::SIGWINCH, resize_handler as *const () as usize);
+            libc::signal(libc::SIGINT, sigint_handler as *const () as usize);
+            libc::signal(libc::SIGCONT, sigcont_handler as *const () as usize);
         }
 
         thread::spawn(move || {
@@ -666,19 +1253,16 @@ impl UI {
                     self.process_action(fun(response))?;
                 }
             }
+            Action::Form(_, url, answers) => self.submit(&url, answers)?,
             // F5 = redraw the display on resize
             Action::Keypress(Key::F(5)) => self.dirty = true,
-            Action::Keypress(Key::Left) | Action::Keypress(Key::Backspace) => {
-                if self.focused > 0 {
-                    self.dirty = true;
-                    self.focused -= 1;
-                }
+            Action::Keypress(Key::Left) | Action::Keypress(Key::Backspace) if self.focused > 0 => {
+                self.dirty = true;
+                self.focused -= 1;
             }
-            Action::Keypress(Key::Right) => {
-                if self.focused < self.views.len() - 1 {
-                    self.dirty = true;
-                    self.focused += 1;
-                }
+            Action::Keypress(Key::Right) if self.focused < self.views.len() - 1 => {
+                self.dirty = true;
+                self.focused += 1;
             }
             Action::Keypress(Key::Char(key)) | Action::Keypress(Key::Ctrl(key)) => match key {
                 'a' => self.open("History", "gopher://phetch/1/history")?,
@@ -694,16 +1278,24 @@ impl UI {
                         return Err(error!("Can't download internal phetch pages."));
                     }
 
-                    let u = gopher::parse_url(&url);
-                    let default_filename = u.sel.split_terminator('/').rev().next().unwrap_or("");
-                    if let Some(filename) = self.prompt("Save to disk as: ", default_filename) {
+                    // suggest the name an encoded file will be decoded as
+                    let typ = gopher::type_for_url(url);
+                    let default_filename = match self.views.get(self.focused) {
+                        Some(view)
+                            if gopher::decode::is_encoded(typ)
+                                && self.config.read().unwrap().decode =>
+                        {
+                            gopher::decode::decode(typ, view.raw().as_bytes())
+                                .map(|decoded| decoded.filename())
+                                .unwrap_or_else(|_| gopher::download_filename(url))
+                        }
+                        _ => gopher::download_filename(url),
+                    };
+                    if let Some(filename) = self.prompt("Save to disk as: ", &default_filename) {
                         if filename.trim().is_empty() {
                             return Err(error!("Please provide a filename."));
                         }
-                        match self.download_file_with_filename(url, String::from(filename)) {
-                            Ok(()) => (),
-                            Err(e) => return Err(error!("Download failed: {}", e)),
-                        }
+                        self.download(url, Some(filename))?;
                     }
                 }
                 'g' => {
@@ -764,7 +1356,11 @@ impl UI {
                         self.dirty = true;
                     }
                 }
-                'q' => self.running = false,
+                'q' => {
+                    self.dirty = true;
+                    self.running = self.downloads.is_busy()
+                        && !self.confirm("Downloads still in progress. Quit anyway?");
+                }
                 c => return Err(error!("Unknown keypress: {}", c)),
             },
             _ => (),
@@ -772,3 +1368,244 @@ impl UI {
         Ok(())
     }
 }
+
+/// Did this error happen because a server couldn't be reached? Those
+/// are worth trying a mirror for.
+fn is_unreachable(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::Dns { .. } | Error::Network { .. } | Error::Timeout { .. }
+    ) || e.is_retryable()
+}
+
+/// Builds a chain of Prompts, one for each +ASK question, that ends
+/// by submitting all the answers as a Form. Hitting enter without
+/// typing anything picks the question's default answer.
+fn ask_prompt(
+    title: String,
+    url: String,
+    mut questions: Vec<Question>,
+    mut answers: Vec<String>,
+) -> Action {
+    if questions.is_empty() {
+        return Action::Form(title, url, answers);
+    }
+
+    let question = questions.remove(0);
+    let default = question.defaults.first().cloned().unwrap_or_default();
+    let mut prompt = question.prompt.clone();
+    match question.kind {
+        AskKind::Choose => prompt.push_str(&format!(" [{}]", question.defaults.join("/"))),
+        AskKind::Select if default == "1" => prompt.push_str(" [Y/n]"),
+        AskKind::Select => prompt.push_str(" [y/N]"),
+        _ if !default.is_empty() => prompt.push_str(&format!(" [{}]", default)),
+        _ => {}
+    }
+    prompt.push(' ');
+
+    Action::Prompt(
+        prompt,
+        Box::new(move |answer| {
+            let answer = if answer.is_empty() { default } else { answer };
+            answers.push(if question.kind == AskKind::Select {
+                match answer.to_lowercase().as_ref() {
+                    "1" | "y" | "yes" => "1".into(),
+                    _ => "0".into(),
+                }
+            } else {
+                answer
+            });
+            ask_prompt(title, url, questions, answers)
+        }),
+    )
+}
+
+/// Join download messages into one status line, failures first so
+/// they're never pushed off the end.
+fn download_status(mut msgs: Vec<(String, bool)>, error_color: &str) -> String {
+    msgs.sort_by_key(|&(_, failed)| !failed);
+    let status = msgs
+        .iter()
+        .map(|(msg, failed)| {
+            if *failed {
+                format!("{}{}{}", error_color, msg, reset_color!())
+            } else {
+                msg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" | ");
+    format!("{}{}", status, terminal::HideCursor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gopher::Memory, utils::TempDir};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A UI that prints instead of drawing, on `net` instead of the
+    /// real network.
+    fn offline_ui(net: Memory) -> UI {
+        UI::new(Config {
+            mode: Mode::Print,
+            transport: Some(Arc::new(net)),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_navigation() {
+        let net = Memory::new()
+            .page(
+                "example.com:70",
+                "/",
+                "iWelcome!\t\t\t\r\n\
+                 0About\t/about.txt\texample.com\t70\r\n\
+                 1Docs\t/docs\texample.com\t70\r\n\
+                 .\r\n",
+            )
+            .page("example.com:70", "/about.txt", "All about us.\r\n")
+            .page("example.com:70", "/docs", "iNo docs yet.\t\t\t\r\n.\r\n");
+        let mut ui = offline_ui(net);
+
+        ui.open("Home", "gopher://example.com/").unwrap();
+        assert!(ui.views[0].raw().contains("Welcome!"));
+        // render() does this when there's a terminal
+        ui.views[0].term_size(80, 24);
+
+        let action = ui.views[ui.focused].respond(Key::Char('\n'));
+        ui.process_action(action).unwrap();
+        assert_eq!(ui.focused, 1);
+        assert_eq!(ui.views[1].url(), "gopher://example.com/0/about.txt");
+        assert_eq!(ui.views[1].raw(), "All about us.\r\n");
+
+        ui.process_action(Action::Keypress(Key::Left)).unwrap();
+        assert_eq!(ui.focused, 0);
+        let action = ui.views[ui.focused].respond(Key::Char('2'));
+        ui.process_action(action).unwrap();
+        assert_eq!(ui.focused, 1);
+        assert_eq!(ui.views.len(), 2);
+        assert!(ui.views[1].raw().contains("No docs yet."));
+    }
+
+    #[test]
+    fn test_loaders() {
+        let big = |name: &str| {
+            (1..=1000)
+                .map(|i| format!("{} line {}\r\n", name, i))
+                .collect::<String>()
+        };
+        let net = Memory::new()
+            .page("example.com:70", "/a.txt", big("a"))
+            .page("example.com:70", "/b.txt", big("b"));
+        // pages only stream in while running interactively
+        let mut ui = UI::new(Config {
+            mode: Mode::Run,
+            transport: Some(Arc::new(net)),
+            ..Default::default()
+        });
+
+        // B opens while A is still loading
+        ui.open("A", "gopher://example.com/0/a.txt").unwrap();
+        ui.open("B", "gopher://example.com/0/b.txt").unwrap();
+        assert_eq!(ui.loaders.len(), 2);
+
+        // reloading A doesn't stop B
+        ui.process_action(Action::Keypress(Key::Left)).unwrap();
+        ui.open("A", "gopher://example.com/0/a.txt").unwrap();
+        let mut loading = ui.loaders.iter().map(|l| l.view).collect::<Vec<_>>();
+        loading.sort_unstable();
+        assert_eq!(loading, [0, 1]);
+
+        while !ui.loaders.is_empty() {
+            ui.poll_loaders();
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(ui.views.len(), 2);
+        assert_eq!(ui.views[0].raw(), big("a"));
+        assert_eq!(ui.views[1].raw(), big("b"));
+    }
+
+    #[test]
+    fn test_reload_skips_cache() {
+        // where cache::dir() points in tests
+        let _cache = TempDir::new("cache");
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let counter = fetches.clone();
+        let net = Memory::new().handler("example.com:70", move |sel| {
+            let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            format!("i{} #{}\t\t\t\r\n", sel, n).into_bytes()
+        });
+        let mut ui = UI::new(Config {
+            mode: Mode::Print,
+            transport: Some(Arc::new(net)),
+            cache: cache::Settings {
+                enabled: true,
+                // never stale
+                ttl: Duration::from_secs(0),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let news = "gopher://example.com/1/news";
+
+        ui.open("News", news).unwrap();
+        ui.open("Other", "gopher://example.com/1/other").unwrap();
+        ui.open("News", news).unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert!(ui.views[2].raw().contains("/news #1"));
+
+        ui.process_action(Action::Keypress(Key::Char('R'))).unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+        assert_eq!(ui.views.len(), 3);
+        assert!(ui.views[2].raw().contains("/news #3"));
+        let (_, cached) = cache::get(news, ui.config.read().unwrap().cache).unwrap();
+        assert!(String::from_utf8_lossy(&cached).contains("/news #3"));
+    }
+
+    #[test]
+    fn test_mailbox() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let counter = fetches.clone();
+        let net = Memory::new().handler("example.com:70", move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            b"From joe@example.com Wed Jul 28 17:02:01 1993\r\n\
+              From: Joe Gopher <joe@example.com>\r\n\
+              Subject: Hi\r\n\
+              \r\n\
+              Hello!\r\n"
+                .to_vec()
+        });
+        let mut ui = offline_ui(net);
+
+        ui.open("List", "gopher://example.com/M/list.mbox").unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(ui.views[0]
+            .raw()
+            .contains("Hi\tURL:gopher://example.com/M/list.mbox%091"));
+
+        ui.open("Hi", "gopher://example.com/M/list.mbox%091")
+            .unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(ui.views[1].raw().contains("Hello!"));
+        assert!(ui.open("", "gopher://example.com/M/list.mbox%099").is_err());
+
+        // the list itself is always fresh
+        ui.open("List", "gopher://example.com/M/list.mbox").unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_download_status() {
+        let status = download_status(
+            vec![
+                ("Download complete! 1B saved to a".into(), false),
+                ("Download failed: b".into(), true),
+            ],
+            "<err>",
+        );
+        assert!(status.starts_with("<err>Download failed: b"));
+        assert!(status.contains(" | Download complete! 1B saved to a"));
+    }
+}
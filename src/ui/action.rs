@@ -24,6 +24,9 @@ pub enum Action {
     /// not run.
     /// Prompt(Prompt Query, Callback)
     Prompt(String, Box<dyn FnOnce(String) -> Action>),
+    /// Submit the answers to a Gopher+ +ASK form.
+    /// Form(Title, URL, Answers)
+    Form(String, String, Vec<String>),
     /// Do more than one action.
     List(Vec<Action>),
     /// Display an error message.
@@ -47,6 +50,7 @@ impl fmt::Debug for Action {
             Action::Draw(s) => write!(f, "Draw: {:?}", s),
             Action::Status(s) => write!(f, "Status: {}", s),
             Action::Prompt(s, _) => write!(f, "Prompt: {}", s),
+            Action::Form(title, url, _) => write!(f, "Form: {}, {}", title, url),
             Action::List(li) => {
                 writeln!(f, "List: ")?;
                 for a in li {
@@ -0,0 +1,420 @@
+//! Downloads run on their own threads so you can keep browsing while
+//! they come in. The UI queues them up here, checks on them between
+//! keypresses, and shows them all at `gopher://phetch/1/downloads`,
+//! where they can be cancelled or retried.
+
+use crate::{config::SharedConfig, gopher, utils, Error, Result};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Internal page listing every download.
+pub(super) const URL: &str = "gopher://phetch/1/downloads";
+
+/// How many downloads can run at once. The rest wait their turn.
+const MAX_ACTIVE: usize = 3;
+
+/// Where a download is at.
+enum State {
+    /// Waiting for a free slot.
+    Queued,
+    /// Coming in now.
+    Active,
+    /// Saved to disk.
+    Done {
+        /// Path it was saved to.
+        path: String,
+        /// Size in bytes.
+        bytes: usize,
+        /// How long it took.
+        elapsed: Duration,
+    },
+    /// Gave up, with the reason why.
+    Failed(String),
+    /// The user called it off.
+    Cancelled,
+}
+
+/// One file being downloaded.
+struct Download {
+    /// Shows up in URLs to cancel or retry it.
+    id: usize,
+    /// What we're downloading.
+    url: String,
+    /// Name to save it as, if the user picked one.
+    filename: Option<String>,
+    state: State,
+    /// When it started coming in.
+    started: Instant,
+    /// Bytes saved so far, updated by the download thread.
+    bytes: Arc<AtomicUsize>,
+    /// Set to tell the download thread to stop.
+    cancel: Arc<AtomicBool>,
+}
+
+impl Download {
+    /// Name to show for this download.
+    fn name(&self) -> String {
+        match &self.filename {
+            Some(name) => name.clone(),
+            None => gopher::download_filename(&self.url),
+        }
+    }
+
+    /// Bytes per second since it started.
+    fn throughput(&self, bytes: usize, elapsed: Duration) -> usize {
+        let secs = elapsed.as_secs_f64();
+        if secs > 0.0 {
+            (bytes as f64 / secs) as usize
+        } else {
+            0
+        }
+    }
+}
+
+/// Sent by a download thread when it's finished, one way or another.
+type Finished = (usize, Result<(String, usize)>);
+
+/// All the downloads started this session, newest last.
+pub(super) struct Downloads {
+    list: Vec<Download>,
+    /// Config to pull TLS, transport, and timeout settings from.
+    config: SharedConfig,
+    /// Download threads report back here.
+    done_tx: Sender<Finished>,
+    done_rx: Receiver<Finished>,
+}
+
+impl Downloads {
+    /// No downloads yet.
+    pub(super) fn new(config: SharedConfig) -> Downloads {
+        let (done_tx, done_rx) = channel();
+        Downloads {
+            list: vec![],
+            config,
+            done_tx,
+            done_rx,
+        }
+    }
+
+    /// Queue up `url` to be downloaded, either as `filename` or named
+    /// after its selector. Starts right away if there's room.
+    pub(super) fn add(&mut self, url: &str, filename: Option<String>) {
+        self.list.push(Download {
+            id: self.list.len() + 1,
+            url: url.into(),
+            filename,
+            state: State::Queued,
+            started: Instant::now(),
+            bytes: Arc::new(AtomicUsize::new(0)),
+            cancel: Arc::new(AtomicBool::new(false)),
+        });
+        self.start_queued();
+    }
+
+    /// Stop a queued or active download.
+    pub(super) fn cancel(&mut self, id: usize) -> Result<()> {
+        let dl = self.get(id)?;
+        match dl.state {
+            State::Queued => dl.state = State::Cancelled,
+            // the thread will report back once it's stopped
+            State::Active => dl.cancel.store(true, Ordering::SeqCst),
+            _ => return Err(error!("Download isn't running: {}", dl.name())),
+        }
+        Ok(())
+    }
+
+    /// Try a failed or cancelled download again.
+    pub(super) fn retry(&mut self, id: usize) -> Result<()> {
+        let dl = self.get(id)?;
+        if !matches!(dl.state, State::Failed(_) | State::Cancelled) {
+            return Err(error!("Download didn't fail: {}", dl.name()));
+        }
+        dl.state = State::Queued;
+        dl.bytes = Arc::new(AtomicUsize::new(0));
+        dl.cancel = Arc::new(AtomicBool::new(false));
+        self.start_queued();
+        Ok(())
+    }
+
+    /// Are any downloads queued or coming in?
+    pub(super) fn is_busy(&self) -> bool {
+        self.list
+            .iter()
+            .any(|dl| matches!(dl.state, State::Queued | State::Active))
+    }
+
+    /// Check for finished downloads and start queued ones in their
+    /// place. Returns a message for each download that finished, and
+    /// whether it failed.
+    pub(super) fn poll(&mut self) -> Vec<(String, bool)> {
+        let mut msgs = vec![];
+        while let Ok((id, res)) = self.done_rx.try_recv() {
+            let dl = match self.list.iter_mut().find(|dl| dl.id == id) {
+                Some(dl) => dl,
+                None => continue,
+            };
+            match res {
+                Ok((path, bytes)) => {
+                    msgs.push((
+                        format!(
+                            "Download complete! {} saved to {}",
+                            utils::human_bytes(bytes),
+                            path
+                        ),
+                        false,
+                    ));
+                    dl.state = State::Done {
+                        path,
+                        bytes,
+                        elapsed: dl.started.elapsed(),
+                    };
+                }
+                Err(Error::Cancelled) => {
+                    msgs.push((format!("Download cancelled: {}", dl.name()), false));
+                    dl.state = State::Cancelled;
+                }
+                Err(e) => {
+                    msgs.push((format!("Download failed: {}", e), true));
+                    dl.state = State::Failed(e.to_string());
+                }
+            }
+        }
+        if !msgs.is_empty() {
+            self.start_queued();
+        }
+        msgs
+    }
+
+    /// Summary of downloads in progress for the status line, if any.
+    pub(super) fn status(&self) -> Option<String> {
+        let active = self
+            .list
+            .iter()
+            .filter(|dl| matches!(dl.state, State::Active))
+            .collect::<Vec<_>>();
+        let bytes = active
+            .iter()
+            .map(|dl| dl.bytes.load(Ordering::Relaxed))
+            .sum::<usize>();
+        let rate = active
+            .iter()
+            .map(|dl| dl.throughput(dl.bytes.load(Ordering::Relaxed), dl.started.elapsed()))
+            .sum::<usize>();
+        let queued = self
+            .list
+            .iter()
+            .filter(|dl| matches!(dl.state, State::Queued))
+            .count();
+
+        let mut status = match active.as_slice() {
+            [] => return None,
+            [dl] => format!("Downloading {}", dl.name()),
+            _ => format!("Downloading {} files", active.len()),
+        };
+        status.push_str(&format!(
+            "... {} ({}/s)",
+            utils::human_bytes(bytes),
+            utils::human_bytes(rate)
+        ));
+        if queued > 0 {
+            status.push_str(&format!(", {} queued", queued));
+        }
+        Some(status)
+    }
+
+    /// Every download as a Gophermap, with links to cancel or retry.
+    pub(super) fn as_raw_menu(&self) -> String {
+        let mut out = vec!["iDownloads".to_string(), "i".to_string()];
+        if self.list.is_empty() {
+            out.push("iNothing downloaded yet.".into());
+        }
+        for dl in self.list.iter().rev() {
+            out.push(format!("i{}", dl.name()));
+            match &dl.state {
+                State::Queued => {
+                    out.push("i  queued".into());
+                    out.push(format!("1  cancel\t/downloads/cancel/{}\tphetch", dl.id));
+                }
+                State::Active => {
+                    let bytes = dl.bytes.load(Ordering::Relaxed);
+                    out.push(format!(
+                        "i  {} so far, {}/s",
+                        utils::human_bytes(bytes),
+                        utils::human_bytes(dl.throughput(bytes, dl.started.elapsed()))
+                    ));
+                    out.push(format!("1  cancel\t/downloads/cancel/{}\tphetch", dl.id));
+                }
+                State::Done {
+                    path,
+                    bytes,
+                    elapsed,
+                } => {
+                    out.push(format!(
+                        "i  {} in {}s, {}/s",
+                        utils::human_bytes(*bytes),
+                        elapsed.as_secs(),
+                        utils::human_bytes(dl.throughput(*bytes, *elapsed))
+                    ));
+                    out.push(format!("i  saved to {}", path));
+                }
+                State::Failed(e) => {
+                    out.push(format!("i  failed: {}", e));
+                    out.push(format!("1  retry\t/downloads/retry/{}\tphetch", dl.id));
+                }
+                State::Cancelled => {
+                    out.push("i  cancelled".into());
+                    out.push(format!("1  retry\t/downloads/retry/{}\tphetch", dl.id));
+                }
+            }
+            out.push("i".into());
+        }
+        out.join("\r\n")
+    }
+
+    /// Download with the given id.
+    fn get(&mut self, id: usize) -> Result<&mut Download> {
+        self.list
+            .iter_mut()
+            .find(|dl| dl.id == id)
+            .ok_or_else(|| error!("No such download: {}", id))
+    }
+
+    /// Start queued downloads, oldest first, until we hit MAX_ACTIVE.
+    fn start_queued(&mut self) {
+        let mut active = self
+            .list
+            .iter()
+            .filter(|dl| matches!(dl.state, State::Active))
+            .count();
+        for dl in self.list.iter_mut() {
+            if active >= MAX_ACTIVE {
+                break;
+            }
+            if matches!(dl.state, State::Queued) {
+                active += 1;
+                dl.state = State::Active;
+                dl.started = Instant::now();
+                spawn(dl, &self.config, self.done_tx.clone());
+            }
+        }
+    }
+}
+
+/// Run a download on its own thread.
+fn spawn(dl: &Download, config: &SharedConfig, done: Sender<Finished>) {
+    let (tls, transport, timeouts, dir, decode) = {
+        let config = config.read().unwrap();
+        (
+            config.tls_mode(),
+            config.transport(),
+            config.timeouts,
+            config.downloads.clone(),
+            config.decode,
+        )
+    };
+    let (id, url, filename) = (dl.id, dl.url.clone(), dl.filename.clone());
+    let (bytes, cancel) = (dl.bytes.clone(), dl.cancel.clone());
+
+    thread::spawn(move || {
+        let progress = |n| {
+            bytes.store(n, Ordering::Relaxed);
+            !cancel.load(Ordering::SeqCst)
+        };
+        let typ = gopher::type_for_url(&url);
+        let keep_name = filename.is_some();
+        let res = match filename {
+            Some(name) => gopher::download_url_with_filename(
+                &url,
+                tls,
+                &*transport,
+                timeouts,
+                &dir,
+                &name,
+                progress,
+            ),
+            None => gopher::download_url(&url, tls, &*transport, timeouts, &dir, progress),
+        };
+        let res = match res {
+            Ok((path, _)) if decode && gopher::decode::is_encoded(typ) => {
+                gopher::decode::decode_file(typ, &path, keep_name)
+                    .map_err(|e| error!("Saved {} but couldn't decode it: {}", path, e))
+            }
+            res => res,
+        };
+        let _ = done.send((id, res));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, gopher::Memory, utils::TempDir};
+    use std::sync::RwLock;
+
+    /// Poll until nothing's running, or give up after a few seconds.
+    fn wait(downloads: &mut Downloads) -> Vec<(String, bool)> {
+        let mut msgs = vec![];
+        for _ in 0..100 {
+            msgs.extend(downloads.poll());
+            if !downloads.is_busy() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        msgs
+    }
+
+    #[test]
+    fn test_downloads() {
+        let dir = TempDir::new("downloads");
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+
+        let config = Config {
+            transport: Some(Arc::new(Memory::new().page(
+                "example.com:70",
+                "/file.txt",
+                "hello",
+            ))),
+            downloads: dir.to_path_buf(),
+            ..Config::default()
+        };
+        let mut downloads = Downloads::new(Arc::new(RwLock::new(config)));
+        downloads.add("gopher://example.com/0/file.txt", None);
+        downloads.add("gopher://example.com/0/file.txt", None);
+        downloads.add("gopher://nowhere.org/9/file.zip", None);
+        downloads.add("gopher://example.com/0/file.txt", Some("d.txt".into()));
+        downloads.cancel(4).unwrap();
+        assert_eq!(downloads.is_busy(), true);
+
+        let msgs = wait(&mut downloads);
+        assert_eq!(downloads.is_busy(), false);
+        assert_eq!(msgs.len(), 3);
+        assert_eq!(msgs.iter().filter(|(_, failed)| *failed).count(), 1);
+        assert_eq!(read("file.txt"), "hello");
+        assert_eq!(read("file (1).txt"), "hello");
+        assert!(!dir.join("d.txt").exists());
+
+        let menu = downloads.as_raw_menu();
+        assert!(menu.contains("i  saved to "));
+        assert!(menu.contains("i  failed: Can't connect to nowhere.org:70"));
+        assert!(menu.contains("1  retry\t/downloads/retry/3\tphetch"));
+        assert!(menu.contains("1  retry\t/downloads/retry/4\tphetch"));
+        assert!(downloads.cancel(1).is_err());
+
+        downloads.retry(4).unwrap();
+        let msgs = wait(&mut downloads);
+        assert_eq!(msgs.len(), 1);
+        assert!(msgs[0]
+            .0
+            .starts_with("Download complete! 5 bytes saved to "));
+        assert_eq!(read("d.txt"), "hello");
+        assert!(downloads.retry(4).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! Big pages and slow servers shouldn't leave you staring at a
+//! spinner. Once the first screenful of a response has arrived, the
+//! UI shows it and hands the connection to a Loader, which reads the
+//! rest on its own thread and passes it back a chunk at a time.
+
+use crate::{
+    cache,
+    gopher::{self, Stream, Timeouts},
+};
+use std::{
+    io::Read,
+    sync::mpsc::{channel, Receiver, TryRecvError},
+    thread,
+};
+
+/// What the loading thread sends back to the UI.
+pub(super) enum Chunk {
+    /// More of the response. Always ends on a line break, unless it's
+    /// the very end of the response.
+    Data(Vec<u8>),
+    /// The whole response has arrived.
+    Done,
+    /// Something went wrong, and we're giving up.
+    Error(String),
+}
+
+/// A response that's still coming in over the network.
+pub(super) struct Loader {
+    /// Index of the View we're loading into.
+    pub(super) view: usize,
+    /// How much of the response has arrived so far, in bytes.
+    pub(super) bytes: usize,
+    /// Chunks from the loading thread.
+    chunks: Receiver<Chunk>,
+}
+
+impl Loader {
+    /// Read the rest of `url`'s response from `stream` in the
+    /// background. `body` is everything read so far, of which the
+    /// first `sent` bytes are already in the View. The full response
+    /// is saved to the cache once it's all here.
+    pub(super) fn spawn(
+        url: &str,
+        mut stream: Stream,
+        mut body: Vec<u8>,
+        mut sent: usize,
+        tls: bool,
+        timeouts: Timeouts,
+        settings: cache::Settings,
+    ) -> Loader {
+        let (tx, rx) = channel();
+        let bytes = sent;
+        let url = url.to_string();
+
+        thread::spawn(move || {
+            let u = gopher::parse_url(&url);
+            let addr = u.addr();
+            let mut buf = [0; 4096];
+            loop {
+                let count = match stream.read(&mut buf) {
+                    Ok(count) => count,
+                    Err(e) => {
+                        let e = gopher::read_error(e, &addr, timeouts);
+                        let _ = tx.send(Chunk::Error(e.to_string()));
+                        return;
+                    }
+                };
+
+                if count == 0 {
+                    if sent < body.len() {
+                        let _ = tx.send(Chunk::Data(body[sent..].to_vec()));
+                    }
+                    if settings.enabled {
+                        let _ = cache::put(&url, tls, &body, settings);
+                    }
+                    let _ = tx.send(Chunk::Done);
+                    return;
+                }
+
+                body.extend_from_slice(&buf[..count]);
+                if let Some(end) = body.iter().rposition(|&b| b == b'\n') {
+                    if end >= sent {
+                        // the UI hung up, so nobody wants the rest
+                        if tx.send(Chunk::Data(body[sent..=end].to_vec())).is_err() {
+                            return;
+                        }
+                        sent = end + 1;
+                    }
+                }
+            }
+        });
+
+        Loader {
+            view: 0,
+            bytes,
+            chunks: rx,
+        }
+    }
+
+    /// Everything the loading thread has sent since we last checked.
+    pub(super) fn recv(&mut self) -> Vec<Chunk> {
+        let mut chunks = vec![];
+        loop {
+            match self.chunks.try_recv() {
+                Ok(Chunk::Data(data)) => {
+                    self.bytes += data.len();
+                    chunks.push(Chunk::Data(data));
+                }
+                Ok(chunk) => {
+                    chunks.push(chunk);
+                    break;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    chunks.push(Chunk::Error("Loading stopped unexpectedly".into()));
+                    break;
+                }
+            }
+        }
+        chunks
+    }
+}
@@ -1,9 +1,10 @@
 /// The mode our text UI is in. Run mode is the default while
 /// Print doesn't show the cursor, among other things.
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
 pub enum Mode {
     /// Default, interactive mode.
     ///   phetch URL
+    #[default]
     Run,
 
     /// Just print a rendered version of a URL.
@@ -24,10 +25,16 @@ pub enum Mode {
     /// Print current theme
     ///   phetch --theme
     PrintTheme,
-}
-
-impl Default for Mode {
-    fn default() -> Self {
-        Mode::Run
-    }
+    /// Serve a directory over Gopher.
+    ///   phetch --serve DIR
+    Serve,
+    /// Save a copy of a gopherhole.
+    ///   phetch --mirror URL
+    Mirror,
+    /// Look for broken links.
+    ///   phetch --check URL
+    Check,
+    /// Look for mistakes in a gophermap.
+    ///   phetch --lint FILE
+    Lint,
 }
@@ -29,4 +29,17 @@ pub trait View: fmt::Display {
     fn encoding(&self) -> Encoding {
         Encoding::default()
     }
+    /// Was this View's content served from the cache?
+    fn is_cached(&self) -> bool {
+        false
+    }
+    /// Mark this View as served from the cache, or not.
+    fn set_cached(&mut self, _cached: bool) {}
+    /// Add more of the response to a View that's still loading.
+    fn append(&mut self, _chunk: &[u8]) {}
+    /// URLs of mirrors for a link in this View, in the order to try
+    /// them if `url` can't be reached.
+    fn mirrors(&self, _url: &str) -> Vec<String> {
+        vec![]
+    }
 }
@@ -1,7 +1,8 @@
 //! Helper functions and macros.
+use crate::Result;
 use std::{
     borrow::Cow,
-    io::{Result, Write},
+    io::Write,
     process::{self, Stdio},
 };
 
@@ -30,10 +31,10 @@ macro_rules! log {
     };
 }
 
-/// Creates an Other kind of io::Error.
+/// Creates an `Error::Other` with a message.
 macro_rules! error {
     ($e:expr) => {
-        std::io::Error::new(std::io::ErrorKind::Other, $e)
+        $crate::Error::Other($e.to_string())
     };
     ($e:expr, $($y:expr),*) => {
         error!(format!($e, $($y),*))
@@ -63,7 +64,7 @@ pub fn copy_to_clipboard(data: &str) -> Result<()> {
     #[cfg(not(target_os = "macos"))]
     let mut cmd = process::Command::new("xclip");
     #[cfg(not(target_os = "macos"))]
-    let cmd = cmd.args(&["-sel", "clip"]);
+    let cmd = cmd.args(["-sel", "clip"]);
 
     cmd.stdin(Stdio::piped())
         .spawn()
@@ -137,3 +138,40 @@ pub fn open_media(program: &str, url: &str) -> Result<()> {
 
     Ok(())
 }
+
+/// A fresh directory under the system temp dir, named
+/// `phetch-{name}-{pid}`, that's removed when dropped. For tests.
+#[cfg(test)]
+pub(crate) struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub(crate) fn new(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("phetch-{}-{}", name, process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = std::path::Path;
+    fn deref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<std::path::Path> for TempDir {
+    fn as_ref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,305 @@
+//! Type x items are XML documents, which are often sent as one giant
+//! line (sitemaps, feeds). `pretty()` puts every element on its own
+//! line, indented by how deep it is, and `Highlighter` colors the
+//! result using the `xml.*` theme keys.
+//!
+//! Neither is a real XML parser: they only look at where tags start
+//! and end, so broken documents still come out readable.
+
+use crate::theme::Theme;
+
+/// How much to indent each level of nesting.
+const INDENT: &str = "  ";
+
+/// Pieces of an XML document, as far as indenting it goes.
+#[derive(Debug, PartialEq)]
+enum Node<'a> {
+    /// `<item>`
+    Open(&'a str),
+    /// `</item>`
+    Close(&'a str),
+    /// `<br/>`, comments, `<?xml ...?>`, `<!DOCTYPE ...>`, CDATA.
+    Single(&'a str),
+    /// Anything between tags.
+    Text(&'a str),
+}
+
+/// Indent an XML document, one element per line. Elements with only a
+/// little text inside stay on one line, like `<loc>gopher://…</loc>`.
+pub fn pretty(xml: &str) -> String {
+    let nodes = parse(xml);
+    let mut out = vec![];
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < nodes.len() {
+        let indent = INDENT.repeat(depth);
+        match nodes[i] {
+            Node::Open(tag) => match (nodes.get(i + 1), nodes.get(i + 2)) {
+                (Some(Node::Close(close)), _) => {
+                    out.push(format!("{}{}{}", indent, tag, close));
+                    i += 1;
+                }
+                (Some(Node::Text(text)), Some(Node::Close(close)))
+                    if !text.trim().contains('\n') =>
+                {
+                    out.push(format!("{}{}{}{}", indent, tag, text.trim(), close));
+                    i += 2;
+                }
+                _ => {
+                    out.push(format!("{}{}", indent, tag));
+                    depth += 1;
+                }
+            },
+            Node::Close(tag) => {
+                depth = depth.saturating_sub(1);
+                out.push(format!("{}{}", INDENT.repeat(depth), tag));
+            }
+            Node::Single(tag) => out.push(format!("{}{}", indent, tag)),
+            Node::Text(text) => {
+                for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                    out.push(format!("{}{}", indent, line));
+                }
+            }
+        }
+        i += 1;
+    }
+
+    out.push(String::new());
+    out.join("\n")
+}
+
+/// Split a document into tags and the text between them.
+fn parse(xml: &str) -> Vec<Node<'_>> {
+    let mut nodes = vec![];
+    let mut rest = xml;
+
+    while !rest.is_empty() {
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            nodes.push(Node::Text(&rest[..end]));
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->").map(|i| i + 3)
+        } else if rest.starts_with("<![CDATA[") {
+            rest.find("]]>").map(|i| i + 3)
+        } else {
+            tag_end(rest)
+        };
+        let end = match end {
+            Some(end) => end,
+            // never closed, so it's not really a tag
+            None => {
+                nodes.push(Node::Text(rest));
+                break;
+            }
+        };
+
+        let tag = &rest[..end];
+        nodes.push(if tag.starts_with("</") {
+            Node::Close(tag)
+        } else if tag.starts_with("<!") || tag.starts_with("<?") || tag.ends_with("/>") {
+            Node::Single(tag)
+        } else {
+            Node::Open(tag)
+        });
+        rest = &rest[end..];
+    }
+
+    nodes
+}
+
+/// Where the tag at the start of `s` ends, skipping over any `>` in
+/// quoted attribute values or a DOCTYPE's `[...]` section.
+fn tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut brackets = 0;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '[') => brackets += 1,
+            (None, ']') => brackets -= 1,
+            (None, '>') if brackets <= 0 => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Where we are in the document, which carries over from one line to
+/// the next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Text,
+    /// The tag's name, right after the `<`.
+    Tag,
+    /// After the tag's name, in its attributes.
+    Attrs,
+    /// In an attribute's value, quoted with this character.
+    Value(char),
+    Comment,
+    CData,
+}
+
+/// Colors XML a line at a time. Feed it every line in order, even
+/// ones that aren't shown, so it knows what's a tag and what isn't.
+#[derive(Debug)]
+pub struct Highlighter {
+    state: State,
+}
+
+impl Default for Highlighter {
+    fn default() -> Highlighter {
+        Highlighter { state: State::Text }
+    }
+}
+
+impl Highlighter {
+    /// Color the next line of the document.
+    pub fn line(&mut self, line: &str, theme: &Theme) -> String {
+        let mut out = String::new();
+        let mut color = None;
+        let mut rest = line;
+
+        while !rest.is_empty() {
+            let (len, next) = self.step(rest, theme);
+            if color != Some(next) {
+                if color.is_some() {
+                    out.push_str(reset_color!());
+                }
+                out.push_str(next);
+                color = Some(next);
+            }
+            out.push_str(&rest[..len]);
+            rest = &rest[len..];
+        }
+
+        if color.is_some() {
+            out.push_str(reset_color!());
+        }
+        out
+    }
+
+    /// Move past the next bit of `s`, returning how many bytes that
+    /// was and what color they should be.
+    fn step<'t>(&mut self, s: &str, theme: &'t Theme) -> (usize, &'t str) {
+        let c = s.chars().next().unwrap_or_default();
+        let char_len = c.len_utf8();
+        match self.state {
+            State::Text if s.starts_with("<!--") => {
+                self.state = State::Comment;
+                (4, &theme.xml_comment)
+            }
+            State::Text if s.starts_with("<![CDATA[") => {
+                self.state = State::CData;
+                (9, &theme.xml_tag)
+            }
+            State::Text if c == '<' => {
+                self.state = State::Tag;
+                (1, &theme.xml_tag)
+            }
+            State::Text => (char_len, &theme.xml_text),
+
+            State::Comment if s.starts_with("-->") => {
+                self.state = State::Text;
+                (3, &theme.xml_comment)
+            }
+            State::Comment => (char_len, &theme.xml_comment),
+
+            State::CData if s.starts_with("]]>") => {
+                self.state = State::Text;
+                (3, &theme.xml_tag)
+            }
+            State::CData => (char_len, &theme.xml_text),
+
+            State::Tag | State::Attrs if c == '>' => {
+                self.state = State::Text;
+                (1, &theme.xml_tag)
+            }
+            State::Tag if c.is_whitespace() => {
+                self.state = State::Attrs;
+                (char_len, &theme.xml_attr)
+            }
+            State::Tag => (char_len, &theme.xml_tag),
+
+            State::Attrs if c == '"' || c == '\'' => {
+                self.state = State::Value(c);
+                (1, &theme.xml_value)
+            }
+            State::Attrs if c == '/' || c == '?' => (1, &theme.xml_tag),
+            State::Attrs => (char_len, &theme.xml_attr),
+
+            State::Value(q) => {
+                if c == q {
+                    self.state = State::Attrs;
+                }
+                (char_len, &theme.xml_value)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::to_color;
+
+    #[test]
+    fn test_pretty() {
+        let xml = r#"<?xml version="1.0"?><!-- map --><urlset a="1>2"><url><loc>gopher://example.com/</loc><empty></empty><br/></url><note>
+    two
+    lines
+</note></urlset>"#;
+        assert_eq!(
+            pretty(xml),
+            r#"<?xml version="1.0"?>
+<!-- map -->
+<urlset a="1>2">
+  <url>
+    <loc>gopher://example.com/</loc>
+    <empty></empty>
+    <br/>
+  </url>
+  <note>
+    two
+    lines
+  </note>
+</urlset>
+"#
+        );
+
+        // broken documents still come out
+        assert_eq!(pretty("<a><b>hi</a> <c"), "<a>\n  <b>hi</a>\n  <c\n");
+        assert_eq!(pretty("just text"), "just text\n");
+    }
+
+    #[test]
+    fn test_highlighter() {
+        let theme = Theme {
+            xml_tag: to_color("blue"),
+            xml_attr: to_color("magenta"),
+            xml_value: to_color("green"),
+            xml_text: to_color("white"),
+            xml_comment: to_color("grey"),
+            ..Default::default()
+        };
+        let mut hl = Highlighter::default();
+        assert_eq!(
+            hl.line(r#"<a href="x">hi</a>"#, &theme),
+            "\x1b[94m<a\x1b[0m\x1b[95m href=\x1b[0m\x1b[92m\"x\"\x1b[0m\x1b[94m>\x1b[0m\
+             \x1b[97mhi\x1b[0m\x1b[94m</a>\x1b[0m"
+        );
+
+        // state carries over to the next line
+        assert_eq!(hl.line("<!-- one", &theme), "\x1b[90m<!-- one\x1b[0m");
+        assert_eq!(hl.state, State::Comment);
+        assert_eq!(hl.line("two -->", &theme), "\x1b[90mtwo -->\x1b[0m");
+        assert_eq!(hl.state, State::Text);
+        assert_eq!(hl.line("", &theme), "");
+    }
+}